@@ -0,0 +1,274 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::AuditConfig;
+use crate::redact::Redactor;
+use crate::session::Session;
+use crate::tool_result::truncate_chars;
+
+/// Characters of an error message kept in an audit entry.
+const MAX_ERROR_CHARS: usize = 1000;
+
+/// What a tool call did on the host, collected while it runs.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    /// Bytes read from files
+    pub bytes_read: u64,
+    /// Bytes written to files
+    pub bytes_written: u64,
+    /// Argument vectors of the processes started
+    pub commands: Vec<Vec<String>>,
+}
+
+/// One tool call, filled in as the call is handled.
+#[derive(Debug, Default)]
+pub struct CallRecord {
+    pub tool: Option<String>,
+    pub module: Option<String>,
+    pub module_checksum: Option<String>,
+    pub arguments: Value,
+    pub usage: Usage,
+}
+
+/// How a tool call ended.
+pub enum Outcome<'a> {
+    Success,
+    /// The tool ran, or was held, and returned an `isError` result
+    ToolError(&'a str),
+    /// The call was answered with a JSON-RPC error
+    Rejected(&'a str),
+}
+
+/// Append-only JSON Lines log of every tool call, rotated by size.
+pub struct AuditLog {
+    path: PathBuf,
+    max_file_bytes: u64,
+    max_files: usize,
+    redact_arguments: Vec<String>,
+}
+
+impl AuditLog {
+    pub fn new(config: &AuditConfig) -> Self {
+        Self {
+            path: config.path(),
+            max_file_bytes: config.max_file_bytes(),
+            max_files: config.max_files(),
+            redact_arguments: config.redact_arguments.iter().map(|name| name.to_ascii_lowercase()).collect(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append an entry for a finished call. Failures are reported but never fail the call.
+    ///
+    /// Arguments, errors and commands pass through the redactor as well as `redact_arguments`.
+    pub fn record(&self, session: &Session, call: &CallRecord, outcome: Outcome<'_>, duration: Duration, redactor: &Redactor) {
+        let (status, error) = match outcome {
+            Outcome::Success => ("success", None),
+            Outcome::ToolError(message) => ("error", Some(message)),
+            Outcome::Rejected(message) => ("rejected", Some(message)),
+        };
+        let mut entry = json!({
+            "timestamp": timestamp(SystemTime::now()),
+            "session": session.id(),
+            "client": session.client_info(),
+            "principal": session.principal().map(|principal| principal.name),
+            "tool": call.tool,
+            "module": call.module,
+            "moduleChecksum": call.module_checksum,
+            "arguments": redactor.value(&self.redact(&call.arguments)),
+            "outcome": status,
+            "durationMs": duration.as_millis() as u64,
+            "bytesRead": call.usage.bytes_read,
+            "bytesWritten": call.usage.bytes_written,
+            "commands": redactor.value(&json!(call.usage.commands)),
+        });
+        if let Some(error) = error {
+            entry["error"] = json!(redactor.text(truncate_chars(error, MAX_ERROR_CHARS).0));
+        }
+
+        if let Err(e) = self.append(&entry) {
+            eprintln!("⚠️  Failed to write audit log {}: {:#}", self.path.display(), e);
+        }
+    }
+
+    fn append(&self, entry: &Value) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let size = fs::metadata(&self.path).map_or(0, |meta| meta.len());
+        if size > 0 && size + line.len() as u64 > self.max_file_bytes {
+            self.rotate()?;
+        }
+
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Shift `audit.jsonl.N` to `.N+1`, dropping the oldest, and move the live log to `.1`.
+    fn rotate(&self) -> Result<()> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        if self.max_files == 0 {
+            return fs::remove_file(&self.path).context("Failed to rotate audit log");
+        }
+        let _ = fs::remove_file(rotated(self.max_files));
+        for n in (1..self.max_files).rev() {
+            let from = rotated(n);
+            if from.exists() {
+                fs::rename(&from, rotated(n + 1)).context("Failed to rotate audit log")?;
+            }
+        }
+        fs::rename(&self.path, rotated(1)).context("Failed to rotate audit log")
+    }
+
+    /// Replace the values of configured argument names, at any depth, with `"[redacted]"`.
+    fn redact(&self, value: &Value) -> Value {
+        match value {
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(name, value)| {
+                        let value = if self.redact_arguments.contains(&name.to_ascii_lowercase()) {
+                            json!("[redacted]")
+                        } else {
+                            self.redact(value)
+                        };
+                        (name.clone(), value)
+                    })
+                    .collect(),
+            ),
+            Value::Array(items) => Value::Array(items.iter().map(|item| self.redact(item)).collect()),
+            other => other.clone(),
+        }
+    }
+}
+
+/// RFC 3339 UTC timestamp with millisecond precision.
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64, millis: u64) -> String {
+        timestamp(UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_millis(millis))
+    }
+
+    #[test]
+    fn timestamps_are_utc_calendar_dates() {
+        assert_eq!(at(0, 0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(at(1_704_067_199, 999), "2023-12-31T23:59:59.999Z");
+        assert_eq!(at(1_704_067_200, 0), "2024-01-01T00:00:00.000Z");
+        assert_eq!(at(1_709_164_800 - 1, 0), "2024-02-28T23:59:59.000Z");
+        assert_eq!(at(1_709_164_800 + 86_399, 0), "2024-02-29T23:59:59.000Z");
+        assert_eq!(at(951_782_400, 0), "2000-02-29T00:00:00.000Z");
+        // 2100 is not a leap year
+        assert_eq!(at(4_107_542_400 - 1, 0), "2100-02-28T23:59:59.000Z");
+    }
+
+    struct Log {
+        dir: PathBuf,
+        log: AuditLog,
+    }
+
+    impl Log {
+        /// A log whose entries of [`Log::append`] each fill it, so every append rotates.
+        fn new(max_files: usize) -> Self {
+            let dir = std::env::temp_dir().join(format!("wasmforge-audit-{}", uuid::Uuid::new_v4().simple()));
+            let log = AuditLog { path: dir.join("audit.jsonl"), max_file_bytes: 10, max_files, redact_arguments: Vec::new() };
+            Self { dir, log }
+        }
+
+        fn append(&self, n: u64) {
+            self.log.append(&json!({ "n": n })).unwrap();
+        }
+
+        fn read(&self, suffix: &str) -> Option<String> {
+            fs::read_to_string(format!("{}{}", self.log.path.display(), suffix)).ok()
+        }
+    }
+
+    impl Drop for Log {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn rotation_keeps_max_files() {
+        let log = Log::new(2);
+        for n in 1..=4 {
+            log.append(n);
+        }
+        assert_eq!(log.read("").as_deref(), Some("{\"n\":4}\n"));
+        assert_eq!(log.read(".1").as_deref(), Some("{\"n\":3}\n"));
+        assert_eq!(log.read(".2").as_deref(), Some("{\"n\":2}\n"));
+        assert_eq!(log.read(".3"), None);
+    }
+
+    #[test]
+    fn a_single_rotated_file_is_replaced() {
+        let log = Log::new(1);
+        for n in 1..=3 {
+            log.append(n);
+        }
+        assert_eq!(log.read("").as_deref(), Some("{\"n\":3}\n"));
+        assert_eq!(log.read(".1").as_deref(), Some("{\"n\":2}\n"));
+        assert_eq!(log.read(".2"), None);
+    }
+
+    #[test]
+    fn no_rotated_files_are_kept_without_max_files() {
+        let log = Log::new(0);
+        log.append(1);
+        log.append(2);
+        assert_eq!(log.read("").as_deref(), Some("{\"n\":2}\n"));
+        assert_eq!(log.read(".1"), None);
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::{AuthConfig, TokenConfig};
+use crate::fs_sandbox::expand_home;
+use crate::tool_discovery::DiscoveredTool;
+
+/// Who is calling, and which tools they may see and invoke.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub name: String,
+    scopes: Vec<String>,
+}
+
+impl Principal {
+    /// The user running the server: stdio clients and unauthenticated loopback TCP.
+    pub fn local() -> Self {
+        Self { name: "local".to_string(), scopes: vec!["*".to_string()] }
+    }
+
+    pub fn new(name: impl Into<String>, scopes: Vec<String>) -> Self {
+        Self { name: name.into(), scopes }
+    }
+
+    /// Whether a scope grants this tool: `*`, its module name, or `module::tool`.
+    pub fn allows(&self, tool: &DiscoveredTool) -> bool {
+        self.scopes.iter().any(|scope| {
+            scope == "*"
+                || *scope == tool.module_name
+                || scope.split_once("::").is_some_and(|(module, name)| module == tool.module_name && name == tool.name)
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct TokensFile {
+    #[serde(default)]
+    tokens: Vec<TokenConfig>,
+}
+
+/// Bearer tokens accepted on the TCP transport.
+pub struct Authenticator {
+    tokens: Vec<(Principal, [u8; 32])>,
+}
+
+impl Authenticator {
+    /// Tokens from `[auth]` and its `tokens_file`; a malformed hash is a startup error.
+    pub fn load(config: &AuthConfig) -> Result<Self> {
+        let mut entries = config.tokens.clone();
+        if let Some(path) = &config.tokens_file {
+            let path = expand_home(path);
+            let contents = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read tokens file: {}", path.display()))?;
+            let file: TokensFile = toml::from_str(&contents)
+                .with_context(|| format!("Failed to parse tokens file: {}", path.display()))?;
+            entries.extend(file.tokens);
+        }
+
+        let mut tokens = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let digest = decode_digest(&entry.sha256)
+                .with_context(|| format!("Token '{}' needs a 64-digit hex sha256", entry.name))?;
+            tokens.push((Principal::new(entry.name, entry.scopes), digest));
+        }
+        if tokens.is_empty() {
+            return Err(anyhow::anyhow!("[auth] is configured but lists no tokens"));
+        }
+        Ok(Self { tokens })
+    }
+
+    /// The principal a bearer token belongs to. Every hash is compared in constant time.
+    pub fn authenticate(&self, token: &str) -> Option<Principal> {
+        let digest: [u8; 32] = Sha256::digest(token.as_bytes()).into();
+        let mut found = None;
+        for (principal, expected) in &self.tokens {
+            let difference = expected.iter().zip(&digest).fold(0u8, |acc, (a, b)| acc | (a ^ b));
+            if difference == 0 && found.is_none() {
+                found = Some(principal.clone());
+            }
+        }
+        found
+    }
+}
+
+/// Hex SHA-256 of a token, the form stored in config.
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// A new random token with 244 bits of entropy.
+pub fn generate_token() -> String {
+    format!("wf_{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
+/// Bearer token from `Bearer <token>`.
+pub fn bearer_token(authorization: &str) -> Option<&str> {
+    let (scheme, token) = authorization.trim().split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim()).filter(|token| !token.is_empty())
+}
+
+/// Whether every address a host resolves to is a loopback address.
+pub async fn is_loopback(host: &str, port: u16) -> Result<bool> {
+    let addrs: Vec<_> = tokio::net::lookup_host((host, port)).await
+        .with_context(|| format!("Failed to resolve {}", host))?
+        .collect();
+    Ok(!addrs.is_empty() && addrs.iter().all(|addr| addr.ip().is_loopback()))
+}
+
+fn decode_digest(hex: &str) -> Option<[u8; 32]> {
+    let hex = hex.trim();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut digest = [0u8; 32];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use crate::tool_kinds::ToolKind;
+
+    fn token(name: &str, secret: &str, scopes: &[&str]) -> TokenConfig {
+        TokenConfig {
+            name: name.to_string(),
+            sha256: hash_token(secret),
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn tokens_authenticate_their_principal() {
+        let authenticator = Authenticator::load(&AuthConfig {
+            tokens: vec![token("ci", "ci-secret", &["tools"]), token("admin", "admin-secret", &["*"])],
+            tokens_file: None,
+        })
+        .unwrap();
+        assert_eq!(authenticator.authenticate("ci-secret").unwrap().name, "ci");
+        assert_eq!(authenticator.authenticate("admin-secret").unwrap().name, "admin");
+        assert!(authenticator.authenticate("ci-secret ").is_none());
+        assert!(authenticator.authenticate("").is_none());
+    }
+
+    #[test]
+    fn tokens_file_entries_are_added() {
+        let path = std::env::temp_dir().join(format!("wasmforge-tokens-{}.toml", uuid::Uuid::new_v4().simple()));
+        std::fs::write(&path, format!("[[tokens]]\nname = \"file\"\nsha256 = \"{}\"\nscopes = [\"*\"]\n", hash_token("file-secret"))).unwrap();
+        let authenticator = Authenticator::load(&AuthConfig { tokens: Vec::new(), tokens_file: Some(path.clone()) });
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(authenticator.unwrap().authenticate("file-secret").unwrap().name, "file");
+    }
+
+    #[test]
+    fn malformed_or_missing_tokens_are_startup_errors() {
+        let mut bad = token("bad", "secret", &["*"]);
+        bad.sha256 = "not-hex".to_string();
+        let error = Authenticator::load(&AuthConfig { tokens: vec![bad], tokens_file: None }).err().unwrap();
+        assert!(format!("{:#}", error).contains("64-digit hex"), "{:#}", error);
+        assert!(Authenticator::load(&AuthConfig { tokens: Vec::new(), tokens_file: None }).is_err());
+    }
+
+    #[test]
+    fn scopes_grant_all_tools_a_module_or_one_tool() {
+        let tool = test_support::tool("files", "read", ToolKind::Wasm);
+        assert!(Principal::local().allows(&tool));
+        assert!(Principal::new("module", vec!["files".to_string()]).allows(&tool));
+        assert!(Principal::new("one", vec!["files::read".to_string()]).allows(&tool));
+        assert!(!Principal::new("other", vec!["files::write".to_string(), "shell".to_string()]).allows(&tool));
+        assert!(!Principal::new("prefix", vec!["file".to_string(), "files::".to_string()]).allows(&tool));
+        assert!(!Principal::new("none", Vec::new()).allows(&tool));
+    }
+
+    #[test]
+    fn bearer_tokens_are_parsed_from_authorization_values() {
+        assert_eq!(bearer_token("Bearer abc"), Some("abc"));
+        assert_eq!(bearer_token("  bearer   abc  "), Some("abc"));
+        assert_eq!(bearer_token("Basic abc"), None);
+        assert_eq!(bearer_token("Bearer "), None);
+        assert_eq!(bearer_token("abc"), None);
+    }
+
+    #[test]
+    fn generated_tokens_hash_to_valid_digests() {
+        let token = generate_token();
+        assert!(token.starts_with("wf_") && token.len() == 67);
+        assert_ne!(token, generate_token());
+        assert!(decode_digest(&hash_token(&token)).is_some());
+    }
+
+    #[tokio::test]
+    async fn loopback_hosts_are_recognised() {
+        assert!(is_loopback("127.0.0.1", 0).await.unwrap());
+        assert!(is_loopback("::1", 0).await.unwrap());
+        assert!(!is_loopback("0.0.0.0", 0).await.unwrap());
+    }
+}
@@ -0,0 +1,407 @@
+use anyhow::Result;
+use std::path::PathBuf;
+
+use crate::config::ShellSandboxConfig;
+
+/// Writable scratch directory of a sandboxed command, removed once the command is done.
+#[derive(Debug)]
+pub struct ScratchDir {
+    pub path: PathBuf,
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Arrange for `command` to confine itself as configured just before it executes the program.
+///
+/// Returns the scratch directory when the filesystem is isolated; keep it alive until the
+/// command has exited.
+#[cfg(target_os = "linux")]
+pub fn apply(command: &mut tokio::process::Command, config: &ShellSandboxConfig) -> Result<Option<ScratchDir>> {
+    linux::apply(command, config)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn apply(_command: &mut tokio::process::Command, _config: &ShellSandboxConfig) -> Result<Option<ScratchDir>> {
+    // Refuse rather than run a command the config expects to be confined
+    Err(anyhow::anyhow!("The shell sandbox is only available on Linux"))
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use anyhow::{Context, Result};
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::DirBuilderExt;
+
+    use super::ScratchDir;
+    use crate::config::ShellSandboxConfig;
+
+    /// Syscalls refused with EPERM: kernel, mount, namespace, keyring and tracing interfaces
+    /// a confined command has no use for.
+    const DENIED_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_mount_setattr,
+        libc::SYS_move_mount,
+        libc::SYS_open_tree,
+        libc::SYS_fsopen,
+        libc::SYS_fsconfig,
+        libc::SYS_fsmount,
+        libc::SYS_fspick,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_open_by_handle_at,
+        libc::SYS_kexec_load,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_userfaultfd,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_reboot,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        libc::SYS_acct,
+        libc::SYS_settimeofday,
+        libc::SYS_clock_settime,
+    ];
+
+    /// `clone` flags that would create namespaces.
+    const NAMESPACE_FLAGS: libc::c_int = libc::CLONE_NEWNS
+        | libc::CLONE_NEWUTS
+        | libc::CLONE_NEWIPC
+        | libc::CLONE_NEWUSER
+        | libc::CLONE_NEWPID
+        | libc::CLONE_NEWNET
+        | libc::CLONE_NEWCGROUP;
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xC000_003E);
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xC000_00B7);
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const AUDIT_ARCH: Option<u32> = None;
+
+    /// Everything the child does after fork, prepared up front: between fork and exec
+    /// only async-signal-safe calls are allowed, so nothing there may allocate.
+    struct Plan {
+        unshare_flags: libc::c_int,
+        /// `/proc/self/{setgroups,uid_map,gid_map}` contents for a new user namespace
+        id_maps: Vec<(CString, Vec<u8>)>,
+        scratch: Option<CString>,
+        cpu_secs: Option<u64>,
+        memory_bytes: Option<u64>,
+        file_size_bytes: Option<u64>,
+        max_processes: Option<u64>,
+        seccomp: Option<Vec<libc::sock_filter>>,
+    }
+
+    pub fn apply(command: &mut tokio::process::Command, config: &ShellSandboxConfig) -> Result<Option<ScratchDir>> {
+        let scratch = if config.isolate_filesystem {
+            let path = std::env::temp_dir().join(format!("wasmforge-scratch-{}", uuid::Uuid::new_v4()));
+            std::fs::DirBuilder::new().mode(0o700).create(&path)
+                .with_context(|| format!("Failed to create scratch directory: {}", path.display()))?;
+            command.env("TMPDIR", &path);
+            Some(ScratchDir { path })
+        } else {
+            None
+        };
+
+        let mut unshare_flags = 0;
+        if config.isolate_network {
+            unshare_flags |= libc::CLONE_NEWNET;
+        }
+        if config.isolate_filesystem {
+            unshare_flags |= libc::CLONE_NEWNS;
+        }
+
+        // Without root, a user namespace grants the privileges needed for the others
+        let mut id_maps = Vec::new();
+        // SAFETY: geteuid and getegid cannot fail
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        if unshare_flags != 0 && uid != 0 {
+            unshare_flags |= libc::CLONE_NEWUSER;
+            id_maps = vec![
+                (CString::new("/proc/self/setgroups")?, b"deny".to_vec()),
+                (CString::new("/proc/self/uid_map")?, format!("{} {} 1", uid, uid).into_bytes()),
+                (CString::new("/proc/self/gid_map")?, format!("{} {} 1", gid, gid).into_bytes()),
+            ];
+        }
+
+        let seccomp = match (config.seccomp, AUDIT_ARCH) {
+            (false, _) => None,
+            (true, Some(arch)) => Some(seccomp_filter(arch)),
+            (true, None) => return Err(anyhow::anyhow!("The seccomp profile is not available on this architecture; set seccomp = false")),
+        };
+
+        let plan = Plan {
+            unshare_flags,
+            id_maps,
+            scratch: scratch.as_ref().map(|s| CString::new(s.path.as_os_str().as_bytes())).transpose()?,
+            cpu_secs: config.cpu_secs,
+            memory_bytes: config.memory_bytes,
+            file_size_bytes: config.file_size_bytes,
+            max_processes: config.max_processes,
+            seccomp,
+        };
+
+        // SAFETY: `enter` only makes raw syscalls on data prepared above
+        unsafe {
+            command.pre_exec(move || plan.enter());
+        }
+        Ok(scratch)
+    }
+
+    impl Plan {
+        fn enter(&self) -> io::Result<()> {
+            // SAFETY: every call below is a plain syscall on buffers owned by the plan
+            unsafe {
+                if self.unshare_flags != 0 {
+                    check(libc::unshare(self.unshare_flags))?;
+                }
+                for (path, contents) in &self.id_maps {
+                    write_file(path, contents)?;
+                }
+                if let Some(scratch) = &self.scratch {
+                    read_only_root(scratch)?;
+                }
+
+                set_limit(libc::RLIMIT_CPU, self.cpu_secs)?;
+                set_limit(libc::RLIMIT_AS, self.memory_bytes)?;
+                set_limit(libc::RLIMIT_FSIZE, self.file_size_bytes)?;
+                set_limit(libc::RLIMIT_NPROC, self.max_processes)?;
+
+                check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1 as libc::c_ulong, 0 as libc::c_ulong, 0 as libc::c_ulong, 0 as libc::c_ulong))?;
+
+                // Last, since the filter forbids the namespace and mount calls above
+                if let Some(filter) = &self.seccomp {
+                    let program = libc::sock_fprog {
+                        len: filter.len() as libc::c_ushort,
+                        filter: filter.as_ptr() as *mut libc::sock_filter,
+                    };
+                    check(libc::prctl(
+                        libc::PR_SET_SECCOMP,
+                        libc::SECCOMP_MODE_FILTER as libc::c_ulong,
+                        &program as *const libc::sock_fprog as libc::c_ulong,
+                        0 as libc::c_ulong,
+                        0 as libc::c_ulong,
+                    ))?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn check(result: libc::c_int) -> io::Result<()> {
+        if result < 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
+    }
+
+    unsafe fn write_file(path: &CString, contents: &[u8]) -> io::Result<()> {
+        unsafe {
+            let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            check(fd)?;
+            let written = libc::write(fd, contents.as_ptr().cast(), contents.len());
+            libc::close(fd);
+            if written != contents.len() as isize {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Make every mount read-only except a bind mount of the scratch directory.
+    unsafe fn read_only_root(scratch: &CString) -> io::Result<()> {
+        let root = c"/";
+        unsafe {
+            // Keep the changes below from propagating back to the server's namespace
+            check(libc::mount(std::ptr::null(), root.as_ptr(), std::ptr::null(), libc::MS_REC | libc::MS_PRIVATE, std::ptr::null()))?;
+            // A mount of its own, so it can be made writable again
+            check(libc::mount(scratch.as_ptr(), scratch.as_ptr(), std::ptr::null(), libc::MS_BIND, std::ptr::null()))?;
+            set_mount_attr(root, libc::AT_RECURSIVE as libc::c_uint, libc::MOUNT_ATTR_RDONLY, 0)?;
+            set_mount_attr(scratch, 0, 0, libc::MOUNT_ATTR_RDONLY)
+        }
+    }
+
+    unsafe fn set_mount_attr(path: &std::ffi::CStr, flags: libc::c_uint, set: u64, clear: u64) -> io::Result<()> {
+        let attr = libc::mount_attr { attr_set: set, attr_clr: clear, propagation: 0, userns_fd: 0 };
+        // SAFETY: `attr` outlives the call and its size is passed along
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_mount_setattr,
+                libc::AT_FDCWD,
+                path.as_ptr(),
+                flags,
+                &attr as *const libc::mount_attr,
+                std::mem::size_of::<libc::mount_attr>(),
+            )
+        };
+        if result < 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
+    }
+
+    #[cfg(target_env = "gnu")]
+    type Resource = libc::__rlimit_resource_t;
+    #[cfg(not(target_env = "gnu"))]
+    type Resource = libc::c_int;
+
+    unsafe fn set_limit(resource: Resource, value: Option<u64>) -> io::Result<()> {
+        let Some(value) = value else {
+            return Ok(());
+        };
+        let limit = libc::rlimit { rlim_cur: value, rlim_max: value };
+        // SAFETY: `limit` is a valid rlimit
+        check(unsafe { libc::setrlimit(resource, &limit) })
+    }
+
+    /// Seccomp program: kill foreign-architecture calls, refuse `DENIED_SYSCALLS` and
+    /// namespace-creating `clone`s with EPERM, and make `clone3` look unimplemented so
+    /// libc falls back to the inspectable `clone`.
+    fn seccomp_filter(arch: u32) -> Vec<libc::sock_filter> {
+        const LOAD: u16 = (libc::BPF_LD | libc::BPF_W | libc::BPF_ABS) as u16;
+        const JEQ: u16 = (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16;
+        const JGE: u16 = (libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K) as u16;
+        const JSET: u16 = (libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K) as u16;
+        const RET: u16 = (libc::BPF_RET | libc::BPF_K) as u16;
+        // Offsets into `struct seccomp_data`; the low word of args[0] on little-endian targets
+        const NR: u32 = 0;
+        const ARCH: u32 = 4;
+        const ARG0: u32 = 16;
+
+        let op = |code: u16, k: u32, jt: u8, jf: u8| libc::sock_filter { code, jt, jf, k };
+        let deny = |errno: i32| op(RET, libc::SECCOMP_RET_ERRNO | errno as u32, 0, 0);
+
+        let mut filter = vec![
+            op(LOAD, ARCH, 0, 0),
+            op(JEQ, arch, 1, 0),
+            op(RET, libc::SECCOMP_RET_KILL_PROCESS, 0, 0),
+            op(LOAD, NR, 0, 0),
+        ];
+        // The x32 ABI reuses x86_64 numbers with this bit set
+        if cfg!(target_arch = "x86_64") {
+            filter.push(op(JGE, 0x4000_0000, 0, 1));
+            filter.push(deny(libc::EPERM));
+        }
+        for syscall in DENIED_SYSCALLS {
+            filter.push(op(JEQ, *syscall as u32, 0, 1));
+            filter.push(deny(libc::EPERM));
+        }
+        filter.extend([
+            op(JEQ, libc::SYS_clone3 as u32, 0, 1),
+            deny(libc::ENOSYS),
+            op(JEQ, libc::SYS_clone as u32, 0, 3),
+            op(LOAD, ARG0, 0, 0),
+            op(JSET, NAMESPACE_FLAGS as u32, 0, 1),
+            deny(libc::EPERM),
+            op(RET, libc::SECCOMP_RET_ALLOW, 0, 0),
+        ]);
+        filter
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::process::Output;
+
+    fn unconfined() -> ShellSandboxConfig {
+        ShellSandboxConfig {
+            cpu_secs: None,
+            memory_bytes: None,
+            file_size_bytes: None,
+            max_processes: None,
+            seccomp: false,
+            isolate_network: false,
+            isolate_filesystem: false,
+        }
+    }
+
+    async fn run(config: &ShellSandboxConfig, program: &str, args: &[&str]) -> std::io::Result<Output> {
+        let mut command = tokio::process::Command::new(program);
+        command.args(args);
+        let _scratch = apply(&mut command, config).unwrap();
+        command.output().await
+    }
+
+    /// Run a command that needs namespaces, or `None` when the host does not allow them.
+    async fn run_isolated(config: &ShellSandboxConfig, program: &str, args: &[&str]) -> Option<Output> {
+        match run(config, program, args).await {
+            Ok(output) => Some(output),
+            Err(e) => {
+                eprintln!("Skipping: namespaces are unavailable here ({})", e);
+                None
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn isolated_filesystem_is_read_only_except_scratch() {
+        let probe = format!("/wasmforge-probe-{}", uuid::Uuid::new_v4().simple());
+        let script = format!(r#"if echo x > {} 2>/dev/null; then exit 1; fi; echo ok > "$TMPDIR/ok" && cat "$TMPDIR/ok""#, probe);
+        let config = ShellSandboxConfig { isolate_filesystem: true, ..unconfined() };
+        let Some(output) = run_isolated(&config, "sh", &["-c", &script]).await else {
+            return;
+        };
+        let _ = std::fs::remove_file(&probe);
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+    }
+
+    #[tokio::test]
+    async fn isolated_network_cannot_connect() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let script = format!("exec 3<>/dev/tcp/127.0.0.1/{}", listener.local_addr().unwrap().port());
+
+        let output = run(&unconfined(), "bash", &["-c", &script]).await.unwrap();
+        assert!(output.status.success(), "the unconfined connect should work: {:?}", output);
+
+        let config = ShellSandboxConfig { isolate_network: true, ..unconfined() };
+        let Some(output) = run_isolated(&config, "bash", &["-c", &script]).await else {
+            return;
+        };
+        assert!(!output.status.success(), "{:?}", output);
+    }
+
+    #[tokio::test]
+    async fn file_size_limit_stops_large_writes() {
+        let target = std::env::temp_dir().join(format!("wasmforge-fsize-{}", uuid::Uuid::new_v4().simple()));
+        let script = format!("head -c 4096 /dev/zero > {}", target.display());
+        let config = ShellSandboxConfig { file_size_bytes: Some(1024), ..unconfined() };
+        let output = run(&config, "sh", &["-c", &script]).await.unwrap();
+        let written = std::fs::metadata(&target).map(|m| m.len()).unwrap_or(0);
+        let _ = std::fs::remove_file(&target);
+        assert!(!output.status.success(), "{:?}", output);
+        assert!(written <= 1024, "wrote {} bytes", written);
+    }
+
+    #[tokio::test]
+    async fn cpu_limit_stops_busy_loops() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let config = ShellSandboxConfig { cpu_secs: Some(1), ..unconfined() };
+        let run = run(&config, "bash", &["-c", "while :; do :; done"]);
+        let output = tokio::time::timeout(std::time::Duration::from_secs(20), run).await
+            .expect("the CPU limit should end the loop")
+            .unwrap();
+        assert!(matches!(output.status.signal(), Some(libc::SIGXCPU | libc::SIGKILL)), "{:?}", output);
+    }
+
+    #[tokio::test]
+    async fn seccomp_refuses_blocked_syscalls_with_eperm() {
+        let config = ShellSandboxConfig { seccomp: true, ..unconfined() };
+        let output = run(&config, "unshare", &["--user", "true"]).await.unwrap();
+        assert!(!output.status.success(), "{:?}", output);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Operation not permitted"), "{:?}", output);
+    }
+}
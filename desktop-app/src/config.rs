@@ -4,11 +4,24 @@ use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::policy::DEFAULT_ALLOWED_COMMANDS;
+use crate::tool_kinds::ToolKind;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub server: ServerConfig,
     pub modules: Vec<ModuleConfig>,
     pub cache: CacheConfig,
+    /// Security defaults for every tool, overridden by module and tool settings
+    pub security: Option<ToolSecurityConfig>,
+    /// Record every tool call; unset disables the audit log
+    pub audit: Option<AuditConfig>,
+    /// Secrets masked in tool results, logs and the audit log; built-in patterns apply when unset
+    pub redaction: Option<RedactionConfig>,
+    /// Bearer tokens required on the TCP transport; unset allows only loopback binds
+    pub auth: Option<AuthConfig>,
+    /// Serve TCP connections over TLS, optionally requiring client certificates
+    pub tls: Option<TlsConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +30,18 @@ pub struct ServerConfig {
     pub version: String,
     pub default_port: Option<u16>,
     pub default_host: String,
+    /// Maximum number of tools returned per `tools/list` page
+    pub tools_page_size: Option<usize>,
+}
+
+impl ServerConfig {
+    pub const DEFAULT_TOOLS_PAGE_SIZE: usize = 100;
+
+    pub fn tools_page_size(&self) -> usize {
+        self.tools_page_size
+            .filter(|size| *size > 0)
+            .unwrap_or(Self::DEFAULT_TOOLS_PAGE_SIZE)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,20 +53,277 @@ pub struct ModuleConfig {
     pub enabled: bool,
     pub tools: Option<Vec<ToolConfig>>,
     pub metadata: Option<HashMap<String, String>>, 
+    /// Grants the module the `wasmforge::http_request` import under this egress policy
+    pub http: Option<HttpConfig>,
+    /// Directories the module's file tools may touch; without it file tools are denied
+    pub filesystem: Option<FilesystemConfig>,
+    /// Security defaults for the module's tools, overridden by each tool's own settings
+    pub security: Option<ToolSecurityConfig>,
+}
+
+impl ModuleConfig {
+    /// Host capabilities this config grants the module's manifest tools: `http` with
+    /// `[modules.http]`, `fs.read` with a filesystem root, `fs.write` with a `read_write`
+    /// root and `shell` with module-wide `allowed_commands` (or the legacy CSV metadata).
+    pub fn granted_capabilities(&self) -> Vec<&'static str> {
+        let roots = self.filesystem.as_ref().map_or(&[][..], |fs| fs.roots.as_slice());
+        let commands = self.security.as_ref()
+            .and_then(|security| security.allowed_commands.as_ref())
+            .is_some_and(|list| !list.is_empty())
+            || self.metadata.as_ref()
+                .and_then(|meta| meta.get("allowed_commands_csv"))
+                .is_some_and(|csv| csv.split(',').any(|name| !name.trim().is_empty()));
+        [
+            ("http", self.http.is_some()),
+            ("fs.read", !roots.is_empty()),
+            ("fs.write", roots.iter().any(|root| root.access == FsAccess::ReadWrite)),
+            ("shell", commands),
+        ]
+        .into_iter()
+        .filter_map(|(capability, granted)| granted.then_some(capability))
+        .collect()
+    }
+}
+
+/// Directories a module's file tools are confined to.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FilesystemConfig {
+    #[serde(default)]
+    pub roots: Vec<FsRoot>,
+    /// Largest read a file tool may perform; defaults to 1 MiB
+    pub max_read_bytes: Option<u64>,
+}
+
+impl FilesystemConfig {
+    pub const DEFAULT_MAX_READ_BYTES: u64 = 1024 * 1024;
+
+    pub fn max_read_bytes(&self) -> u64 {
+        self.max_read_bytes.unwrap_or(Self::DEFAULT_MAX_READ_BYTES)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FsRoot {
+    /// Absolute directory; a leading `~/` expands to the home directory
+    pub path: PathBuf,
+    #[serde(default)]
+    pub access: FsAccess,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FsAccess {
+    #[default]
+    ReadOnly,
+    ReadWrite,
+}
+
+impl FsAccess {
+    /// Whether a root with this access permits an operation needing `needed`.
+    pub fn permits(self, needed: FsAccess) -> bool {
+        self == FsAccess::ReadWrite || needed == FsAccess::ReadOnly
+    }
+}
+
+/// Egress policy for guest-initiated HTTP requests.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HttpConfig {
+    /// Host names the module may reach; `*.example.com` matches subdomains
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    /// Defaults to 80 and 443
+    pub allowed_ports: Option<Vec<u16>>,
+    /// Defaults to `https` only
+    pub allowed_schemes: Option<Vec<String>>,
+    /// Defaults to 1 MiB
+    pub max_response_bytes: Option<usize>,
+    /// Defaults to 30 seconds
+    pub timeout_secs: Option<u64>,
+}
+
+impl HttpConfig {
+    pub const DEFAULT_PORTS: [u16; 2] = [80, 443];
+    pub const DEFAULT_MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+    pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+    pub fn allowed_ports(&self) -> Vec<u16> {
+        self.allowed_ports.clone().unwrap_or_else(|| Self::DEFAULT_PORTS.to_vec())
+    }
+
+    pub fn allowed_schemes(&self) -> Vec<String> {
+        self.allowed_schemes.clone().unwrap_or_else(|| vec!["https".to_string()])
+    }
+
+    pub fn max_response_bytes(&self) -> usize {
+        self.max_response_bytes.unwrap_or(Self::DEFAULT_MAX_RESPONSE_BYTES)
+    }
+
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.timeout_secs.unwrap_or(Self::DEFAULT_TIMEOUT_SECS))
+    }
+
+    /// Whether `host` matches one of the allowed host patterns.
+    pub fn allows_host(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.allowed_hosts.iter().any(|pattern| {
+            let pattern = pattern.to_ascii_lowercase();
+            match pattern.strip_prefix("*.") {
+                Some(suffix) => host.ends_with(&format!(".{}", suffix)),
+                None => host == pattern,
+            }
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolConfig {
     pub name: String,
+    pub title: Option<String>,
     pub description: Option<String>,
     pub function_name: String,
+    /// How the tool executes; host-backed kinds use `function_name` as their validator
+    pub kind: Option<ToolKind>,
+    /// Calling convention for `wasm` tools; only `json` is declared, others are inferred
+    pub pattern: Option<String>,
     pub parameters: Option<serde_json::Value>,
+    /// Argument names mapped onto the function's parameters, in order
+    pub params: Option<Vec<String>>,
+    /// Host input -> argument name mapping for host-backed kinds, e.g. `path = "file"`
+    pub arguments: Option<HashMap<String, String>>,
+    pub output_schema: Option<serde_json::Value>,
     pub security: Option<ToolSecurityConfig>,
+    pub annotations: Option<ToolAnnotations>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Behavioural hints reported to clients in `tools/list`.
+///
+/// Unset fields fall back to the defaults inferred for the tool during discovery.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ToolAnnotations {
+    #[serde(alias = "readOnlyHint")]
+    pub read_only_hint: Option<bool>,
+    #[serde(alias = "destructiveHint")]
+    pub destructive_hint: Option<bool>,
+    #[serde(alias = "idempotentHint")]
+    pub idempotent_hint: Option<bool>,
+    #[serde(alias = "openWorldHint")]
+    pub open_world_hint: Option<bool>,
+}
+
+impl ToolAnnotations {
+    pub fn new(read_only: bool, destructive: bool, idempotent: bool, open_world: bool) -> Self {
+        Self {
+            read_only_hint: Some(read_only),
+            destructive_hint: Some(destructive),
+            idempotent_hint: Some(idempotent),
+            open_world_hint: Some(open_world),
+        }
+    }
+
+    /// Fill unset hints from `defaults`, keeping the ones set here.
+    pub fn or(&self, defaults: &ToolAnnotations) -> ToolAnnotations {
+        ToolAnnotations {
+            read_only_hint: self.read_only_hint.or(defaults.read_only_hint),
+            destructive_hint: self.destructive_hint.or(defaults.destructive_hint),
+            idempotent_hint: self.idempotent_hint.or(defaults.idempotent_hint),
+            open_world_hint: self.open_world_hint.or(defaults.open_world_hint),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut hints = serde_json::Map::new();
+        let fields = [
+            ("readOnlyHint", self.read_only_hint),
+            ("destructiveHint", self.destructive_hint),
+            ("idempotentHint", self.idempotent_hint),
+            ("openWorldHint", self.open_world_hint),
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                hints.insert(key.to_string(), serde_json::Value::Bool(value));
+            }
+        }
+        serde_json::Value::Object(hints)
+    }
+}
+
+/// Security settings of a tool, module or the whole server; see `policy` for how they merge.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ToolSecurityConfig {
     pub allowed_commands: Option<Vec<String>>, 
+    /// Characters of a response body shown in the tool result before it is truncated
+    pub max_response_chars: Option<usize>,
+    /// Seconds a `shell_exec` command may run before it is killed
+    pub timeout_secs: Option<u64>,
+    /// Bytes kept from each of a `shell_exec` command's stdout and stderr
+    pub max_output_bytes: Option<usize>,
+    /// Server environment variables passed to `shell_exec` commands; callers may only set these, never `PATH`
+    pub allowed_env: Option<Vec<String>>,
+    /// Argument rules per allowed command, keyed by program name
+    pub command_rules: Option<HashMap<String, CommandRule>>,
+    /// Confine `shell_exec` child processes (Linux only); unset runs them unconfined
+    pub sandbox: Option<ShellSandboxConfig>,
+    /// When a call must be approved by the user before it runs
+    pub confirm: Option<ConfirmMode>,
+}
+
+/// When a tool call waits for the user's approval.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfirmMode {
+    /// Every call
+    Always,
+    /// No call; the default
+    #[default]
+    Never,
+    /// Calls naming a path outside the module's filesystem roots; once approved,
+    /// that call may use those paths
+    OutsideRoots,
+}
+
+/// Kernel-level confinement of `shell_exec` child processes, applied before the program starts.
+///
+/// Enabling the table always sets no-new-privs; limits left unset are inherited from the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellSandboxConfig {
+    /// Seconds of CPU time (`RLIMIT_CPU`)
+    pub cpu_secs: Option<u64>,
+    /// Bytes of address space (`RLIMIT_AS`)
+    pub memory_bytes: Option<u64>,
+    /// Largest file the command may write (`RLIMIT_FSIZE`)
+    pub file_size_bytes: Option<u64>,
+    /// Processes the server's user may have (`RLIMIT_NPROC`)
+    pub max_processes: Option<u64>,
+    /// Refuse kernel, mount, namespace and tracing syscalls with a seccomp filter
+    #[serde(default = "default_true")]
+    pub seccomp: bool,
+    /// Run in a new network namespace with no usable interfaces
+    #[serde(default)]
+    pub isolate_network: bool,
+    /// Run in a new mount namespace where `/` is read-only and `TMPDIR` is a fresh writable scratch dir
+    #[serde(default)]
+    pub isolate_filesystem: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Arguments a `shell_exec` command may receive, checked by the host before it is spawned.
+///
+/// Arguments starting with `-` are flags; everything else, and anything after `--`, is an operand.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CommandRule {
+    /// Flags the command accepts; `--name=value` matches `--name` and `-la` matches `-l` and `-a`.
+    /// Attached values are checked like operands. Unset allows no flags when `path_args` or
+    /// `arg_pattern` is set, and any flag otherwise
+    pub allowed_flags: Option<Vec<String>>,
+    /// Operands are paths that must lie inside the module's roots with this access
+    pub path_args: Option<FsAccess>,
+    /// Regular expression every operand must match in full
+    pub arg_pattern: Option<String>,
+    /// Most arguments (flags and operands) after the program name
+    pub max_args: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,6 +334,101 @@ pub enum ModuleSource {
     Registry { name: String, version: Option<String> },
 }
 
+/// Bearer tokens accepted on the TCP transport, stored as SHA-256 hashes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub tokens: Vec<TokenConfig>,
+    /// TOML file with more `[[tokens]]` entries, kept out of the main config
+    pub tokens_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenConfig {
+    /// Who holds the token; recorded in the audit log
+    pub name: String,
+    /// Hex SHA-256 of the token, as printed by `desktop-app hash-token`
+    pub sha256: String,
+    /// Tools the token may list and call: `*`, a module name, or `module::tool`
+    pub scopes: Vec<String>,
+}
+
+/// Certificate and key for the TCP transport, re-read when the files change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first
+    pub cert: PathBuf,
+    /// PEM private key (PKCS#8, PKCS#1 or SEC1)
+    pub key: PathBuf,
+    /// PEM CA bundle; when set, clients must present a certificate it signed
+    pub client_ca: Option<PathBuf>,
+    /// Scopes granted to client certificates, by subject
+    #[serde(default)]
+    pub clients: Vec<ClientCertConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientCertConfig {
+    /// Common name, full subject such as `CN=laptop, O=Acme`, or `*` for any trusted certificate
+    pub subject: String,
+    /// Tools the certificate may list and call: `*`, a module name, or `module::tool`
+    pub scopes: Vec<String>,
+}
+
+/// Secrets masked before tool results reach the client and before anything is logged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactionConfig {
+    /// Mask common credential formats: AWS keys, bearer tokens, private keys and more
+    #[serde(default = "default_true")]
+    pub builtins: bool,
+    /// Extra regular expressions; when one has a group named `secret`, only that group is masked
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    /// Text that replaces each secret; defaults to `[REDACTED]`
+    pub replacement: Option<String>,
+}
+
+/// Append-only JSON Lines log of tool calls.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditConfig {
+    /// Defaults to `wasmforge/audit.jsonl` in the local data directory; `~/` expands to the home directory
+    pub path: Option<PathBuf>,
+    /// Size at which the log is rotated; defaults to 10 MiB
+    pub max_file_bytes: Option<u64>,
+    /// Rotated files kept as `audit.jsonl.1` (newest) to `.N`; defaults to 5
+    pub max_files: Option<usize>,
+    /// Argument names, matched at any depth and ignoring case, whose values are logged as `"[redacted]"`
+    #[serde(default = "default_redact_arguments")]
+    pub redact_arguments: Vec<String>,
+}
+
+impl AuditConfig {
+    pub const DEFAULT_MAX_FILE_BYTES: u64 = 10 * 1024 * 1024;
+    pub const DEFAULT_MAX_FILES: usize = 5;
+
+    pub fn path(&self) -> PathBuf {
+        match &self.path {
+            Some(path) => crate::fs_sandbox::expand_home(path),
+            None => dirs::data_local_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join("wasmforge")
+                .join("audit.jsonl"),
+        }
+    }
+
+    pub fn max_file_bytes(&self) -> u64 {
+        self.max_file_bytes.unwrap_or(Self::DEFAULT_MAX_FILE_BYTES)
+    }
+
+    pub fn max_files(&self) -> usize {
+        self.max_files.unwrap_or(Self::DEFAULT_MAX_FILES)
+    }
+}
+
+fn default_redact_arguments() -> Vec<String> {
+    ["content", "body", "headers", "env", "stdin"].iter().map(|name| name.to_string()).collect()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheConfig {
     pub directory: PathBuf,
@@ -72,6 +449,7 @@ impl Default for Config {
                 version: "0.1.0".to_string(),
                 default_port: None,
                 default_host: "127.0.0.1".to_string(),
+                tools_page_size: None,
             },
             modules: vec![
                 ModuleConfig {
@@ -85,33 +463,63 @@ impl Default for Config {
                     tools: Some(vec![
                         ToolConfig {
                             name: "add".to_string(),
+                            title: None,
                             description: Some("Add two numbers".to_string()),
                             function_name: "add".to_string(),
                             parameters: Some(serde_json::json!({
                                 "type": "object",
                                 "properties": {
-                                    "a": {"type": "number"},
-                                    "b": {"type": "number"}
+                                    "a": {"type": "integer", "minimum": i32::MIN, "maximum": i32::MAX},
+                                    "b": {"type": "integer", "minimum": i32::MIN, "maximum": i32::MAX}
                                 },
-                                "required": ["a", "b"]
+                                "required": ["a", "b"],
+                                "additionalProperties": false
                             })),
+                            kind: None,
+                            pattern: None,
+                            params: None,
+                            arguments: None,
+                            output_schema: None,
                             security: None,
+                            annotations: None,
                         },
                         ToolConfig {
                             name: "validate_url".to_string(),
+                            title: None,
                             description: Some("Validate URL format".to_string()),
                             function_name: "validate_url".to_string(),
                             parameters: Some(serde_json::json!({
                                 "type": "object",
                                 "properties": {
-                                    "url": {"type": "string"}
+                                    "url": {"type": "string", "minLength": 1}
                                 },
-                                "required": ["url"]
+                                "required": ["url"],
+                                "additionalProperties": false
                             })),
+                            kind: None,
+                            pattern: None,
+                            params: None,
+                            arguments: None,
+                            output_schema: None,
                             security: None,
+                            annotations: None,
                         },
                     ]),
                     metadata: None,
+                    http: None,
+                    // Read the directory the config was created from, write only to the temp dir
+                    filesystem: Some(FilesystemConfig {
+                        roots: std::env::current_dir().into_iter()
+                            .map(|path| FsRoot { path, access: FsAccess::ReadOnly })
+                            .chain(std::iter::once(FsRoot { path: std::env::temp_dir(), access: FsAccess::ReadWrite }))
+                            .collect(),
+                        max_read_bytes: None,
+                    }),
+                    // Naming the commands grants the module's shell tool
+                    security: Some(ToolSecurityConfig {
+                        allowed_commands: Some(DEFAULT_ALLOWED_COMMANDS.map(str::to_string).to_vec()),
+                        ..ToolSecurityConfig::default()
+                    }),
                 }
             ],
             cache: CacheConfig {
@@ -119,6 +527,11 @@ impl Default for Config {
                 max_size_mb: 100,
                 ttl_hours: 24,
             },
+            security: None,
+            audit: None,
+            redaction: None,
+            auth: None,
+            tls: None,
         }
     }
 }
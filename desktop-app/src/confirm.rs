@@ -0,0 +1,328 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::config::FsAccess;
+use crate::fs_sandbox::FsSandbox;
+use crate::fs_tools;
+use crate::redact::Redactor;
+use crate::session::Session;
+use crate::tool_discovery::DiscoveredTool;
+use crate::tool_kinds::{self, ToolKind};
+use crate::tool_result::{truncate_chars, ToolResult};
+
+/// Argument that carries a confirmation token back with the original arguments.
+pub const TOKEN_ARGUMENT: &str = "confirmation_token";
+
+/// How long a confirmation token stays valid.
+const TOKEN_TTL: Duration = Duration::from_secs(300);
+
+/// How long to wait for the user to answer an elicitation.
+const ELICITATION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Characters of each argument shown when asking the user.
+const PREVIEW_CHARS: usize = 200;
+
+/// Whether a held call may run.
+pub enum Decision {
+    Approved,
+    /// Returned to the client instead of running the call
+    Held(ToolResult),
+    /// Ask the user with this message through `elicitation/create`; see [`elicit`]
+    Ask(String),
+}
+
+/// Tokens issued to clients without elicitation support, one per held call.
+#[derive(Default)]
+pub struct Confirmations {
+    pending: HashMap<String, PendingCall>,
+}
+
+struct PendingCall {
+    /// Only the connection the token was issued to may redeem it
+    session: String,
+    digest: String,
+    expires: Instant,
+}
+
+impl Confirmations {
+    fn issue(&mut self, session: &Session, tool: &DiscoveredTool, arguments: &Value) -> String {
+        let now = Instant::now();
+        self.pending.retain(|_, call| call.expires > now);
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        self.pending.insert(token.clone(), PendingCall {
+            session: session.id().to_string(),
+            digest: call_digest(tool, arguments),
+            expires: now + TOKEN_TTL,
+        });
+        token
+    }
+
+    /// Consume a token. It is valid once, until it expires, on the connection it was issued
+    /// to, for exactly the call it was issued for. Other connections cannot use up the token.
+    fn redeem(&mut self, session: &Session, token: &str, tool: &DiscoveredTool, arguments: &Value) -> bool {
+        if self.pending.get(token).is_none_or(|call| call.session != session.id()) {
+            return false;
+        }
+        self.pending.remove(token)
+            .is_some_and(|call| call.expires > Instant::now() && call.digest == call_digest(tool, arguments))
+    }
+}
+
+/// Binds a token to the tool and its exact arguments; object keys serialize in sorted order.
+fn call_digest(tool: &DiscoveredTool, arguments: &Value) -> String {
+    fs_tools::sha256_hex(format!("{}::{}\0{}", tool.module_name, tool.name, arguments).as_bytes())
+}
+
+/// Decide whether a call needs the user: a token is checked here, a client supporting
+/// elicitation gets [`Decision::Ask`], and any other client gets a token to repeat the call with.
+///
+/// This never waits, so it can run while the server is locked.
+pub fn decide(
+    tool: &DiscoveredTool,
+    arguments: &Value,
+    token: Option<&str>,
+    outside: &[(PathBuf, FsAccess)],
+    confirmations: &mut Confirmations,
+    session: &Session,
+    redactor: &Redactor,
+) -> Decision {
+    if let Some(token) = token {
+        return if confirmations.redeem(session, token, tool, arguments) {
+            Decision::Approved
+        } else {
+            Decision::Held(ToolResult::error(
+                "Confirmation token is invalid, expired, already used, issued to another connection or for different arguments",
+            ))
+        };
+    }
+
+    let message = redactor.text(&describe(tool, arguments, outside)).into_owned();
+    if session.client_supports("elicitation") {
+        return Decision::Ask(message);
+    }
+
+    let token = confirmations.issue(session, tool, arguments);
+    let text = format!(
+        "Confirmation required. {}\n\nAsk the user to approve this call, then repeat it with the same arguments plus \"{}\": \"{}\". The token can be used once, on this connection, within {} seconds.",
+        message,
+        TOKEN_ARGUMENT,
+        token,
+        TOKEN_TTL.as_secs()
+    );
+    let structured = json!({
+        "confirmationRequired": true,
+        "confirmationToken": token,
+        "expiresInSecs": TOKEN_TTL.as_secs(),
+        "message": message,
+        "outsideRoots": outside.iter().map(|(path, _)| path.display().to_string()).collect::<Vec<_>>()
+    });
+    Decision::Held(ToolResult::structured(structured, text).with_error(true))
+}
+
+/// Ask the user through `elicitation/create` and wait up to [`ELICITATION_TIMEOUT`] for the answer.
+///
+/// Call this without holding the server lock: the wait must not stall other connections.
+pub async fn elicit(tool: &DiscoveredTool, message: String, session: &Session) -> Decision {
+    let params = json!({
+        "message": message,
+        "requestedSchema": {
+            "type": "object",
+            "properties": {
+                "approve": {
+                    "type": "boolean",
+                    "title": "Approve",
+                    "description": "Run this tool call"
+                }
+            },
+            "required": ["approve"]
+        }
+    });
+    match session.request("elicitation/create", params, ELICITATION_TIMEOUT).await {
+        Ok(reply) if reply["action"] == "accept" && reply["content"]["approve"] == true => Decision::Approved,
+        Ok(reply) => Decision::Held(ToolResult::error(format!(
+            "The user did not approve {}::{} ({})",
+            tool.module_name,
+            tool.name,
+            reply["action"].as_str().unwrap_or("no answer")
+        ))),
+        Err(e) => Decision::Held(ToolResult::error(format!("Could not ask the user for approval: {:#}", e))),
+    }
+}
+
+/// Paths a call names outside the module's filesystem roots, with the access it needs.
+pub fn paths_outside_roots(tool: &DiscoveredTool, arguments: &Value, sandbox: &FsSandbox) -> Vec<(PathBuf, FsAccess)> {
+    let input = |name: &str| arguments.get(tool.argument_name(name)).and_then(Value::as_str).filter(|value| !value.is_empty());
+    let mut named: Vec<(String, FsAccess)> = Vec::new();
+    match tool.kind {
+        ToolKind::FileRead | ToolKind::ListDir | ToolKind::FileStat | ToolKind::Glob | ToolKind::Grep => {
+            named.extend(input("path").map(|path| (path.to_string(), FsAccess::ReadOnly)));
+        }
+        ToolKind::FileWrite | ToolKind::FileAppend | ToolKind::MakeDir | ToolKind::FileDelete => {
+            named.extend(input("path").map(|path| (path.to_string(), FsAccess::ReadWrite)));
+        }
+        ToolKind::FileMove => {
+            named.extend(["source", "destination"].into_iter().filter_map(input).map(|path| (path.to_string(), FsAccess::ReadWrite)));
+        }
+        ToolKind::ShellExec => {
+            let cwd = input("cwd").unwrap_or(".");
+            named.push((cwd.to_string(), FsAccess::ReadOnly));
+            // Path-like operands, resolved lexically so `..` cannot hide where they point
+            let base = sandbox.resolve(cwd, FsAccess::ReadOnly).or_else(|_| Path::new(cwd).canonicalize()).ok();
+            let argv = tool_kinds::shell_argv(tool, arguments).unwrap_or_default();
+            for arg in argv.iter().skip(1) {
+                let operand = arg.split_once('=').filter(|_| arg.starts_with('-')).map_or(arg.as_str(), |(_, value)| value);
+                if !operand.contains('/') && operand != ".." {
+                    continue;
+                }
+                let path = match &base {
+                    Some(base) => normalize(&base.join(operand)),
+                    None => normalize(Path::new(operand)),
+                };
+                named.push((path.to_string_lossy().into_owned(), FsAccess::ReadOnly));
+            }
+        }
+        _ => {}
+    }
+
+    let mut outside = Vec::new();
+    for (path, access) in named {
+        if let Some(entry) = sandbox.outside_roots(&path, access)
+            && !outside.iter().any(|(seen, _)| seen == &entry)
+        {
+            outside.push((entry, access));
+        }
+    }
+    outside
+}
+
+/// Advertise the token argument in a tool's input schema.
+pub fn advertise_token(schema: &mut Value) {
+    if !schema["properties"].is_object() {
+        schema["properties"] = json!({});
+    }
+    schema["properties"][TOKEN_ARGUMENT] = json!({
+        "type": "string",
+        "description": "Token from a \"confirmation required\" result; send it with the same arguments after the user approved the call"
+    });
+}
+
+/// What the user is asked to approve.
+fn describe(tool: &DiscoveredTool, arguments: &Value, outside: &[(PathBuf, FsAccess)]) -> String {
+    let mut lines = vec![format!("Allow {}::{} ({}) to run?", tool.module_name, tool.name, tool.kind.as_str())];
+    if let Some(arguments) = arguments.as_object() {
+        for (name, value) in arguments {
+            let text = value.as_str().map_or_else(|| value.to_string(), str::to_string);
+            let (shown, truncated) = truncate_chars(&text, PREVIEW_CHARS);
+            lines.push(format!("  {}: {}{}", name, shown, if truncated { "…" } else { "" }));
+        }
+    }
+    if !outside.is_empty() {
+        lines.push("Outside the module's filesystem roots:".to_string());
+        for (path, access) in outside {
+            let kind = if *access == FsAccess::ReadWrite { "write" } else { "read" };
+            lines.push(format!("  {} ({})", path.display(), kind));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Drop `.` and apply `..` without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, SandboxFixture};
+    use tokio::sync::mpsc;
+
+    fn session() -> Session {
+        Session::new(mpsc::unbounded_channel().0, None)
+    }
+
+    fn write_tool() -> DiscoveredTool {
+        test_support::tool("files", "write", ToolKind::FileWrite)
+    }
+
+    #[test]
+    fn tokens_are_redeemed_once() {
+        let (session, tool, arguments) = (session(), write_tool(), json!({"path": "a.txt"}));
+        let mut confirmations = Confirmations::default();
+        let token = confirmations.issue(&session, &tool, &arguments);
+        assert!(confirmations.redeem(&session, &token, &tool, &arguments));
+        assert!(!confirmations.redeem(&session, &token, &tool, &arguments));
+    }
+
+    #[test]
+    fn expired_tokens_are_refused() {
+        let (session, tool, arguments) = (session(), write_tool(), json!({"path": "a.txt"}));
+        let mut confirmations = Confirmations::default();
+        let token = confirmations.issue(&session, &tool, &arguments);
+        confirmations.pending.get_mut(&token).unwrap().expires = Instant::now();
+        assert!(!confirmations.redeem(&session, &token, &tool, &arguments));
+    }
+
+    #[test]
+    fn other_sessions_cannot_use_up_a_token() {
+        let (owner, tool, arguments) = (session(), write_tool(), json!({"path": "a.txt"}));
+        let mut confirmations = Confirmations::default();
+        let token = confirmations.issue(&owner, &tool, &arguments);
+        assert!(!confirmations.redeem(&session(), &token, &tool, &arguments));
+        assert!(confirmations.redeem(&owner, &token, &tool, &arguments));
+    }
+
+    #[test]
+    fn tokens_are_bound_to_the_tool_and_arguments() {
+        let (session, tool, arguments) = (session(), write_tool(), json!({"path": "a.txt", "content": "x"}));
+        // Key order does not change the digest
+        assert_eq!(call_digest(&tool, &arguments), call_digest(&tool, &json!({"content": "x", "path": "a.txt"})));
+        assert_ne!(call_digest(&tool, &arguments), call_digest(&tool, &json!({"path": "b.txt", "content": "x"})));
+
+        let mut confirmations = Confirmations::default();
+        let token = confirmations.issue(&session, &tool, &arguments);
+        assert!(!confirmations.redeem(&session, &token, &tool, &json!({"path": "b.txt", "content": "x"})));
+
+        let token = confirmations.issue(&session, &tool, &arguments);
+        let other = test_support::tool("files", "append", ToolKind::FileAppend);
+        assert!(!confirmations.redeem(&session, &token, &other, &arguments));
+    }
+
+    #[test]
+    fn shell_operands_are_resolved_before_checking_roots() {
+        let fixture = SandboxFixture::new(&[("root", FsAccess::ReadWrite)], None);
+        fixture.write("root/inside.txt", "inside");
+        fixture.write("outside/secret.txt", "secret");
+        let tool = test_support::tool("shell", "run", ToolKind::ShellExec);
+        let arguments = json!({
+            "argv": ["cat", "inside.txt", "sub/../inside.txt", "../outside/secret.txt", "-o=/etc/passwd", "--file=../outside"],
+            "cwd": fixture.path("root"),
+        });
+
+        let outside = paths_outside_roots(&tool, &arguments, &fixture.sandbox);
+        let paths: Vec<&Path> = outside.iter().map(|(path, _)| path.as_path()).collect();
+        assert_eq!(paths, [fixture.base.join("outside/secret.txt").as_path(), Path::new("/etc/passwd"), &fixture.base.join("outside")]);
+        assert!(outside.iter().all(|(_, access)| *access == FsAccess::ReadOnly));
+    }
+
+    #[test]
+    fn move_destinations_need_write_access() {
+        let fixture = SandboxFixture::new(&[("root", FsAccess::ReadWrite)], None);
+        let tool = test_support::tool("files", "move", ToolKind::FileMove);
+        let arguments = json!({"source": fixture.path("root/a.txt"), "destination": fixture.path("elsewhere.txt")});
+        let outside = paths_outside_roots(&tool, &arguments, &fixture.sandbox);
+        assert_eq!(outside, [(fixture.base.join("elsewhere.txt"), FsAccess::ReadWrite)]);
+    }
+}
@@ -0,0 +1,247 @@
+use anyhow::{Context, Result};
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::{FilesystemConfig, FsAccess};
+
+/// Host-side confinement of file tools to a module's configured roots.
+///
+/// Enforced independently of the module's validator: every path is canonicalized
+/// (following symlinks) and must land inside a root granting the needed access.
+#[derive(Debug, Clone)]
+pub struct FsSandbox {
+    roots: Vec<(PathBuf, FsAccess)>,
+    /// Paths outside the roots that the user approved for the current call
+    grants: Vec<(PathBuf, FsAccess)>,
+    max_read_bytes: u64,
+}
+
+impl FsSandbox {
+    /// Canonicalize the configured roots; roots that do not exist are skipped.
+    pub fn new(config: Option<&FilesystemConfig>) -> Self {
+        let roots = config
+            .map(|config| config.roots.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|root| {
+                let path = expand_home(&root.path);
+                match path.canonicalize() {
+                    Ok(canonical) if canonical.is_dir() => Some((canonical, root.access)),
+                    _ => {
+                        eprintln!("⚠️  Ignoring filesystem root '{}': not an existing directory", path.display());
+                        None
+                    }
+                }
+            })
+            .collect();
+        let max_read_bytes = config.map_or(FilesystemConfig::DEFAULT_MAX_READ_BYTES, FilesystemConfig::max_read_bytes);
+        Self { roots, grants: Vec::new(), max_read_bytes }
+    }
+
+    /// Largest number of bytes a single read may return.
+    pub fn max_read_bytes(&self) -> u64 {
+        self.max_read_bytes
+    }
+
+    /// Resolve a path for reading or writing, returning the canonical path to use.
+    ///
+    /// Relative paths resolve against the first root granting `access`. `..` components
+    /// are rejected outright. For writes the path may not exist yet, so its deepest
+    /// existing ancestor is canonicalized instead.
+    pub fn resolve(&self, path: &str, access: FsAccess) -> Result<PathBuf> {
+        let candidate = self.candidate(path, access)?;
+
+        let canonical = match candidate.canonicalize() {
+            Ok(canonical) => canonical,
+            Err(_) if access == FsAccess::ReadWrite => canonicalize_missing(&candidate, path)?,
+            Err(e) => return Err(anyhow::Error::new(e).context(format!("Failed to resolve path: {}", path))),
+        };
+
+        self.check(canonical, path, access)
+    }
+
+    /// Resolve a directory entry without following a symlink in its last component,
+    /// for operations that act on the entry itself (move, delete).
+    pub fn resolve_entry(&self, path: &str, access: FsAccess) -> Result<PathBuf> {
+        let candidate = self.candidate(path, access)?;
+        let (Some(parent), Some(name)) = (candidate.parent(), candidate.file_name()) else {
+            return Err(anyhow::anyhow!("Invalid path: {}", path));
+        };
+        let parent = parent.canonicalize()
+            .with_context(|| format!("Parent directory of '{}' does not exist", path))?;
+
+        self.check(parent.join(name), path, access)
+    }
+
+    /// Whether a canonical path lies inside a root or grant permitting `access`.
+    pub fn permits(&self, canonical: &Path, access: FsAccess) -> bool {
+        self.roots.iter().chain(&self.grants).any(|(root, granted)| granted.permits(access) && canonical.starts_with(root))
+    }
+
+    /// The entry a path names, if it lies outside every root granting `access`.
+    ///
+    /// The last component is not followed, matching `resolve_entry`. Paths that cannot
+    /// be resolved return `None`; the operation itself reports them.
+    pub fn outside_roots(&self, path: &str, access: FsAccess) -> Option<PathBuf> {
+        let candidate = self.candidate(path, access).ok()?;
+        let entry = match (candidate.parent(), candidate.file_name()) {
+            (Some(parent), Some(name)) => canonicalize_missing(parent, path).ok()?.join(name),
+            _ => candidate,
+        };
+        (!self.permits(&entry, access)).then_some(entry)
+    }
+
+    /// Allow one path outside the roots, after the user approved a call naming it.
+    /// Grants are not roots: `is_root` ignores them.
+    pub fn grant(&mut self, path: PathBuf, access: FsAccess) {
+        self.grants.push((path, access));
+    }
+
+    /// Whether a canonical path is one of the roots themselves.
+    pub fn is_root(&self, canonical: &Path) -> bool {
+        self.roots.iter().any(|(root, _)| root == canonical)
+    }
+
+    /// The requested path, made absolute but not yet canonicalized.
+    fn candidate(&self, path: &str, access: FsAccess) -> Result<PathBuf> {
+        if path.is_empty() || path.contains('\0') {
+            return Err(anyhow::anyhow!("Invalid path: {:?}", path));
+        }
+        let requested = Path::new(path);
+        if requested.components().any(|c| matches!(c, Component::ParentDir)) {
+            return Err(anyhow::anyhow!("Path '{}' must not contain '..'", path));
+        }
+
+        if requested.is_absolute() {
+            return Ok(requested.to_path_buf());
+        }
+        let (root, _) = self.roots.iter()
+            .find(|(_, granted)| granted.permits(access))
+            .ok_or_else(|| self.denied(path, access))?;
+        Ok(root.join(requested))
+    }
+
+    fn check(&self, canonical: PathBuf, path: &str, access: FsAccess) -> Result<PathBuf> {
+        if self.permits(&canonical, access) {
+            Ok(canonical)
+        } else {
+            Err(self.denied(path, access))
+        }
+    }
+
+    fn denied(&self, path: &str, access: FsAccess) -> anyhow::Error {
+        let kind = if access == FsAccess::ReadWrite { "writable" } else { "readable" };
+        if self.roots.is_empty() {
+            anyhow::anyhow!("File access denied: the module has no [modules.filesystem] roots")
+        } else {
+            anyhow::anyhow!("File access denied: '{}' is outside the module's {} roots", path, kind)
+        }
+    }
+}
+
+/// Canonicalize the deepest existing ancestor of a path that does not exist yet.
+fn canonicalize_missing(candidate: &Path, path: &str) -> Result<PathBuf> {
+    for ancestor in candidate.ancestors() {
+        if ancestor.symlink_metadata().is_err() {
+            continue;
+        }
+        // A dangling symlink here would be followed by the write itself
+        let base = ancestor.canonicalize()
+            .map_err(|_| anyhow::anyhow!("Path '{}' goes through a symlink to a missing target", path))?;
+        let rest = candidate.strip_prefix(ancestor).unwrap_or(Path::new(""));
+        return Ok(base.join(rest));
+    }
+    Err(anyhow::anyhow!("Invalid path: {}", path))
+}
+
+/// Expand a leading `~` to the home directory.
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::SandboxFixture;
+
+    /// A read-only and a read-write root side by side, plus a directory outside both.
+    fn fixture() -> SandboxFixture {
+        let fixture = SandboxFixture::new(&[("ro", FsAccess::ReadOnly), ("rw", FsAccess::ReadWrite)], None);
+        fixture.write("ro/file.txt", "ro");
+        fixture.write("outside/secret.txt", "secret");
+        fixture
+    }
+
+    #[test]
+    fn paths_inside_roots_resolve_with_their_access() {
+        let fixture = fixture();
+        let file = fixture.sandbox.resolve(&fixture.path("ro/file.txt"), FsAccess::ReadOnly).unwrap();
+        assert_eq!(file, fixture.base.join("ro/file.txt"));
+        assert!(fixture.sandbox.resolve(&fixture.path("ro/file.txt"), FsAccess::ReadWrite).is_err());
+
+        let new_file = fixture.sandbox.resolve(&fixture.path("rw/new/file.txt"), FsAccess::ReadWrite).unwrap();
+        assert_eq!(new_file, fixture.base.join("rw/new/file.txt"));
+        // Relative paths resolve against the first root granting the access
+        assert_eq!(fixture.sandbox.resolve("new.txt", FsAccess::ReadWrite).unwrap(), fixture.base.join("rw/new.txt"));
+    }
+
+    #[test]
+    fn parent_components_are_rejected() {
+        let fixture = fixture();
+        for path in [fixture.path("ro/../outside/secret.txt"), "../outside/secret.txt".to_string(), fixture.path("rw/a/../b")] {
+            let error = fixture.sandbox.resolve(&path, FsAccess::ReadOnly).unwrap_err();
+            assert!(error.to_string().contains("'..'"), "{}: {}", path, error);
+        }
+    }
+
+    #[test]
+    fn paths_outside_roots_are_denied() {
+        let fixture = fixture();
+        let error = fixture.sandbox.resolve(&fixture.path("outside/secret.txt"), FsAccess::ReadOnly).unwrap_err();
+        assert!(error.to_string().contains("outside the module's readable roots"), "{}", error);
+        assert!(fixture.sandbox.resolve("", FsAccess::ReadOnly).is_err());
+        assert!(fixture.sandbox.resolve("a\0b", FsAccess::ReadOnly).is_err());
+        assert!(FsSandbox::new(None).resolve(&fixture.path("ro/file.txt"), FsAccess::ReadOnly).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_leading_out_of_roots_are_denied() {
+        use std::os::unix::fs::symlink;
+
+        let fixture = fixture();
+        symlink(fixture.base.join("outside/secret.txt"), fixture.base.join("rw/link.txt")).unwrap();
+        symlink(fixture.base.join("outside"), fixture.base.join("rw/linked_dir")).unwrap();
+        symlink(fixture.base.join("outside/missing.txt"), fixture.base.join("rw/dangling.txt")).unwrap();
+
+        assert!(fixture.sandbox.resolve(&fixture.path("rw/link.txt"), FsAccess::ReadOnly).is_err());
+        assert!(fixture.sandbox.resolve(&fixture.path("rw/linked_dir/secret.txt"), FsAccess::ReadOnly).is_err());
+        assert!(fixture.sandbox.resolve(&fixture.path("rw/linked_dir/new.txt"), FsAccess::ReadWrite).is_err());
+        let error = fixture.sandbox.resolve(&fixture.path("rw/dangling.txt"), FsAccess::ReadWrite).unwrap_err();
+        assert!(error.to_string().contains("symlink to a missing target"), "{}", error);
+
+        // The entry itself can still be moved or deleted
+        let entry = fixture.sandbox.resolve_entry(&fixture.path("rw/link.txt"), FsAccess::ReadWrite).unwrap();
+        assert_eq!(entry, fixture.base.join("rw/link.txt"));
+    }
+
+    #[test]
+    fn grants_allow_approved_paths_only() {
+        let fixture = fixture();
+        let mut sandbox = fixture.sandbox.clone();
+        let secret = fixture.base.join("outside/secret.txt");
+        assert_eq!(sandbox.outside_roots(&fixture.path("outside/secret.txt"), FsAccess::ReadOnly), Some(secret.clone()));
+
+        sandbox.grant(secret.clone(), FsAccess::ReadOnly);
+        assert_eq!(sandbox.resolve(&fixture.path("outside/secret.txt"), FsAccess::ReadOnly).unwrap(), secret);
+        assert_eq!(sandbox.outside_roots(&fixture.path("outside/secret.txt"), FsAccess::ReadOnly), None);
+        assert!(sandbox.resolve(&fixture.path("outside/secret.txt"), FsAccess::ReadWrite).is_err());
+        assert!(!sandbox.is_root(&secret));
+        assert!(sandbox.is_root(&fixture.base.join("rw")));
+
+        // The original sandbox is untouched
+        assert!(fixture.sandbox.resolve(&fixture.path("outside/secret.txt"), FsAccess::ReadOnly).is_err());
+    }
+}
@@ -0,0 +1,744 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::config::FsAccess;
+use crate::fs_sandbox::FsSandbox;
+use crate::tool_result::{detect_mime_type, truncate_chars};
+
+// Host side of the filesystem tool kinds. Every path goes through the module's
+// `FsSandbox` before it is touched.
+
+/// Entries returned by `list_dir` and `glob`.
+pub const MAX_ENTRIES: usize = 1000;
+/// Matching lines returned by `grep`.
+pub const MAX_MATCHES: usize = 200;
+const MAX_DEPTH: usize = 32;
+const MAX_GREP_FILE_BYTES: u64 = 1024 * 1024;
+const MAX_LINE_CHARS: usize = 500;
+
+/// Part of a file to read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadRange {
+    Whole,
+    /// `length` bytes from `offset`, or up to the end of the file
+    Bytes { offset: u64, length: Option<u64> },
+    /// 1-based inclusive line numbers; `end` defaults to the last line
+    Lines { start: u64, end: Option<u64> },
+}
+
+/// Bytes read from a file together with what they are.
+#[derive(Debug, Clone)]
+pub struct FileContent {
+    pub path: PathBuf,
+    pub data: Vec<u8>,
+    pub mime_type: String,
+    /// Size of the whole file
+    pub total_size: u64,
+}
+
+/// Read a file or part of it, refusing reads larger than the sandbox's limit before
+/// any data is loaded.
+pub async fn read(sandbox: &FsSandbox, path: &str, range: ReadRange) -> Result<FileContent> {
+    let resolved = sandbox.resolve(path, FsAccess::ReadOnly)?;
+    let metadata = tokio::fs::metadata(&resolved).await
+        .with_context(|| format!("Failed to read file: {}", path))?;
+    if !metadata.is_file() {
+        return Err(anyhow::anyhow!("Not a regular file: {}", path));
+    }
+    let total_size = metadata.len();
+    let limit = sandbox.max_read_bytes();
+    let too_large = |size: u64| anyhow::anyhow!(
+        "Read of {} bytes exceeds the module's limit of {} bytes; read a range with offset/length or start_line/end_line",
+        size, limit
+    );
+
+    let mut file = tokio::fs::File::open(&resolved).await
+        .with_context(|| format!("Failed to open file: {}", path))?;
+    let data = match range {
+        ReadRange::Whole => {
+            if total_size > limit {
+                return Err(too_large(total_size));
+            }
+            let mut data = Vec::with_capacity(total_size as usize);
+            file.read_to_end(&mut data).await?;
+            data
+        }
+        ReadRange::Bytes { offset, length } => {
+            let offset = offset.min(total_size);
+            let available = total_size - offset;
+            let wanted = length.map_or(available, |length| length.min(available));
+            if wanted > limit {
+                return Err(too_large(wanted));
+            }
+            file.seek(std::io::SeekFrom::Start(offset)).await?;
+            let mut data = Vec::with_capacity(wanted as usize);
+            file.take(wanted).read_to_end(&mut data).await?;
+            data
+        }
+        ReadRange::Lines { start, end } => {
+            // Lines are found by scanning from the top, so the scan is held to the limit
+            // too; one byte past it is enough to tell that the limit was hit
+            let mut reader = tokio::io::BufReader::new(file).take(limit.saturating_add(1));
+            let mut data = Vec::new();
+            let mut line = Vec::new();
+            let mut scanned = 0u64;
+            let mut number = 0;
+            while end.is_none_or(|end| number < end) {
+                line.clear();
+                let read = reader.read_until(b'\n', &mut line).await?;
+                if read == 0 {
+                    break;
+                }
+                scanned += read as u64;
+                if scanned > limit {
+                    return Err(anyhow::anyhow!(
+                        "Reaching line {} scans more than the module's limit of {} bytes; read a range with offset/length",
+                        number + 1, limit
+                    ));
+                }
+                number += 1;
+                if number >= start {
+                    data.extend_from_slice(&line);
+                }
+            }
+            data
+        }
+    };
+
+    let mime_type = detect_mime_type(&data, &resolved);
+    Ok(FileContent { path: resolved, data, mime_type, total_size })
+}
+
+/// How `file_write` treats an existing file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WriteMode {
+    /// Fail if the file already exists
+    CreateNew,
+    #[default]
+    Overwrite,
+    Append,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    pub mode: WriteMode,
+    /// Copy the previous contents to `<file>.bak` first
+    pub backup: bool,
+    /// Return a line diff against the previous contents
+    pub diff: bool,
+    /// Only write if the current contents hash to this SHA-256 (hex)
+    pub expected_sha256: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WriteOutcome {
+    pub path: PathBuf,
+    /// SHA-256 of the file after the write; unknown after appending to an unread file
+    pub sha256: Option<String>,
+    pub backup: Option<PathBuf>,
+    pub diff: Option<String>,
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    format!("{:x}", hasher.finalize())
+}
+
+/// Write a file inside the sandbox.
+///
+/// `create_new` and `overwrite` write a temporary file next to the target and move it
+/// into place, so readers never see a partial file; `append` appends in place.
+pub async fn write(sandbox: &FsSandbox, path: &str, content: &[u8], options: &WriteOptions) -> Result<WriteOutcome> {
+    let resolved = sandbox.resolve(path, FsAccess::ReadWrite)?;
+    let existing = match tokio::fs::metadata(&resolved).await {
+        Ok(metadata) if metadata.is_file() => Some(metadata),
+        Ok(_) => return Err(anyhow::anyhow!("Not a regular file: {}", path)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(anyhow::Error::new(e).context(format!("Failed to stat: {}", path))),
+    };
+
+    if options.mode == WriteMode::CreateNew && existing.is_some() {
+        return Err(anyhow::anyhow!("File already exists: {}", path));
+    }
+
+    let needs_previous = options.diff || options.expected_sha256.is_some();
+    let previous = match (&existing, needs_previous) {
+        (Some(metadata), true) => {
+            if metadata.len() > sandbox.max_read_bytes() {
+                return Err(anyhow::anyhow!("Existing file is larger than the module's read limit: {}", path));
+            }
+            Some(tokio::fs::read(&resolved).await.with_context(|| format!("Failed to read file: {}", path))?)
+        }
+        _ => None,
+    };
+
+    if let Some(expected) = &options.expected_sha256 {
+        let actual = previous.as_deref().map(sha256_hex)
+            .ok_or_else(|| anyhow::anyhow!("File does not exist, so it cannot match expected_sha256: {}", path))?;
+        if !actual.eq_ignore_ascii_case(expected) {
+            return Err(anyhow::anyhow!("File changed since it was read: expected sha256 {}, found {}", expected, actual));
+        }
+    }
+
+    let backup = match (&existing, options.backup) {
+        (Some(metadata), true) => {
+            let mut name = resolved.file_name().unwrap_or_default().to_os_string();
+            name.push(".bak");
+            let backup = resolved.with_file_name(name);
+            write_backup(&resolved, &backup, metadata).await
+                .with_context(|| format!("Failed to back up file: {}", path))?;
+            Some(backup)
+        }
+        _ => None,
+    };
+
+    let final_content = match (options.mode, &previous) {
+        (WriteMode::Append, Some(previous)) => [previous.as_slice(), content].concat(),
+        _ => content.to_vec(),
+    };
+
+    if options.mode == WriteMode::Append {
+        let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(&resolved).await
+            .with_context(|| format!("Failed to open file for appending: {}", path))?;
+        file.write_all(content).await
+            .with_context(|| format!("Failed to append to file: {}", path))?;
+        file.sync_all().await?;
+    } else {
+        write_atomic(&resolved, content, options.mode == WriteMode::CreateNew, existing.as_ref()).await
+            .with_context(|| format!("Failed to write file: {}", path))?;
+    }
+
+    let diff = match (options.diff, &previous) {
+        (true, Some(previous)) => Some(line_diff(&String::from_utf8_lossy(previous), &String::from_utf8_lossy(&final_content))),
+        (true, None) => Some("(new file)".to_string()),
+        _ => None,
+    };
+
+    let sha256 = match (options.mode, &existing, &previous) {
+        (WriteMode::Append, Some(_), None) => None,
+        _ => Some(sha256_hex(&final_content)),
+    };
+
+    Ok(WriteOutcome { path: resolved, sha256, backup, diff })
+}
+
+/// Unused name next to `target` for staging a write.
+fn temp_sibling(target: &Path) -> PathBuf {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    target.with_file_name(format!(".{}.{}.tmp", name, uuid::Uuid::new_v4()))
+}
+
+/// Copy `source` to `backup` through a temporary sibling and a rename, so a symlink
+/// planted at `backup` is replaced rather than written through.
+async fn write_backup(source: &Path, backup: &Path, metadata: &Metadata) -> Result<()> {
+    let temp = temp_sibling(backup);
+
+    let result = async {
+        let mut from = tokio::fs::File::open(source).await?;
+        let mut to = tokio::fs::OpenOptions::new().write(true).create_new(true).open(&temp).await?;
+        tokio::io::copy(&mut from, &mut to).await?;
+        to.sync_all().await?;
+        drop(to);
+        tokio::fs::set_permissions(&temp, metadata.permissions()).await?;
+        tokio::fs::rename(&temp, backup).await?;
+        Ok(())
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temp).await;
+    }
+    result
+}
+
+/// Write to a temporary sibling, flush it to disk, then move it over the target.
+async fn write_atomic(target: &Path, content: &[u8], create_new: bool, existing: Option<&Metadata>) -> Result<()> {
+    let temp = temp_sibling(target);
+
+    let result = async {
+        let mut file = tokio::fs::OpenOptions::new().write(true).create_new(true).open(&temp).await?;
+        file.write_all(content).await?;
+        file.sync_all().await?;
+        drop(file);
+        if let Some(metadata) = existing {
+            tokio::fs::set_permissions(&temp, metadata.permissions()).await?;
+        }
+
+        if create_new {
+            // Linking fails if the target appeared in the meantime, unlike rename
+            match tokio::fs::hard_link(&temp, target).await {
+                Ok(()) => tokio::fs::remove_file(&temp).await?,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    return Err(anyhow::anyhow!("File already exists: {}", target.display()));
+                }
+                Err(_) => tokio::fs::rename(&temp, target).await?,
+            }
+        } else {
+            tokio::fs::rename(&temp, target).await?;
+        }
+        Ok(())
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temp).await;
+    }
+    result
+}
+
+/// Lines compared by `line_diff` before it gives up on a full diff.
+const MAX_DIFF_LINES: usize = 2000;
+const DIFF_CONTEXT: usize = 2;
+
+/// Unified-style line diff with a little context around each change.
+pub fn line_diff(old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    if old_lines == new_lines {
+        return "(no changes)".to_string();
+    }
+    if old_lines.len() > MAX_DIFF_LINES || new_lines.len() > MAX_DIFF_LINES {
+        return format!("(too large to diff: {} -> {} lines)", old_lines.len(), new_lines.len());
+    }
+
+    // Longest common subsequence table, filled from the end
+    let (n, m) = (old_lines.len(), new_lines.len());
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old_lines[i] == new_lines[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+
+    // (tag, line) where tag is ' ', '-' or '+'
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old_lines[i] == new_lines[j] {
+            ops.push((' ', old_lines[i]));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(('-', old_lines[i]));
+            i += 1;
+        } else {
+            ops.push(('+', new_lines[j]));
+            j += 1;
+        }
+    }
+
+    let changed: Vec<usize> = ops.iter().enumerate().filter(|(_, (tag, _))| *tag != ' ').map(|(index, _)| index).collect();
+    let mut output = Vec::new();
+    let mut last_shown = None;
+    for (index, (tag, line)) in ops.iter().enumerate() {
+        let near_change = changed.iter().any(|c| index + DIFF_CONTEXT >= *c && index <= c + DIFF_CONTEXT);
+        if !near_change {
+            continue;
+        }
+        if last_shown.is_some_and(|last| index > last + 1) || (last_shown.is_none() && index > 0) {
+            output.push("...".to_string());
+        }
+        output.push(format!("{}{}", tag, line));
+        last_shown = Some(index);
+    }
+    if last_shown.is_some_and(|last| last + 1 < ops.len()) {
+        output.push("...".to_string());
+    }
+    output.join("\n")
+}
+
+pub async fn list_dir(sandbox: &FsSandbox, path: &str) -> Result<Value> {
+    let dir = sandbox.resolve(path, FsAccess::ReadOnly)?;
+    let mut reader = tokio::fs::read_dir(&dir).await
+        .with_context(|| format!("Failed to list directory: {}", path))?;
+
+    let mut entries = Vec::new();
+    let mut truncated = false;
+    while let Some(entry) = reader.next_entry().await? {
+        if entries.len() == MAX_ENTRIES {
+            truncated = true;
+            break;
+        }
+        let metadata = entry.metadata().await?;
+        entries.push(json!({
+            "name": entry.file_name().to_string_lossy(),
+            "type": entry_type(&metadata),
+            "size": metadata.len()
+        }));
+    }
+    entries.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+
+    Ok(json!({ "path": dir.display().to_string(), "entries": entries, "truncated": truncated }))
+}
+
+pub async fn stat(sandbox: &FsSandbox, path: &str) -> Result<Value> {
+    let resolved = sandbox.resolve(path, FsAccess::ReadOnly)?;
+    let metadata = tokio::fs::metadata(&resolved).await
+        .with_context(|| format!("Failed to stat: {}", path))?;
+
+    let mut result = json!({
+        "path": resolved.display().to_string(),
+        "type": entry_type(&metadata),
+        "size": metadata.len(),
+        "readonly": metadata.permissions().readonly()
+    });
+    if let Some(modified) = metadata.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+        result["modified"] = json!(modified.as_secs());
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        result["mode"] = json!(format!("{:o}", metadata.permissions().mode() & 0o7777));
+    }
+    Ok(result)
+}
+
+/// Find paths under `path` (default: the first readable root) matching a glob pattern.
+pub async fn glob(sandbox: &FsSandbox, pattern: &str, path: &str) -> Result<Value> {
+    let matcher = glob::Pattern::new(pattern)
+        .with_context(|| format!("Invalid glob pattern: {}", pattern))?;
+    let base = sandbox.resolve(search_base(path), FsAccess::ReadOnly)?;
+    let sandbox = sandbox.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let options = glob::MatchOptions { require_literal_separator: true, ..Default::default() };
+        let mut matches = Vec::new();
+        let mut truncated = false;
+        walk(&sandbox, &base, &mut |relative, _, metadata| {
+            if matcher.matches_path_with(relative, options) {
+                if matches.len() == MAX_ENTRIES {
+                    truncated = true;
+                    return false;
+                }
+                matches.push(json!({ "path": relative.display().to_string(), "type": entry_type(metadata) }));
+            }
+            true
+        });
+        json!({ "base": base.display().to_string(), "matches": matches, "truncated": truncated })
+    })
+    .await
+    .context("Glob search failed")
+}
+
+/// Search UTF-8 files under `path` (a file or directory) for lines matching a regex.
+pub async fn grep(sandbox: &FsSandbox, pattern: &str, path: &str) -> Result<Value> {
+    let regex = regex::Regex::new(pattern)
+        .with_context(|| format!("Invalid regular expression: {}", pattern))?;
+    let base = sandbox.resolve(search_base(path), FsAccess::ReadOnly)?;
+    let sandbox = sandbox.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let mut matches = Vec::new();
+        let mut files_searched = 0;
+        let mut truncated = false;
+
+        let mut search = |display: &Path, file: &Path, metadata: &Metadata| {
+            if !metadata.is_file() || metadata.len() > MAX_GREP_FILE_BYTES {
+                return true;
+            }
+            let Ok(content) = std::fs::read_to_string(file) else {
+                return true; // Binary or unreadable
+            };
+            files_searched += 1;
+            for (index, line) in content.lines().enumerate() {
+                if regex.is_match(line) {
+                    if matches.len() == MAX_MATCHES {
+                        truncated = true;
+                        return false;
+                    }
+                    matches.push(json!({
+                        "path": display.display().to_string(),
+                        "line": index + 1,
+                        "text": truncate_chars(line, MAX_LINE_CHARS).0
+                    }));
+                }
+            }
+            true
+        };
+
+        match std::fs::metadata(&base) {
+            Ok(metadata) if metadata.is_file() => {
+                search(&base, &base, &metadata);
+            }
+            _ => walk(&sandbox, &base, &mut search),
+        }
+        json!({ "base": base.display().to_string(), "matches": matches, "filesSearched": files_searched, "truncated": truncated })
+    })
+    .await
+    .context("Grep search failed")
+}
+
+pub async fn append(sandbox: &FsSandbox, path: &str, content: &str) -> Result<PathBuf> {
+    let resolved = sandbox.resolve(path, FsAccess::ReadWrite)?;
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&resolved)
+        .await
+        .with_context(|| format!("Failed to open file for appending: {}", path))?;
+    file.write_all(content.as_bytes()).await
+        .with_context(|| format!("Failed to append to file: {}", path))?;
+    file.sync_all().await?;
+    Ok(resolved)
+}
+
+pub async fn make_dir(sandbox: &FsSandbox, path: &str) -> Result<PathBuf> {
+    let resolved = sandbox.resolve(path, FsAccess::ReadWrite)?;
+    tokio::fs::create_dir_all(&resolved).await
+        .with_context(|| format!("Failed to create directory: {}", path))?;
+    Ok(resolved)
+}
+
+/// Rename a file or directory; never replaces an existing destination.
+pub async fn move_entry(sandbox: &FsSandbox, source: &str, destination: &str) -> Result<(PathBuf, PathBuf)> {
+    let from = sandbox.resolve_entry(source, FsAccess::ReadWrite)?;
+    let to = sandbox.resolve_entry(destination, FsAccess::ReadWrite)?;
+
+    if sandbox.is_root(&from) {
+        return Err(anyhow::anyhow!("Refusing to move a filesystem root: {}", source));
+    }
+    if tokio::fs::symlink_metadata(&to).await.is_ok() {
+        return Err(anyhow::anyhow!("Destination already exists: {}", destination));
+    }
+    tokio::fs::rename(&from, &to).await
+        .with_context(|| format!("Failed to move '{}' to '{}'", source, destination))?;
+    Ok((from, to))
+}
+
+/// Delete a file, symlink or empty directory.
+pub async fn delete(sandbox: &FsSandbox, path: &str) -> Result<PathBuf> {
+    let entry = sandbox.resolve_entry(path, FsAccess::ReadWrite)?;
+    if sandbox.is_root(&entry) {
+        return Err(anyhow::anyhow!("Refusing to delete a filesystem root: {}", path));
+    }
+
+    let metadata = tokio::fs::symlink_metadata(&entry).await
+        .with_context(|| format!("Failed to stat: {}", path))?;
+    if metadata.is_dir() {
+        tokio::fs::remove_dir(&entry).await
+            .with_context(|| format!("Failed to delete directory (it must be empty): {}", path))?;
+    } else {
+        tokio::fs::remove_file(&entry).await
+            .with_context(|| format!("Failed to delete file: {}", path))?;
+    }
+    Ok(entry)
+}
+
+fn search_base(path: &str) -> &str {
+    if path.is_empty() { "." } else { path }
+}
+
+fn entry_type(metadata: &Metadata) -> &'static str {
+    if metadata.is_symlink() {
+        "symlink"
+    } else if metadata.is_dir() {
+        "dir"
+    } else if metadata.is_file() {
+        "file"
+    } else {
+        "other"
+    }
+}
+
+/// Depth-first walk below `base`, calling `visit(relative, absolute, metadata)` for
+/// every entry until it returns `false`.
+///
+/// Symlinks are only visited when their target is readable in the sandbox, and
+/// symlinked directories are never descended into.
+fn walk(sandbox: &FsSandbox, base: &Path, visit: &mut impl FnMut(&Path, &Path, &Metadata) -> bool) {
+    let mut stack = vec![(base.to_path_buf(), 0)];
+    while let Some((dir, depth)) = stack.pop() {
+        let Ok(reader) = std::fs::read_dir(&dir) else { continue };
+        let mut entries: Vec<_> = reader.flatten().map(|entry| entry.path()).collect();
+        entries.sort();
+
+        for path in entries {
+            let Ok(mut metadata) = std::fs::symlink_metadata(&path) else { continue };
+            let mut target = path.clone();
+            if metadata.is_symlink() {
+                let Ok(resolved) = path.canonicalize() else { continue };
+                if !sandbox.permits(&resolved, FsAccess::ReadOnly) {
+                    continue;
+                }
+                let Ok(resolved_metadata) = std::fs::metadata(&resolved) else { continue };
+                metadata = resolved_metadata;
+                target = resolved;
+            } else if metadata.is_dir() && depth < MAX_DEPTH {
+                stack.push((path.clone(), depth + 1));
+            }
+
+            let relative = path.strip_prefix(base).unwrap_or(&path);
+            if !visit(relative, &target, &metadata) {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::SandboxFixture;
+
+    fn fixture(max_read_bytes: u64) -> SandboxFixture {
+        SandboxFixture::new(&[("", FsAccess::ReadWrite)], Some(max_read_bytes))
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn backups_replace_planted_symlinks() {
+        let fixture = fixture(1024);
+        let outside = std::env::temp_dir().join(format!("wasmforge-outside-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::write(&outside, "outside").unwrap();
+        std::fs::write(fixture.base.join("notes.txt"), "old").unwrap();
+        std::os::unix::fs::symlink(&outside, fixture.base.join("notes.txt.bak")).unwrap();
+
+        let options = WriteOptions { backup: true, ..WriteOptions::default() };
+        let outcome = write(&fixture.sandbox, &fixture.path("notes.txt"), b"new", &options).await.unwrap();
+        let backup = outcome.backup.unwrap();
+
+        assert_eq!(std::fs::read_to_string(&outside).unwrap(), "outside");
+        assert!(!std::fs::symlink_metadata(&backup).unwrap().file_type().is_symlink());
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), "old");
+        assert_eq!(std::fs::read_to_string(fixture.base.join("notes.txt")).unwrap(), "new");
+        let _ = std::fs::remove_file(&outside);
+    }
+
+    #[tokio::test]
+    async fn line_ranges_within_the_limit_are_read() {
+        let fixture = fixture(64);
+        std::fs::write(fixture.base.join("lines.txt"), "one\ntwo\nthree\nfour\n").unwrap();
+        let content = read(&fixture.sandbox, &fixture.path("lines.txt"), ReadRange::Lines { start: 2, end: Some(3) })
+            .await
+            .unwrap();
+        assert_eq!(content.data, b"two\nthree\n");
+    }
+
+    #[tokio::test]
+    async fn overlong_lines_hit_the_limit() {
+        let fixture = fixture(64);
+        std::fs::write(fixture.base.join("long.txt"), format!("{}\nshort\n", "x".repeat(1000))).unwrap();
+        for range in [ReadRange::Lines { start: 1, end: Some(1) }, ReadRange::Lines { start: 2, end: None }] {
+            let error = read(&fixture.sandbox, &fixture.path("long.txt"), range).await.unwrap_err();
+            assert!(error.to_string().contains("limit of 64 bytes"), "{:?}: {}", range, error);
+        }
+    }
+
+    #[tokio::test]
+    async fn skipped_lines_count_towards_the_limit() {
+        let fixture = fixture(64);
+        let text: String = (1..=100).map(|n| format!("{}\n", n)).collect();
+        std::fs::write(fixture.base.join("many.txt"), text).unwrap();
+        let error = read(&fixture.sandbox, &fixture.path("many.txt"), ReadRange::Lines { start: 90, end: Some(90) })
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("limit of 64 bytes"), "{}", error);
+    }
+
+    /// A read-only and a read-write root, plus a directory outside both.
+    fn roots() -> SandboxFixture {
+        let fixture = SandboxFixture::new(&[("ro", FsAccess::ReadOnly), ("rw", FsAccess::ReadWrite)], None);
+        fixture.write("ro/kept.txt", "kept");
+        fixture.write("rw/a.txt", "hello\nworld\n");
+        fixture.write("rw/sub/b.md", "hello there\n");
+        fixture.write("outside/secret.txt", "secret");
+        fixture
+    }
+
+    #[tokio::test]
+    async fn listings_and_stats_stay_inside_the_roots() {
+        let fixture = roots();
+        let listing = list_dir(&fixture.sandbox, &fixture.path("rw")).await.unwrap();
+        let entries: Vec<(&str, &str)> = listing["entries"].as_array().unwrap().iter()
+            .map(|entry| (entry["name"].as_str().unwrap(), entry["type"].as_str().unwrap()))
+            .collect();
+        assert_eq!(entries, [("a.txt", "file"), ("sub", "dir")]);
+
+        let info = stat(&fixture.sandbox, &fixture.path("rw/a.txt")).await.unwrap();
+        assert_eq!((info["type"].as_str(), info["size"].as_u64()), (Some("file"), Some(12)));
+
+        assert!(list_dir(&fixture.sandbox, &fixture.path("outside")).await.is_err());
+        assert!(stat(&fixture.sandbox, &fixture.path("outside/secret.txt")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn searches_report_paths_below_their_base() {
+        let fixture = roots();
+        let found = glob(&fixture.sandbox, "sub/*.md", &fixture.path("rw")).await.unwrap();
+        assert_eq!(found["matches"], json!([{"path": "sub/b.md", "type": "file"}]));
+        let found = glob(&fixture.sandbox, "*.md", &fixture.path("rw")).await.unwrap();
+        assert_eq!(found["matches"], json!([]));
+
+        let hits = grep(&fixture.sandbox, "^hello", &fixture.path("rw")).await.unwrap();
+        assert_eq!(hits["filesSearched"], 2);
+        assert_eq!(hits["matches"].as_array().unwrap().len(), 2);
+        let hits = grep(&fixture.sandbox, "world", &fixture.path("rw/a.txt")).await.unwrap();
+        assert_eq!(hits["matches"][0]["line"], 2);
+
+        assert!(grep(&fixture.sandbox, "secret", &fixture.path("outside")).await.is_err());
+        assert!(grep(&fixture.sandbox, "(", &fixture.path("rw")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn appends_and_new_directories_need_a_read_write_root() {
+        let fixture = roots();
+        append(&fixture.sandbox, &fixture.path("rw/log.txt"), "one\n").await.unwrap();
+        append(&fixture.sandbox, &fixture.path("rw/log.txt"), "two\n").await.unwrap();
+        assert_eq!(std::fs::read_to_string(fixture.base.join("rw/log.txt")).unwrap(), "one\ntwo\n");
+        assert!(append(&fixture.sandbox, &fixture.path("ro/kept.txt"), "more").await.is_err());
+        assert_eq!(std::fs::read_to_string(fixture.base.join("ro/kept.txt")).unwrap(), "kept");
+
+        make_dir(&fixture.sandbox, &fixture.path("rw/x/y")).await.unwrap();
+        assert!(fixture.base.join("rw/x/y").is_dir());
+        assert!(make_dir(&fixture.sandbox, &fixture.path("ro/new")).await.is_err());
+        assert!(make_dir(&fixture.sandbox, &fixture.path("outside/new")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn roots_cannot_be_deleted_or_moved() {
+        let fixture = roots();
+        let error = delete(&fixture.sandbox, &fixture.path("rw")).await.unwrap_err();
+        assert!(error.to_string().contains("filesystem root"), "{}", error);
+        let error = move_entry(&fixture.sandbox, &fixture.path("rw"), &fixture.path("rw/moved")).await.unwrap_err();
+        assert!(error.to_string().contains("filesystem root"), "{}", error);
+        assert!(delete(&fixture.sandbox, &fixture.path("ro")).await.is_err());
+        assert!(fixture.base.join("rw/a.txt").exists() && fixture.base.join("ro/kept.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn moves_stay_within_read_write_roots() {
+        let fixture = roots();
+        for destination in ["ro/a.txt", "outside/a.txt", "rw/sub/b.md"] {
+            assert!(
+                move_entry(&fixture.sandbox, &fixture.path("rw/a.txt"), &fixture.path(destination)).await.is_err(),
+                "moved to {}", destination
+            );
+        }
+        assert!(move_entry(&fixture.sandbox, &fixture.path("ro/kept.txt"), &fixture.path("rw/kept.txt")).await.is_err());
+        assert_eq!(std::fs::read_to_string(fixture.base.join("rw/a.txt")).unwrap(), "hello\nworld\n");
+
+        move_entry(&fixture.sandbox, &fixture.path("rw/a.txt"), &fixture.path("rw/sub/a.txt")).await.unwrap();
+        assert!(!fixture.base.join("rw/a.txt").exists());
+        assert!(fixture.base.join("rw/sub/a.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn deletes_remove_files_and_empty_directories() {
+        let fixture = roots();
+        assert!(delete(&fixture.sandbox, &fixture.path("rw/sub")).await.is_err());
+        delete(&fixture.sandbox, &fixture.path("rw/sub/b.md")).await.unwrap();
+        delete(&fixture.sandbox, &fixture.path("rw/sub")).await.unwrap();
+        assert!(!fixture.base.join("rw/sub").exists());
+        assert!(delete(&fixture.sandbox, &fixture.path("ro/kept.txt")).await.is_err());
+        assert!(delete(&fixture.sandbox, &fixture.path("outside/secret.txt")).await.is_err());
+        assert!(fixture.base.join("outside/secret.txt").exists());
+    }
+}
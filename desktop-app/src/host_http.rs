@@ -0,0 +1,416 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use wasmtime::{Caller, Linker};
+
+use crate::config::HttpConfig;
+use crate::wasm_executor::HostState;
+
+/// Import module name for host functions offered to guests.
+pub const IMPORT_MODULE: &str = "wasmforge";
+
+/// Guest request, read as JSON from guest memory.
+#[derive(Debug, Deserialize)]
+struct HttpRequest {
+    #[serde(default = "default_method")]
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    body: Option<String>,
+    body_base64: Option<String>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+/// Register `wasmforge::http_request` and `wasmforge::http_response_read`.
+///
+/// `http_request(req_ptr, req_len) -> i32` performs the request described by the JSON
+/// document in the buffer and returns the length of the JSON response, or `-1` if the
+/// buffer is outside guest memory. The response is `{"status", "headers", "body"}`
+/// (`body_base64` for binary bodies) or `{"error", "kind"}` where kind is `invalid`,
+/// `denied` or `failed`.
+///
+/// `http_response_read(ptr, cap) -> i32` copies that response into guest memory and
+/// returns its length; if `cap` is too small nothing is copied and the negated
+/// required length is returned so the guest can retry with a larger buffer.
+pub fn add_to_linker(linker: &mut Linker<HostState>) -> Result<()> {
+    linker.func_wrap(IMPORT_MODULE, "http_request", |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> i32 {
+        let Some(raw) = read_guest(&mut caller, ptr, len) else {
+            return -1;
+        };
+        let response = match caller.data().http.clone() {
+            Some(policy) => perform(&policy, &raw),
+            None => error_response("denied", "HTTP is not granted to this module"),
+        };
+        let bytes = serde_json::to_vec(&response).unwrap_or_default();
+        let length = bytes.len() as i32;
+        caller.data_mut().http_response = bytes;
+        length
+    })?;
+
+    linker.func_wrap(IMPORT_MODULE, "http_response_read", |mut caller: Caller<'_, HostState>, ptr: i32, cap: i32| -> i32 {
+        let length = caller.data().http_response.len();
+        if (cap.max(0) as usize) < length {
+            return -(length as i32);
+        }
+        let response = std::mem::take(&mut caller.data_mut().http_response);
+        let Some(memory) = caller.get_export("memory").and_then(|e| e.into_memory()) else {
+            return -1;
+        };
+        match memory.write(&mut caller, ptr as u32 as usize, &response) {
+            Ok(()) => length as i32,
+            Err(_) => -1,
+        }
+    })?;
+
+    Ok(())
+}
+
+fn read_guest(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> Option<Vec<u8>> {
+    let memory = caller.get_export("memory")?.into_memory()?;
+    let mut buffer = vec![0u8; usize::try_from(len).ok()?];
+    memory.read(&caller, ptr as u32 as usize, &mut buffer).ok()?;
+    Some(buffer)
+}
+
+fn error_response(kind: &str, message: impl Into<String>) -> Value {
+    json!({ "error": message.into(), "kind": kind })
+}
+
+fn perform(policy: &HttpConfig, raw: &[u8]) -> Value {
+    let request: HttpRequest = match serde_json::from_slice(raw) {
+        Ok(request) => request,
+        Err(e) => return error_response("invalid", format!("Malformed request: {}", e)),
+    };
+    let url = match reqwest::Url::parse(&request.url) {
+        Ok(url) => url,
+        Err(e) => return error_response("invalid", format!("Invalid URL '{}': {}", request.url, e)),
+    };
+    if let Err(reason) = check_policy(policy, &url) {
+        return error_response("denied", reason);
+    }
+
+    // Host functions are synchronous; park this worker while the request runs
+    let Ok(handle) = tokio::runtime::Handle::try_current() else {
+        return error_response("failed", "No async runtime available for HTTP");
+    };
+    match tokio::task::block_in_place(|| handle.block_on(send(policy, request, url))) {
+        Ok(response) => response,
+        Err(e) => error_response("failed", format!("{:#}", e)),
+    }
+}
+
+/// Check a URL against the module's egress allow-list.
+pub fn check_policy(policy: &HttpConfig, url: &reqwest::Url) -> Result<(), String> {
+    if !policy.allowed_schemes().iter().any(|s| s.eq_ignore_ascii_case(url.scheme())) {
+        return Err(format!("Scheme '{}' is not allowed", url.scheme()));
+    }
+    let host = url.host_str().ok_or_else(|| "URL has no host".to_string())?;
+    if !policy.allows_host(host) {
+        return Err(format!("Host '{}' is not allowed", host));
+    }
+    let port = url.port_or_known_default().ok_or_else(|| "URL has no port".to_string())?;
+    if !policy.allowed_ports().contains(&port) {
+        return Err(format!("Port {} is not allowed", port));
+    }
+    Ok(())
+}
+
+/// Client for policy-checked egress, with the policy's timeout.
+///
+/// Redirects are returned to the caller rather than followed, so every hop is policy-checked.
+pub fn client(policy: &HttpConfig) -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .timeout(policy.timeout())
+        .redirect(reqwest::redirect::Policy::none())
+        .user_agent("WasmForge/0.1.0")
+        .build()?)
+}
+
+/// Read a response body, failing once it exceeds `max` bytes.
+pub async fn read_body(response: &mut reqwest::Response, max: usize) -> Result<Vec<u8>> {
+    if response.content_length().is_some_and(|length| length > max as u64) {
+        return Err(anyhow::anyhow!("Response exceeds the {} byte limit", max));
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.context("Failed to read response body")? {
+        if body.len() + chunk.len() > max {
+            return Err(anyhow::anyhow!("Response exceeds the {} byte limit", max));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+async fn send(policy: &HttpConfig, request: HttpRequest, url: reqwest::Url) -> Result<Value> {
+    let client = client(policy)?;
+
+    let method = reqwest::Method::from_bytes(request.method.to_ascii_uppercase().as_bytes())
+        .with_context(|| format!("Invalid HTTP method: {}", request.method))?;
+    let mut builder = client.request(method, url);
+    for (name, value) in &request.headers {
+        builder = builder.header(name, value);
+    }
+    if let Some(body) = request.body {
+        builder = builder.body(body);
+    } else if let Some(encoded) = request.body_base64 {
+        let body = base64::engine::general_purpose::STANDARD.decode(encoded)
+            .context("body_base64 is not valid base64")?;
+        builder = builder.body(body);
+    }
+
+    let mut response = builder.send().await.context("HTTP request failed")?;
+
+    let status = response.status().as_u16();
+    let mut headers = Map::new();
+    for (name, value) in response.headers() {
+        headers.insert(name.to_string(), json!(String::from_utf8_lossy(value.as_bytes())));
+    }
+
+    let body = read_body(&mut response, policy.max_response_bytes()).await?;
+
+    let mut result = json!({ "status": status, "headers": headers });
+    match String::from_utf8(body) {
+        Ok(text) => result["body"] = json!(text),
+        Err(e) => result["body_base64"] = json!(base64::engine::general_purpose::STANDARD.encode(e.into_bytes())),
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wasm_executor::WasmExecutor;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Guest that forwards its (ptr, len) argument to `http_request` and publishes the
+    /// response through the result buffer.
+    const FETCH_WAT: &str = r#"
+        (module
+          (import "wasmforge" "http_request" (func $request (param i32 i32) (result i32)))
+          (import "wasmforge" "http_response_read" (func $read (param i32 i32) (result i32)))
+          (memory (export "memory") 2)
+          (global $result_len (mut i32) (i32.const 0))
+          (global $next (mut i32) (i32.const 65536))
+          (func (export "wasmforge_alloc") (param $len i32) (result i32)
+            (global.get $next)
+            (global.set $next (i32.add (global.get $next) (local.get $len))))
+          (data (i32.const 4096) "application/json\00")
+          (func (export "fetch") (param $ptr i32) (param $len i32) (result i32)
+            (local $n i32)
+            (local.set $n (call $request (local.get $ptr) (local.get $len)))
+            (if (i32.lt_s (local.get $n) (i32.const 0)) (then (return (local.get $n))))
+            (global.set $result_len
+              (i32.add (i32.const 17) (call $read (i32.const 4113) (i32.const 60000))))
+            (i32.const 0))
+          (func (export "wasmforge_result_ptr") (result i32) (i32.const 4096))
+          (func (export "wasmforge_result_len") (result i32) (global.get $result_len)))
+    "#;
+
+    fn fetch_module() -> Vec<u8> {
+        wat::parse_str(FETCH_WAT).unwrap()
+    }
+
+    /// Minimal HTTP server answering every request with `body_size` bytes of the request line.
+    async fn stub_server(body_size: usize) -> u16 {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buffer = vec![0u8; 8192];
+                let n = stream.read(&mut buffer).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buffer[..n]).to_string();
+                let request_line = request.lines().next().unwrap_or_default().to_string();
+                let body = if body_size == 0 { request_line } else { "x".repeat(body_size) };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nX-Stub: yes\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        port
+    }
+
+    fn policy(port: u16) -> HttpConfig {
+        HttpConfig {
+            allowed_hosts: vec!["127.0.0.1".to_string()],
+            allowed_ports: Some(vec![port]),
+            allowed_schemes: Some(vec!["http".to_string()]),
+            max_response_bytes: Some(1000),
+            timeout_secs: Some(5),
+        }
+    }
+
+    fn fetch(policy: HttpConfig, request: Value) -> Value {
+        let mut executor = WasmExecutor::new().unwrap();
+        executor.load_module_bytes("stub-client", &fetch_module(), Some(policy)).unwrap();
+        let status = executor
+            .call_function_ptr_len_to_i32("stub-client", "fetch", request.to_string().as_bytes())
+            .unwrap();
+        assert_eq!(status, 0);
+        let output = executor.take_guest_output("stub-client").unwrap().unwrap();
+        serde_json::from_slice(&output.data).unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn allowed_request_reaches_server() {
+        let port = stub_server(0).await;
+        let response = fetch(policy(port), json!({
+            "method": "POST",
+            "url": format!("http://127.0.0.1:{}/echo", port),
+            "headers": {"Content-Type": "text/plain"},
+            "body": "ping"
+        }));
+        assert_eq!(response["status"], 200);
+        assert_eq!(response["body"], "POST /echo HTTP/1.1");
+        assert_eq!(response["headers"]["x-stub"], "yes");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn disallowed_host_port_and_scheme_are_denied() {
+        let port = stub_server(0).await;
+        for url in [
+            format!("http://localhost:{}/", port),
+            format!("http://127.0.0.1:{}/", port + 1),
+            format!("https://127.0.0.1:{}/", port),
+        ] {
+            let response = fetch(policy(port), json!({ "url": url }));
+            assert_eq!(response["kind"], "denied", "{} should be denied", url);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn oversized_response_is_rejected() {
+        let port = stub_server(5000).await;
+        let response = fetch(policy(port), json!({ "url": format!("http://127.0.0.1:{}/", port) }));
+        assert_eq!(response["kind"], "failed");
+        assert!(response["error"].as_str().unwrap().contains("1000 byte limit"));
+    }
+
+    /// Module whose `accept` validator approves every URL, for the host-backed http kinds.
+    fn accepting_executor(policy: Option<HttpConfig>) -> WasmExecutor {
+        let wasm = wat::parse_str(r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "wasmforge_alloc") (param i32) (result i32) (i32.const 1024))
+              (func (export "accept") (param i32 i32) (result i32) (i32.const 1)))
+        "#).unwrap();
+        let mut executor = WasmExecutor::new().unwrap();
+        executor.load_module_bytes("accepting", &wasm, policy).unwrap();
+        executor
+    }
+
+    /// Server answering every request with a redirect to `location`.
+    async fn redirect_server(location: String) -> u16 {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buffer = vec![0u8; 8192];
+                let _ = stream.read(&mut buffer).await;
+                let response = format!(
+                    "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    location
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        port
+    }
+
+    fn get_spec(url: String) -> crate::wasm_executor::HttpRequestSpec {
+        crate::wasm_executor::HttpRequestSpec {
+            method: "GET".to_string(),
+            url,
+            headers: Default::default(),
+            body: None,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn http_kinds_need_a_grant() {
+        let port = stub_server(0).await;
+        let url = format!("http://127.0.0.1:{}/", port);
+        let mut executor = accepting_executor(None);
+        let error = executor.http_get_with_validation("accepting", "accept", &url).await.unwrap_err();
+        assert!(error.to_string().contains("[modules.http]"), "{}", error);
+        let error = executor.http_request_with_validation("accepting", "accept", &get_spec(url.clone())).await.unwrap_err();
+        assert!(error.to_string().contains("[modules.http]"), "{}", error);
+        let error = executor.fetch_url_with_validation("accepting", "accept", &url).await.unwrap_err();
+        assert!(error.to_string().contains("[modules.http]"), "{}", error);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn http_kinds_follow_the_egress_policy() {
+        let port = stub_server(0).await;
+        let mut executor = accepting_executor(Some(policy(port)));
+        let content = executor
+            .http_get_with_validation("accepting", "accept", &format!("http://127.0.0.1:{}/ok", port))
+            .await
+            .unwrap();
+        assert_eq!(content.data, b"GET /ok HTTP/1.1");
+
+        for url in [format!("http://localhost:{}/", port), format!("https://127.0.0.1:{}/", port)] {
+            let error = executor.http_get_with_validation("accepting", "accept", &url).await.unwrap_err();
+            assert!(error.to_string().contains("is not allowed"), "{}", error);
+            let error = executor.http_request_with_validation("accepting", "accept", &get_spec(url)).await.unwrap_err();
+            assert!(error.to_string().contains("is not allowed"), "{}", error);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn http_kinds_cap_the_response_size() {
+        let port = stub_server(5000).await;
+        let url = format!("http://127.0.0.1:{}/", port);
+        let mut executor = accepting_executor(Some(policy(port)));
+        let error = executor.http_get_with_validation("accepting", "accept", &url).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("1000 byte limit"), "{:#}", error);
+        let error = executor.http_request_with_validation("accepting", "accept", &get_spec(url)).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("1000 byte limit"), "{:#}", error);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn http_kinds_do_not_follow_redirects() {
+        let target = stub_server(0).await;
+        let port = redirect_server(format!("http://127.0.0.1:{}/target", target)).await;
+        let mut policy = policy(port);
+        policy.allowed_ports = Some(vec![port, target]);
+        let mut executor = accepting_executor(Some(policy));
+        let url = format!("http://127.0.0.1:{}/", port);
+        let response = executor.http_request_with_validation("accepting", "accept", &get_spec(url.clone())).await.unwrap();
+        assert_eq!(response.status, 302);
+        let error = executor.http_get_with_validation("accepting", "accept", &url).await.unwrap_err();
+        assert!(error.to_string().contains("302"), "{}", error);
+    }
+
+    #[test]
+    fn manifest_must_declare_http_to_import_it() {
+        let undeclared = FETCH_WAT.replacen(
+            "(module",
+            r#"(module (@custom "wasmforge" "{\"tools\": [{\"name\": \"fetch\", \"function\": \"fetch\"}]}")"#,
+            1,
+        );
+        let mut executor = WasmExecutor::new().unwrap();
+        let error = executor
+            .load_module_bytes("stub-client", &wat::parse_str(&undeclared).unwrap(), Some(policy(80)))
+            .unwrap_err();
+        assert!(format!("{:#}", error).contains("declares no 'http' capability"), "{:#}", error);
+
+        let declared = undeclared.replace(r#"\"function\": \"fetch\""#, r#"\"function\": \"fetch\", \"capabilities\": [\"http\"]"#);
+        executor.load_module_bytes("stub-client", &wat::parse_str(&declared).unwrap(), Some(policy(80))).unwrap();
+    }
+
+    #[test]
+    fn module_without_grant_cannot_import_http() {
+        let mut executor = WasmExecutor::new().unwrap();
+        let error = executor.load_module_bytes("stub-client", &fetch_module(), None).unwrap_err();
+        assert!(format!("{:#}", error).contains("[modules.http]"));
+    }
+}
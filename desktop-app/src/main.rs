@@ -1,19 +1,47 @@
 use wasmtime::*;
 use serde::{Deserialize, Serialize};
-use std::io::{self, BufRead, BufReader};
-use clap::Parser;
-use tokio::net::{TcpListener, TcpStream};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader as AsyncBufReader};
-
+use std::sync::Arc;
+use clap::{Parser, Subcommand};
+use tokio::net::TcpListener;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader as AsyncBufReader};
+use tokio::sync::{mpsc, Mutex};
+
+mod audit;
+mod auth;
 mod config;
+mod manifest;
+mod schema_validator;
 mod module_manager;
 mod wasm_executor;
+mod host_http;
+mod fs_sandbox;
+mod fs_tools;
+mod child_sandbox;
+mod confirm;
+mod policy;
+mod redact;
+mod session;
+mod shell;
 mod tool_discovery;
-
-use config::Config;
+mod tool_kinds;
+mod tool_result;
+mod tls;
+#[cfg(test)]
+mod test_support;
+
+use audit::{AuditLog, CallRecord, Outcome};
+use auth::{Authenticator, Principal};
+use config::{Config, ConfirmMode};
+use confirm::{Confirmations, Decision};
 use module_manager::ModuleManager;
 use wasm_executor::WasmExecutor;
-use tool_discovery::ToolDiscovery;
+use fs_sandbox::FsSandbox;
+use redact::Redactor;
+use session::Session;
+use tool_discovery::{DiscoveredTool, ToolDiscovery};
+use tool_kinds::ToolContext;
+use tool_result::{InvalidParams, ToolContent, ToolResult};
+use tls::TlsReloader;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct MCPRequest {
@@ -27,7 +55,9 @@ struct MCPRequest {
 struct MCPResponse {
     jsonrpc: String,
     id: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     error: Option<serde_json::Value>,
 }
 
@@ -36,6 +66,11 @@ struct WasmForgeServer {
     tool_discovery: ToolDiscovery,
     module_manager: ModuleManager,
     config: Config,
+    confirmations: Confirmations,
+    audit: Option<AuditLog>,
+    redactor: Arc<Redactor>,
+    /// Set when `[auth]` is configured; TCP clients must then present a token
+    auth: Option<Authenticator>,
 }
 
 impl WasmForgeServer {
@@ -55,22 +90,124 @@ impl WasmForgeServer {
         tool_discovery.discover_tools_from_executor(&executor)?;
         tool_discovery.print_discovered_tools();
 
+        // Tools held for confirmation accept the token of an approved call
+        for tool in tool_discovery.tools_mut() {
+            if policy::EffectivePolicy::resolve(&config, tool).confirm.value != ConfirmMode::Never {
+                confirm::advertise_token(&mut tool.schema);
+            }
+        }
+
+        let redactor = Arc::new(Redactor::new(config.redaction.as_ref())?);
+        let auth = config.auth.as_ref().map(Authenticator::load).transpose()?;
+        let audit = config.audit.as_ref().map(AuditLog::new);
+        if let Some(audit) = &audit {
+            eprintln!("📝 Auditing tool calls to {}", audit.path().display());
+        }
+
         Ok(Self {
             executor,
             tool_discovery,
             module_manager,
             config,
+            confirmations: Confirmations::default(),
+            audit,
+            redactor,
+            auth,
         })
     }
 }
 
-async fn handle_mcp_message(request: MCPRequest, server: &mut WasmForgeServer) -> MCPResponse {
+/// Protocol versions this server can speak, newest first.
+const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// How long a TCP client may take to complete the TLS handshake.
+const TLS_HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// A JSON-RPC error, used only for protocol-level failures.
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<serde_json::Value>,
+}
+
+impl RpcError {
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self { code: -32602, message: message.into(), data: None }
+    }
+
+    fn unauthorized(message: impl Into<String>) -> Self {
+        Self { code: -32001, message: message.into(), data: None }
+    }
+
+    fn from_invalid_params(invalid: InvalidParams) -> Self {
+        Self {
+            code: -32602,
+            message: invalid.to_string(),
+            data: Some(serde_json::json!({ "violations": invalid.0 })),
+        }
+    }
+
+    fn redact(&mut self, redactor: &Redactor) {
+        self.message = redactor.text(&self.message).into_owned();
+        self.data = self.data.as_ref().map(|data| redactor.value(data));
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let mut error = serde_json::json!({
+            "code": self.code,
+            "message": self.message
+        });
+        if let Some(data) = &self.data {
+            error["data"] = data.clone();
+        }
+        error
+    }
+}
+
+impl MCPResponse {
+    fn success(id: Option<serde_json::Value>, result: serde_json::Value) -> Self {
+        Self { jsonrpc: "2.0".to_string(), id, result: Some(result), error: None }
+    }
+
+    fn failure(id: Option<serde_json::Value>, error: RpcError) -> Self {
+        Self { jsonrpc: "2.0".to_string(), id, result: None, error: Some(error.to_json()) }
+    }
+}
+
+/// Answer one request. The server is locked per step rather than for the whole request,
+/// so a call waiting on its user does not hold up other connections.
+async fn handle_mcp_message(request: MCPRequest, server: &Mutex<WasmForgeServer>, session: &Session) -> MCPResponse {
+    // Unauthenticated connections may only initialize, presenting their token
+    let principal = match session.principal() {
+        Some(principal) => principal,
+        None if request.method == "initialize" => match authenticate(request.params.as_ref(), &*server.lock().await) {
+            Ok(principal) => {
+                session.set_principal(principal.clone());
+                principal
+            }
+            Err(e) => return MCPResponse::failure(request.id, e),
+        },
+        None => return MCPResponse::failure(request.id, RpcError::unauthorized("Unauthorized: initialize with a bearer token first")),
+    };
+
     match request.method.as_str() {
-        "initialize" => MCPResponse {
-            jsonrpc: "2.0".to_string(),
-            id: request.id,
-            result: Some(serde_json::json!({
-                "protocolVersion": "2024-11-05",
+        "initialize" => {
+            // Client info is audited; elicitation is only used when the client declares it
+            if let Some(params) = &request.params {
+                session.set_client(params["clientInfo"].clone(), params["capabilities"].clone());
+            }
+
+            // Answer with the client's version when we support it, otherwise our latest
+            let requested = request.params.as_ref()
+                .and_then(|p| p.get("protocolVersion"))
+                .and_then(|v| v.as_str());
+            let protocol_version = requested
+                .filter(|v| SUPPORTED_PROTOCOL_VERSIONS.contains(v))
+                .unwrap_or(SUPPORTED_PROTOCOL_VERSIONS[0]);
+
+            MCPResponse::success(request.id, serde_json::json!({
+                "protocolVersion": protocol_version,
                 "capabilities": {
                     "tools": {}
                 },
@@ -78,313 +215,158 @@ async fn handle_mcp_message(request: MCPRequest, server: &mut WasmForgeServer) -
                     "name": "wasmforge",
                     "version": "0.1.0"
                 }
-            })),
-            error: None,
+            }))
         },
-        "tools/list" => MCPResponse {
-            jsonrpc: "2.0".to_string(),
-            id: request.id,
-            result: Some(server.tool_discovery.get_mcp_tools_schema()),
-            error: None,
+        "tools/list" => {
+            let cursor = request.params.as_ref()
+                .and_then(|p| p.get("cursor"))
+                .and_then(|v| v.as_str());
+            let server = server.lock().await;
+            let page_size = server.config.server.tools_page_size();
+            match server.tool_discovery.get_mcp_tools_schema(cursor, page_size, |tool| principal.allows(tool)) {
+                Ok(result) => MCPResponse::success(request.id, result),
+                Err(e) => MCPResponse::failure(request.id, RpcError::invalid_params(e.to_string())),
+            }
         },
         "tools/call" => {
-            match handle_tool_call(request.clone(), server).await {
-                Ok(response) => response,
-                Err(e) => MCPResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id: request.id,
-                    result: None,
-                    error: Some(serde_json::json!({
-                        "code": -32000,
-                        "message": e.to_string()
-                    })),
-                }
+            let started = std::time::Instant::now();
+            let mut call = CallRecord::default();
+            let mut result = handle_tool_call(request.params, server, session, &principal, &mut call).await;
+            let server = server.lock().await;
+            // Secrets never reach the client, whether in a result or an error
+            match &mut result {
+                Ok(result) => server.redactor.tool_result(result),
+                Err(e) => e.redact(&server.redactor),
+            }
+            if let Some(audit) = &server.audit {
+                let outcome = match &result {
+                    Ok(result) if result.is_error => Outcome::ToolError(result.first_text().unwrap_or_default()),
+                    Ok(_) => Outcome::Success,
+                    Err(e) => Outcome::Rejected(&e.message),
+                };
+                audit.record(session, &call, outcome, started.elapsed(), &server.redactor);
+            }
+            match result {
+                Ok(result) => MCPResponse::success(request.id, result.to_json()),
+                Err(e) => MCPResponse::failure(request.id, e),
             }
         },
-        _ => MCPResponse {
-            jsonrpc: "2.0".to_string(),
-            id: request.id,
-            result: None,
-            error: Some(serde_json::json!({
-                "code": -32601,
-                "message": "Method not found"
-            })),
-        },
+        _ => MCPResponse::failure(request.id, RpcError {
+            code: -32601,
+            message: "Method not found".to_string(),
+            data: None,
+        }),
     }
 }
 
-async fn handle_tool_call(request: MCPRequest, server: &mut WasmForgeServer) -> Result<MCPResponse, anyhow::Error> {
-    let params = request.params.ok_or_else(|| anyhow::anyhow!("Missing parameters"))?;
+async fn handle_tool_call(
+    params: Option<serde_json::Value>,
+    server: &Mutex<WasmForgeServer>,
+    session: &Session,
+    principal: &Principal,
+    call: &mut CallRecord,
+) -> Result<ToolResult, RpcError> {
+    let mut guard = server.lock().await;
+    let params = params.ok_or_else(|| RpcError::invalid_params("Missing parameters"))?;
     let tool_name = params.get("name")
         .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Missing tool name"))?;
-    let arguments = params.get("arguments")
-        .ok_or_else(|| anyhow::anyhow!("Missing arguments"))?;
-
-    // Find the tool in our discovery system
-    let tool = server.tool_discovery.find_tool_by_name(tool_name)
-        .ok_or_else(|| anyhow::anyhow!("Unknown tool: {}", tool_name))?;
-
-    // Call the appropriate function based on the tool's pattern
-    let result_text = match tool.pattern.as_str() {
-        "i32_i32_to_i32" => {
-            let a = arguments.get("a")
-                .and_then(|v| v.as_i64())
-                .ok_or_else(|| anyhow::anyhow!("Missing or invalid parameter 'a'"))? as i32;
-            let b = arguments.get("b")
-                .and_then(|v| v.as_i64())
-                .ok_or_else(|| anyhow::anyhow!("Missing or invalid parameter 'b'"))? as i32;
-
-            let result = server.executor.call_function_i32_i32_to_i32(
-                &tool.module_name,
-                &tool.function_name,
-                a,
-                b,
-            )?;
-
-            format!("WASM calculation result: {} (from {}::{})", result, tool.module_name, tool.function_name)
-        }
-        "ptr_len_to_i32" => {
-            // Handle different async operation patterns
-            if tool.function_name == "validate_url" {
-                let url = arguments.get("url")
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow::anyhow!("Missing or invalid parameter 'url'"))?;
-
-                let result = server.executor.call_function_ptr_len_to_i32(
-                    &tool.module_name,
-                    &tool.function_name,
-                    url.as_bytes(),
-                )?;
-
-                format!("URL validation result: {} (1=valid, 0=invalid)", result)
-            } else if tool.function_name == "prepare_http_get" {
-                // New HTTP GET async operation
-                let url = arguments.get("url")
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow::anyhow!("Missing URL parameter"))?;
-
-                let content = server.executor.http_get_with_validation(&tool.module_name, url).await?;
-                
-                format!("HTTP GET successful!\nURL: {}\nContent length: {} bytes\n\nContent preview (first 500 chars):\n{}", 
-                    url,
-                    content.len(),
-                    if content.len() > 500 { 
-                        format!("{}...", &content[..500]) 
-                    } else { 
-                        content 
-                    }
-                )
-            } else if tool.function_name == "prepare_file_read" {
-                // New file reading async operation
-                let file_path = arguments.get("path")
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow::anyhow!("Missing path parameter"))?;
-
-                let content = server.executor.read_file_with_validation(&tool.module_name, file_path).await?;
-                
-                format!("File read successful!\nPath: {}\nContent length: {} bytes\n\nContent:\n{}", 
-                    file_path,
-                    content.len(),
-                    content
-                )
-            } else if tool.function_name == "prepare_file_write" {
-                // New file writing async operation
-                let file_path = arguments.get("path")
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow::anyhow!("Missing path parameter"))?;
-                
-                let content = arguments.get("content")
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow::anyhow!("Missing content parameter"))?;
-
-                let result = server.executor.write_file_with_validation(&tool.module_name, file_path, content).await?;
-                
-                format!("File write successful!\nPath: {}\nContent length: {} bytes\nResult: {}", 
-                    file_path,
-                    content.len(),
-                    result
-                )
-            } else if tool.function_name == "prepare_shell_exec" {
-                // Shell execution with dual validation and config-driven allow-list
-                let cmd = arguments.get("command")
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow::anyhow!("Missing command parameter"))?;
-
-                // Resolve allowed commands: tool security -> module metadata -> defaults
-                let module_config_opt = server.config.find_module(&tool.module_name);
-                let mut allowed: Vec<String> = vec![
-                    "echo".to_string(),
-                    "cat".to_string(),
-                    "ls".to_string(),
-                    "wc".to_string(),
-                    "uname".to_string(),
-                ];
-
-                if let Some(module_config) = module_config_opt {
-                    // Prefer structured tool security config
-                    if let Some(tools) = &module_config.tools {
-                        if let Some(tool_cfg) = tools.iter().find(|t| t.function_name == "prepare_shell_exec") {
-                            if let Some(sec) = &tool_cfg.security {
-                                if let Some(list) = &sec.allowed_commands {
-                                    if !list.is_empty() { allowed = list.clone(); }
-                                }
-                            }
-                        }
-                    }
-
-                    // Fallback: metadata CSV
-                    if let Some(meta) = &module_config.metadata {
-                        if let Some(csv) = meta.get("allowed_commands_csv") {
-                            let parsed: Vec<String> = csv.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
-                            if !parsed.is_empty() { allowed = parsed; }
-                        }
-                    }
-                }
-
-                let result = server.executor.execute_shell_with_validation(&tool.module_name, cmd, &allowed).await?;
-                result
-            } else if tool.name == "fetch" {
-                // Legacy fetch tool for backward compatibility
-                let url = arguments.get("url")
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow::anyhow!("Missing URL parameter"))?;
-
-                let content = server.executor.fetch_url_with_validation(&tool.module_name, url).await?;
-                
-                format!("URL: {}\n\nContent (first 500 chars):\n{}", 
-                    url, 
-                    if content.len() > 500 { 
-                        format!("{}...", &content[..500]) 
-                    } else { 
-                        content 
-                    }
-                )
-            } else if tool.function_name == "prepare_recommend_mcps" {
-                let task = arguments.get("task")
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow::anyhow!("Missing task parameter"))?;
-
-                // Validate via WASM first
-                let ok = server.executor.call_function_ptr_len_to_i32(
-                    &tool.module_name,
-                    &tool.function_name,
-                    task.as_bytes(),
-                )? == 1;
-                if !ok { return Err(anyhow::anyhow!("Task rejected by WASM validation")); }
-
-                // Build recommendations from discovered tools
-                let query = task.to_lowercase();
-                let tools = server.tool_discovery.get_all_tools();
-
-                let mut categories: Vec<serde_json::Value> = Vec::new();
-
-                // Helpers to collect methods by function name
-                let has_fn = |fname: &str| tools.values().any(|t| t.function_name == fname);
-                let collect_methods = |fnames: &[&str]| -> Vec<serde_json::Value> {
-                    let mut v = Vec::new();
-                    for t in tools.values() {
-                        if fnames.contains(&t.function_name.as_str()) {
-                            v.push(serde_json::json!({
-                                "name": t.name,
-                                "inputSchema": t.schema
-                            }));
-                        }
-                    }
-                    v
-                };
-
-                // Scoring keywords
-                let contains_any = |words: &[&str]| words.iter().any(|w| query.contains(w));
-
-                // Web browser
-                if has_fn("prepare_http_get") && contains_any(&["download","fetch","http","https","url","get","retrieve","request"]) {
-                    categories.push(serde_json::json!({
-                        "name": "web_browser",
-                        "description": "Fetch content via HTTP GET with WASM validation",
-                        "methods": collect_methods(&["prepare_http_get"]) 
-                    }));
-                }
-
-                // File ops
-                if (has_fn("prepare_file_read") || has_fn("prepare_file_write")) && contains_any(&["save","file","write","read","open","load","store"]) {
-                    categories.push(serde_json::json!({
-                        "name": "file_ops",
-                        "description": "Read and write files with WASM path validation",
-                        "methods": collect_methods(&["prepare_file_read","prepare_file_write"]) 
-                    }));
-                }
-
-                // Shell executor
-                if has_fn("prepare_shell_exec") && contains_any(&["shell","bash","command","execute","run","ls","echo","cat","wc","uname","terminal"]) {
-                    categories.push(serde_json::json!({
-                        "name": "shell_executor",
-                        "description": "Execute simple whitelisted shell commands with WASM validation",
-                        "methods": collect_methods(&["prepare_shell_exec"]) 
-                    }));
-                }
-
-                // Fallback: if none matched, include the three families if present
-                if categories.is_empty() {
-                    if has_fn("prepare_http_get") { categories.push(serde_json::json!({
-                        "name": "web_browser",
-                        "description": "Fetch content via HTTP GET with WASM validation",
-                        "methods": collect_methods(&["prepare_http_get"]) 
-                    })); }
-                    if has_fn("prepare_file_read") || has_fn("prepare_file_write") { categories.push(serde_json::json!({
-                        "name": "file_ops",
-                        "description": "Read and write files with WASM path validation",
-                        "methods": collect_methods(&["prepare_file_read","prepare_file_write"]) 
-                    })); }
-                    if has_fn("prepare_shell_exec") { categories.push(serde_json::json!({
-                        "name": "shell_executor",
-                        "description": "Execute simple whitelisted shell commands with WASM validation",
-                        "methods": collect_methods(&["prepare_shell_exec"]) 
-                    })); }
-                }
-
-                let json_text = serde_json::to_string_pretty(&serde_json::json!(categories))?;
-                json_text
-            } else {
-                let data = arguments.get("data")
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow::anyhow!("Missing or invalid parameter 'data'"))?;
-
-                let result = server.executor.call_function_ptr_len_to_i32(
-                    &tool.module_name,
-                    &tool.function_name,
-                    data.as_bytes(),
-                )?;
+        .ok_or_else(|| RpcError::invalid_params("Missing tool name"))?;
+    call.tool = Some(tool_name.to_string());
+    let mut arguments = params.get("arguments")
+        .cloned()
+        .unwrap_or_else(|| serde_json::json!({}));
+
+    // Find the tool in our discovery system; tools outside the caller's scopes do not exist for it
+    let tool = guard.tool_discovery.find_tool_by_name(tool_name)
+        .filter(|tool| principal.allows(tool))
+        .ok_or_else(|| RpcError::invalid_params(format!("Unknown tool: {}", tool_name)))?
+        .clone();
+    call.tool = Some(tool.name.clone());
+    call.module = Some(tool.module_name.clone());
+    call.module_checksum = guard.module_manager.get_module_metadata(&tool.module_name).map(|meta| meta.checksum.clone());
+
+    // Reject arguments that don't match the declared inputSchema before touching the module
+    let Some(object) = arguments.as_object_mut() else {
+        return Err(RpcError::from_invalid_params(InvalidParams::single("arguments must be an object")));
+    };
+    let token = match object.remove(confirm::TOKEN_ARGUMENT) {
+        None => None,
+        Some(serde_json::Value::String(token)) => Some(token),
+        Some(_) => return Err(RpcError::invalid_params(format!("{} must be a string", confirm::TOKEN_ARGUMENT))),
+    };
+    call.arguments = arguments.clone();
+    let violations = schema_validator::validate(&tool.schema, &arguments);
+    if !violations.is_empty() {
+        return Err(RpcError::from_invalid_params(InvalidParams(violations)));
+    }
 
-                format!("WASM processing result: {} (from {}::{})", result, tool.module_name, tool.function_name)
+    // Destructive calls may wait for the user; approving a call outside the
+    // module's roots lets that call, and only that call, use the paths it named
+    let mut sandbox = tool_kinds::fs_sandbox(&guard.config, &tool);
+    let mode = policy::EffectivePolicy::resolve(&guard.config, &tool).confirm.value;
+    let outside = match mode {
+        ConfirmMode::OutsideRoots => confirm::paths_outside_roots(&tool, &arguments, &sandbox),
+        _ => Vec::new(),
+    };
+    if mode == ConfirmMode::Always || !outside.is_empty() {
+        let redactor = guard.redactor.clone();
+        let decision = match confirm::decide(&tool, &arguments, token.as_deref(), &outside, &mut guard.confirmations, session, &redactor) {
+            // The user may take minutes to answer; other connections keep being served meanwhile
+            Decision::Ask(message) => {
+                drop(guard);
+                let decision = confirm::elicit(&tool, message, session).await;
+                guard = server.lock().await;
+                decision
             }
+            decision => decision,
+        };
+        if let Decision::Held(result) = decision {
+            return Ok(result);
         }
-        "no_params_to_i32" => {
-            let result = server.executor.call_function_no_params_to_i32(
-                &tool.module_name,
-                &tool.function_name,
-            )?;
-
-            format!("WASM result: {} (from {}::{})", result, tool.module_name, tool.function_name)
-        }
-        _ => {
-            return Err(anyhow::anyhow!("Unsupported function pattern: {}", tool.pattern));
+        for (path, access) in outside {
+            sandbox.grant(path, access);
         }
+    }
+
+    // Execution failures are surfaced to the model as isError results;
+    // only malformed arguments are reported as JSON-RPC errors
+    match execute_tool(&tool, &arguments, &sandbox, &mut guard, principal, &mut call.usage).await {
+        Ok(result) => Ok(result),
+        Err(e) => match e.downcast::<InvalidParams>() {
+            Ok(invalid) => Err(RpcError::from_invalid_params(invalid)),
+            Err(e) => Ok(ToolResult::error(format!("{:#}", e))),
+        },
+    }
+}
+
+async fn execute_tool(
+    tool: &DiscoveredTool,
+    arguments: &serde_json::Value,
+    sandbox: &FsSandbox,
+    server: &mut WasmForgeServer,
+    principal: &Principal,
+    usage: &mut audit::Usage,
+) -> Result<ToolResult, anyhow::Error> {
+    let ctx = ToolContext {
+        executor: &mut server.executor,
+        config: &server.config,
+        discovery: &server.tool_discovery,
+        sandbox,
+        usage,
+        principal,
     };
+    let mut result = tool.kind.execute(tool, arguments, ctx).await?;
+
+    // Guests may attach a typed payload (image, audio, document) via the result buffer;
+    // json tools have already consumed it as their reply
+    if tool.pattern != "json"
+        && let Some(output) = server.executor.take_guest_output(&tool.module_name)?
+    {
+        let uri = format!("wasmforge://{}/{}", tool.module_name, tool.function_name);
+        result.push(ToolContent::from_bytes(output.data, &output.mime_type, &uri));
+    }
 
-    Ok(MCPResponse {
-        jsonrpc: "2.0".to_string(),
-        id: request.id,
-        result: Some(serde_json::json!({
-            "content": [
-                {
-                    "type": "text",
-                    "text": result_text
-                }
-            ]
-        })),
-        error: None,
-    })
+    Ok(result)
 }
 
 #[derive(Parser, Debug)]
@@ -398,91 +380,229 @@ struct Args {
     /// Host to bind to
     #[arg(long, default_value = "127.0.0.1")]
     host: String,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-async fn handle_tcp_connection(mut stream: TcpStream, server: std::sync::Arc<tokio::sync::Mutex<WasmForgeServer>>) -> Result<(), anyhow::Error> {
-    let (reader, mut writer) = stream.split();
-    let mut reader = AsyncBufReader::new(reader);
-    let mut line = String::new();
-    
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the effective security policy of every tool as JSON, then exit
+    Policy {
+        /// Only show tools with this name (`module::tool` or the bare tool name)
+        #[arg(long)]
+        tool: Option<String>,
+    },
+    /// Generate a bearer token (or hash an existing one) and print its `[[auth.tokens]]` entry
+    HashToken {
+        /// Hash this token instead of generating one
+        #[arg(long)]
+        token: Option<String>,
+        /// Name recorded for the token in the audit log
+        #[arg(long, default_value = "client")]
+        name: String,
+    },
+}
+
+/// Print a token and the config entry that accepts it.
+fn print_token(token: Option<&str>, name: &str) {
+    let token = token.map_or_else(auth::generate_token, str::to_string);
+    println!("Token:   {}", token);
+    println!("SHA-256: {}", auth::hash_token(&token));
+    println!();
+    println!("[[auth.tokens]]");
+    println!("name = {:?}", name);
+    println!("sha256 = {:?}", auth::hash_token(&token));
+    println!("scopes = [\"*\"]");
+}
+
+/// Principal for an `initialize` carrying `_meta.authorization: "Bearer <token>"`.
+fn authenticate(params: Option<&serde_json::Value>, server: &WasmForgeServer) -> Result<Principal, RpcError> {
+    let Some(auth) = &server.auth else {
+        return Ok(Principal::local());
+    };
+    let token = params
+        .and_then(|p| p.pointer("/_meta/authorization"))
+        .and_then(|v| v.as_str())
+        .and_then(auth::bearer_token)
+        .ok_or_else(|| RpcError::unauthorized("Unauthorized: missing _meta.authorization bearer token"))?;
+    auth.authenticate(token).ok_or_else(|| RpcError::unauthorized("Unauthorized: invalid token"))
+}
+
+/// Print what each discovered tool is allowed to do after merging every config layer.
+fn print_policies(server: &WasmForgeServer, filter: Option<&str>) -> Result<(), anyhow::Error> {
+    let policies: Vec<policy::EffectivePolicy> = server.tool_discovery.sorted_tools()
+        .into_iter()
+        .filter(|tool| filter.is_none_or(|name| name == tool.name || name == format!("{}::{}", tool.module_name, tool.name)))
+        .map(|tool| policy::EffectivePolicy::resolve(&server.config, tool))
+        .collect();
+    if let Some(name) = filter
+        && policies.is_empty()
+    {
+        return Err(anyhow::anyhow!("No tool named '{}'", name));
+    }
+    println!("{}", serde_json::to_string_pretty(&policies)?);
+    Ok(())
+}
+
+/// Serve one client until it disconnects.
+///
+/// Requests are handled one at a time, in order. The reader keeps running meanwhile, so a
+/// tool call waiting on a request the server sent the client (elicitation) gets its reply.
+async fn serve_connection<R, W>(
+    reader: R,
+    mut writer: W,
+    server: Arc<Mutex<WasmForgeServer>>,
+    principal: Option<Principal>,
+) -> Result<(), anyhow::Error>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let redactor = server.lock().await.redactor.clone();
+    let (outbound, mut outgoing) = mpsc::unbounded_channel::<String>();
+    let session = Arc::new(Session::new(outbound, principal));
+    let writer_task = tokio::spawn(async move {
+        while let Some(line) = outgoing.recv().await {
+            writer.write_all(line.as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+            writer.flush().await?;
+        }
+        Ok::<_, std::io::Error>(())
+    });
+
+    let (requests, mut queued) = mpsc::unbounded_channel::<MCPRequest>();
+    let worker = {
+        let session = session.clone();
+        tokio::spawn(async move {
+            while let Some(request) = queued.recv().await {
+                let response = handle_mcp_message(request, &server, &session).await;
+                session.send(&response)?;
+            }
+            Ok::<_, anyhow::Error>(())
+        })
+    };
+
+    let mut lines = AsyncBufReader::new(reader).lines();
     loop {
-        line.clear();
-        match reader.read_line(&mut line).await? {
-            0 => break, // Connection closed
-            _ => {
-                if line.trim().is_empty() {
-                    continue;
-                }
-                
-                match serde_json::from_str::<MCPRequest>(&line) {
-                    Ok(request) => {
-                        let mut server = server.lock().await;
-                        let response = handle_mcp_message(request, &mut server).await;
-                        let response_json = serde_json::to_string(&response)?;
-                        writer.write_all(response_json.as_bytes()).await?;
-                        writer.write_all(b"\n").await?;
-                        writer.flush().await?;
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to parse request: {}", e);
-                    }
-                }
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            // TLS clients often hang up without sending close_notify
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let message = match serde_json::from_str::<serde_json::Value>(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                eprintln!("Failed to parse request: {}", redactor.text(&e.to_string()));
+                continue;
+            }
+        };
+        if session.resolve(&message) {
+            continue;
+        }
+        match serde_json::from_value::<MCPRequest>(message) {
+            Ok(request) => {
+                let _ = requests.send(request);
+            }
+            Err(e) => {
+                eprintln!("Failed to parse request: {}", redactor.text(&e.to_string()));
             }
         }
     }
-    
+
+    // Nobody is left to answer; queued requests still get their responses
+    session.close();
+    drop(requests);
+    worker.await??;
+    drop(session);
+    writer_task.await??;
     Ok(())
 }
 
 async fn run_tcp_server(host: &str, port: u16, server: WasmForgeServer) -> Result<(), anyhow::Error> {
+    let tls = server.config.tls.as_ref().map(TlsReloader::load).transpose()?.map(Arc::new);
+    let tokens = server.auth.is_some();
+    let client_certs = tls.as_ref().is_some_and(|tls| tls.requires_client_cert());
+    if !tokens && !client_certs && !auth::is_loopback(host, port).await? {
+        return Err(anyhow::anyhow!(
+            "Refusing to listen on {} without authentication: add [[auth.tokens]] (see `hash-token`), require client certificates with [tls] client_ca, or bind to 127.0.0.1",
+            host
+        ));
+    }
     let addr = format!("{}:{}", host, port);
     let listener = TcpListener::bind(&addr).await?;
-    let server = std::sync::Arc::new(tokio::sync::Mutex::new(server));
+    let server = Arc::new(Mutex::new(server));
     
-    eprintln!("WasmForge MCP Server listening on {}", addr);
+    eprintln!("WasmForge MCP Server listening on {}{}", addr, if tls.is_some() { " (TLS)" } else { "" });
     
     loop {
         let (stream, addr) = listener.accept().await?;
         eprintln!("New connection from {}", addr);
         
         let server_clone = server.clone();
+        let redactor = server.lock().await.redactor.clone();
+        let tls = tls.clone();
         tokio::spawn(async move {
-            if let Err(e) = handle_tcp_connection(stream, server_clone).await {
-                eprintln!("Connection error: {}", e);
+            let result = match tls {
+                Some(tls) => serve_tls(stream, &tls, tokens, server_clone).await,
+                None => {
+                    let (reader, writer) = stream.into_split();
+                    // Without [auth] only loopback clients can connect, and they act as the local user
+                    serve_connection(reader, writer, server_clone, (!tokens).then(Principal::local)).await
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("Connection error: {}", redactor.text(&e.to_string()));
             }
         });
     }
 }
 
-async fn run_stdio_server(mut server: WasmForgeServer) -> Result<(), anyhow::Error> {
-    eprintln!("WasmForge MCP Server started on stdio");
-    
-    let stdin = io::stdin();
-    let reader = BufReader::new(stdin.lock());
-    
-    for line in reader.lines() {
-        let line = line?;
-        if line.trim().is_empty() {
-            continue;
+/// Complete the TLS handshake, then serve the connection. A client certificate matching
+/// `[[tls.clients]]` authenticates it; otherwise bearer tokens apply as on plain TCP.
+async fn serve_tls(
+    stream: tokio::net::TcpStream,
+    tls: &TlsReloader,
+    tokens: bool,
+    server: Arc<Mutex<WasmForgeServer>>,
+) -> Result<(), anyhow::Error> {
+    let stream = tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, tls.acceptor().accept(stream))
+        .await
+        .map_err(|_| anyhow::anyhow!("TLS handshake timed out"))?
+        .map_err(|e| anyhow::anyhow!("TLS handshake failed: {}", e))?;
+    let principal = match tls.client_principal(stream.get_ref().1.peer_certificates()) {
+        Ok(Some(principal)) => Some(principal),
+        Ok(None) => (!tokens).then(Principal::local),
+        Err(e) if tokens => {
+            eprintln!("{:#}; expecting a bearer token", e);
+            None
         }
-        
-        match serde_json::from_str::<MCPRequest>(&line) {
-            Ok(request) => {
-                let response = handle_mcp_message(request, &mut server).await;
-                println!("{}", serde_json::to_string(&response)?);
-            }
-            Err(e) => {
-                eprintln!("Failed to parse request: {}", e);
-            }
-        }
-    }
-    
-    Ok(())
+        Err(e) => return Err(e),
+    };
+    let (reader, writer) = tokio::io::split(stream);
+    serve_connection(reader, writer, server, principal).await
+}
+
+async fn run_stdio_server(server: WasmForgeServer) -> Result<(), anyhow::Error> {
+    eprintln!("WasmForge MCP Server started on stdio");
+    // Whoever can write to our stdin already runs as this user
+    serve_connection(tokio::io::stdin(), tokio::io::stdout(), Arc::new(Mutex::new(server)), Some(Principal::local())).await
 }
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
     let args = Args::parse();
+
+    if let Some(Command::HashToken { token, name }) = &args.command {
+        print_token(token.as_deref(), name);
+        return Ok(());
+    }
     
     // Load configuration
     let config_path = Config::get_config_path();
@@ -491,11 +611,15 @@ async fn main() -> Result<(), anyhow::Error> {
     // Validate configuration
     config.validate()?;
     
-    println!("📋 WasmForge MCP Server starting...");
-    println!("Config: {}", config_path.display());
+    eprintln!("📋 WasmForge MCP Server starting...");
+    eprintln!("Config: {}", config_path.display());
     
     // Initialize the server with all modules
     let server = WasmForgeServer::new(config).await?;
+
+    if let Some(Command::Policy { tool }) = &args.command {
+        return print_policies(&server, tool.as_deref());
+    }
     
     match args.port {
         Some(port) => {
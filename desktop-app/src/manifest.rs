@@ -0,0 +1,104 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::config::ToolAnnotations;
+use crate::tool_kinds::ToolKind;
+
+/// Name of the custom section holding a module's embedded tool manifest.
+pub const MANIFEST_SECTION: &str = "wasmforge";
+
+/// Tool metadata embedded in a module so the `.wasm` file is self-describing.
+///
+/// The section payload is a UTF-8 JSON document. Entries from `config.toml`
+/// `[[modules.tools]]` take precedence over the manifest.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModuleManifest {
+    #[serde(default)]
+    pub tools: Vec<ManifestTool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestTool {
+    /// Tool name exposed to MCP clients
+    pub name: String,
+    /// Exported function backing the tool
+    pub function: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    #[serde(alias = "inputSchema")]
+    pub input_schema: Option<Value>,
+    #[serde(alias = "outputSchema")]
+    pub output_schema: Option<Value>,
+    /// How the tool executes; defaults to a plain WASM call
+    pub kind: Option<ToolKind>,
+    /// Calling convention for `wasm` tools; only `json` is declared, others are inferred
+    pub pattern: Option<String>,
+    /// Argument names mapped onto the function's parameters, in order
+    pub params: Option<Vec<String>>,
+    /// Host input -> argument name mapping for host-backed kinds
+    pub arguments: Option<HashMap<String, String>>,
+    pub annotations: Option<ToolAnnotations>,
+    /// Host capabilities the tool needs, e.g. `http`, `fs.read`, `fs.write`, `shell`
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+/// Parse the `wasmforge` custom section of a module, if present.
+pub fn parse_manifest(wasm_bytes: &[u8]) -> Result<Option<ModuleManifest>> {
+    let Some(payload) = find_custom_section(wasm_bytes, MANIFEST_SECTION)? else {
+        return Ok(None);
+    };
+
+    let manifest: ModuleManifest = serde_json::from_slice(payload)
+        .context("Failed to parse embedded wasmforge manifest")?;
+
+    Ok(Some(manifest))
+}
+
+/// Walk the module's sections and return the payload of the first custom section named `name`.
+fn find_custom_section<'a>(wasm_bytes: &'a [u8], name: &str) -> Result<Option<&'a [u8]>> {
+    if wasm_bytes.len() < 8 || wasm_bytes[0..4] != *b"\0asm" {
+        return Err(anyhow::anyhow!("Invalid WASM module: missing magic bytes"));
+    }
+
+    let mut offset = 8;
+    while offset < wasm_bytes.len() {
+        let section_id = wasm_bytes[offset];
+        offset += 1;
+        let size = read_leb128_u32(wasm_bytes, &mut offset)? as usize;
+        let end = offset.checked_add(size)
+            .filter(|end| *end <= wasm_bytes.len())
+            .ok_or_else(|| anyhow::anyhow!("Invalid WASM module: section extends past end of file"))?;
+
+        if section_id == 0 {
+            let mut cursor = offset;
+            let name_len = read_leb128_u32(wasm_bytes, &mut cursor)? as usize;
+            let name_end = cursor.checked_add(name_len)
+                .filter(|name_end| *name_end <= end)
+                .ok_or_else(|| anyhow::anyhow!("Invalid WASM module: malformed custom section name"))?;
+            if &wasm_bytes[cursor..name_end] == name.as_bytes() {
+                return Ok(Some(&wasm_bytes[name_end..end]));
+            }
+        }
+
+        offset = end;
+    }
+
+    Ok(None)
+}
+
+fn read_leb128_u32(bytes: &[u8], offset: &mut usize) -> Result<u32> {
+    let mut result: u32 = 0;
+    for shift in (0..35).step_by(7) {
+        let byte = *bytes.get(*offset)
+            .ok_or_else(|| anyhow::anyhow!("Invalid WASM module: truncated LEB128 value"))?;
+        *offset += 1;
+        result |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+    Err(anyhow::anyhow!("Invalid WASM module: LEB128 value too long"))
+}
@@ -43,12 +43,16 @@ impl ModuleManager {
         })
     }
 
+    pub fn get_module_config(&self, name: &str) -> Option<&ModuleConfig> {
+        self.config.find_module(name)
+    }
+
     pub async fn load_all_modules(&mut self) -> Result<()> {
         let enabled_modules: Vec<_> = self.config.enabled_modules().cloned().collect();
         for module_config in enabled_modules {
             match self.load_module(&module_config).await {
                 Ok(metadata) => {
-                    println!("✓ Loaded module: {} v{}", metadata.name, metadata.version);
+                    eprintln!("✓ Loaded module: {} v{}", metadata.name, metadata.version);
                     self.loaded_modules.insert(metadata.name.clone(), metadata);
                 }
                 Err(e) => {
@@ -126,14 +130,15 @@ impl ModuleManager {
         let cached_path = self.cache_dir.join(format!("{}.wasm", module_id));
 
         // Check if module is already cached and valid
-        if let Ok(metadata) = self.load_cached_metadata(&module_id) {
-            if cached_path.exists() && self.is_cache_valid(&metadata) {
-                println!("Using cached module: {}", config.name);
-                return Ok(metadata);
-            }
+        if let Ok(metadata) = self.load_cached_metadata(&module_id)
+            && cached_path.exists()
+            && self.is_cache_valid(&metadata)
+        {
+            eprintln!("Using cached module: {}", config.name);
+            return Ok(metadata);
         }
 
-        println!("Downloading module from: {}", url);
+        eprintln!("Downloading module from: {}", url);
         let response = self.client.get(url).send().await
             .with_context(|| format!("Failed to download module from: {}", url))?;
 
@@ -151,15 +156,15 @@ impl ModuleManager {
         let checksum = self.calculate_checksum(&wasm_bytes);
 
         // Verify checksum if provided
-        if let Some(expected) = expected_checksum {
-            if checksum != expected {
-                return Err(anyhow::anyhow!(
-                    "Checksum mismatch for module '{}': expected {}, got {}",
-                    config.name,
-                    expected,
-                    checksum
-                ));
-            }
+        if let Some(expected) = expected_checksum
+            && checksum != expected
+        {
+            return Err(anyhow::anyhow!(
+                "Checksum mismatch for module '{}': expected {}, got {}",
+                config.name,
+                expected,
+                checksum
+            ));
         }
 
         self.validate_wasm_module(&wasm_bytes)?;
@@ -202,12 +207,12 @@ impl ModuleManager {
             return Err(anyhow::anyhow!("Invalid WASM module: too short"));
         }
 
-        if &wasm_bytes[0..4] != b"\0asm" {
+        if wasm_bytes[0..4] != *b"\0asm" {
             return Err(anyhow::anyhow!("Invalid WASM module: missing magic bytes"));
         }
 
         // Check version (should be 1)
-        if &wasm_bytes[4..8] != [0x01, 0x00, 0x00, 0x00] {
+        if wasm_bytes[4..8] != [0x01, 0x00, 0x00, 0x00] {
             return Err(anyhow::anyhow!("Unsupported WASM version"));
         }
 
@@ -253,6 +258,7 @@ impl ModuleManager {
         &self.loaded_modules
     }
 
+    #[allow(dead_code)]
     pub fn get_module_metadata(&self, name: &str) -> Option<&ModuleMetadata> {
         self.loaded_modules.get(name)
     }
@@ -265,6 +271,7 @@ impl ModuleManager {
             .with_context(|| format!("Failed to read module file: {}", metadata.wasm_path.display()))
     }
 
+    #[allow(dead_code)]
     pub async fn reload_module(&mut self, name: &str) -> Result<()> {
         let module_config = self.config.find_module(name)
             .ok_or_else(|| anyhow::anyhow!("Module '{}' not found in configuration", name))?
@@ -283,6 +290,7 @@ impl ModuleManager {
         }
     }
 
+    #[allow(dead_code)]
     pub fn cleanup_cache(&self) -> Result<()> {
         // TODO: Implement cache cleanup based on size limits and TTL
         println!("Cache cleanup not yet implemented");
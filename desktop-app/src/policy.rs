@@ -0,0 +1,303 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::config::{CommandRule, Config, ConfirmMode, FilesystemConfig, HttpConfig, ModuleConfig, ShellSandboxConfig, ToolSecurityConfig};
+use crate::shell;
+use crate::tool_discovery::DiscoveredTool;
+use crate::tool_kinds::ToolKind;
+
+/// Commands `shell_exec` tools may run unless `allowed_commands` is set.
+pub const DEFAULT_ALLOWED_COMMANDS: [&str; 5] = ["echo", "cat", "ls", "wc", "uname"];
+
+/// Body characters shown by `http_get` and `fetch` unless `max_response_chars` is set.
+pub const DEFAULT_PREVIEW_CHARS: usize = 500;
+
+/// Body characters returned by `http` unless `max_response_chars` is set.
+pub const DEFAULT_HTTP_BODY_CHARS: usize = 8000;
+
+/// Config layer an effective setting was taken from.
+///
+/// Layers are consulted from most to least specific: the tool's
+/// `[modules.tools.security]`, the module's `[modules.security]`, the module's legacy
+/// `allowed_commands_csv` metadata, the global `[security]` table, then built-in defaults.
+/// Each setting is resolved on its own, so a tool can override one field and inherit the rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicySource {
+    Tool,
+    Module,
+    ModuleMetadata,
+    Global,
+    Default,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Setting<T> {
+    pub value: T,
+    pub source: PolicySource,
+}
+
+/// Limits applied to a `shell_exec` tool.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShellSettings {
+    pub allowed_commands: Setting<Vec<String>>,
+    pub command_rules: Setting<BTreeMap<String, CommandRule>>,
+    pub timeout_secs: Setting<u64>,
+    pub max_output_bytes: Setting<usize>,
+    pub allowed_env: Setting<Vec<String>>,
+    /// Taken whole from the first layer that sets it
+    pub sandbox: Setting<Option<ShellSandboxConfig>>,
+}
+
+/// Everything a tool is allowed to do, after merging every config layer.
+#[derive(Debug, Clone, Serialize)]
+pub struct EffectivePolicy {
+    pub tool: String,
+    pub module: String,
+    pub kind: ToolKind,
+    pub capabilities: Vec<String>,
+    pub confirm: Setting<ConfirmMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shell: Option<ShellSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_response_chars: Option<Setting<usize>>,
+    /// Module-wide filesystem roots; `None` denies every file operation
+    pub filesystem: Option<FilesystemConfig>,
+    /// Module-wide egress grant for the guest `wasmforge::http_request` import
+    pub http: Option<HttpConfig>,
+}
+
+impl EffectivePolicy {
+    pub fn resolve(config: &Config, tool: &DiscoveredTool) -> Self {
+        let module = config.find_module(&tool.module_name);
+        let layers = Layers {
+            tool: tool_security(config, tool),
+            module: module.and_then(|m| m.security.as_ref()),
+            global: config.security.as_ref(),
+        };
+
+        let shell = (tool.kind == ToolKind::ShellExec).then(|| ShellSettings {
+            allowed_commands: allowed_commands(&layers, module),
+            command_rules: layers.pick(
+                |s| s.command_rules.as_ref().map(|rules| rules.iter().map(|(k, v)| (k.clone(), v.clone())).collect()),
+                BTreeMap::new(),
+            ),
+            timeout_secs: layers.pick(|s| s.timeout_secs, shell::DEFAULT_TIMEOUT_SECS),
+            max_output_bytes: layers.pick(|s| s.max_output_bytes, shell::DEFAULT_MAX_OUTPUT_BYTES),
+            allowed_env: layers.pick(
+                |s| s.allowed_env.clone(),
+                shell::DEFAULT_ALLOWED_ENV.iter().map(|name| name.to_string()).collect(),
+            ),
+            sandbox: layers.pick(|s| s.sandbox.clone().map(Some), None),
+        });
+
+        let max_response_chars = match tool.kind {
+            ToolKind::HttpGet | ToolKind::Fetch => Some(DEFAULT_PREVIEW_CHARS),
+            ToolKind::Http => Some(DEFAULT_HTTP_BODY_CHARS),
+            _ => None,
+        }
+        .map(|default| layers.pick(|s| s.max_response_chars, default));
+
+        Self {
+            tool: tool.name.clone(),
+            module: tool.module_name.clone(),
+            kind: tool.kind,
+            capabilities: tool.capabilities.clone(),
+            confirm: layers.pick(|s| s.confirm, ConfirmMode::Never),
+            shell,
+            max_response_chars,
+            filesystem: module.and_then(|m| m.filesystem.clone()),
+            http: module.and_then(|m| m.http.clone()),
+        }
+    }
+
+    /// Resolved `max_response_chars`, or `default` for kinds that do not use it.
+    pub fn max_response_chars(&self, default: usize) -> usize {
+        self.max_response_chars.as_ref().map_or(default, |setting| setting.value)
+    }
+}
+
+struct Layers<'a> {
+    tool: Option<&'a ToolSecurityConfig>,
+    module: Option<&'a ToolSecurityConfig>,
+    global: Option<&'a ToolSecurityConfig>,
+}
+
+impl Layers<'_> {
+    /// First layer setting the field, falling back to the built-in default.
+    fn pick<T>(&self, field: impl Fn(&ToolSecurityConfig) -> Option<T>, default: T) -> Setting<T> {
+        [(PolicySource::Tool, self.tool), (PolicySource::Module, self.module), (PolicySource::Global, self.global)]
+            .into_iter()
+            .find_map(|(source, layer)| layer.and_then(&field).map(|value| Setting { value, source }))
+            .unwrap_or(Setting { value: default, source: PolicySource::Default })
+    }
+}
+
+/// Allowed commands, with the legacy CSV metadata ranked below structured module config.
+/// An empty list is treated as unset.
+fn allowed_commands(layers: &Layers<'_>, module: Option<&ModuleConfig>) -> Setting<Vec<String>> {
+    let structured = |s: &ToolSecurityConfig| s.allowed_commands.clone().filter(|list| !list.is_empty());
+    let csv = module
+        .and_then(|m| m.metadata.as_ref())
+        .and_then(|meta| meta.get("allowed_commands_csv"))
+        .map(|csv| csv.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect::<Vec<_>>())
+        .filter(|list| !list.is_empty());
+
+    [(PolicySource::Tool, layers.tool), (PolicySource::Module, layers.module)]
+        .into_iter()
+        .find_map(|(source, layer)| layer.and_then(structured).map(|value| Setting { value, source }))
+        .or_else(|| csv.map(|value| Setting { value, source: PolicySource::ModuleMetadata }))
+        .or_else(|| layers.global.and_then(structured).map(|value| Setting { value, source: PolicySource::Global }))
+        .unwrap_or_else(|| Setting {
+            value: DEFAULT_ALLOWED_COMMANDS.iter().map(|c| c.to_string()).collect(),
+            source: PolicySource::Default,
+        })
+}
+
+/// Security settings of the config entry declaring this tool, matched by name and then by export.
+fn tool_security<'a>(config: &'a Config, tool: &DiscoveredTool) -> Option<&'a ToolSecurityConfig> {
+    let tools = config.find_module(&tool.module_name)?.tools.as_ref()?;
+    tools.iter().find(|t| t.name == tool.name)
+        .or_else(|| tools.iter().find(|t| t.function_name == tool.function_name))?
+        .security.as_ref()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn config(extra: &str) -> Config {
+        toml::from_str(&format!(r#"
+            [server]
+            name = "wasmforge"
+            version = "0.1.0"
+            default_host = "127.0.0.1"
+
+            [cache]
+            directory = "/tmp"
+            max_size_mb = 1
+            ttl_hours = 1
+            {extra}
+        "#)).unwrap()
+    }
+
+    /// One `guest` module whose `run` tool has the given security tables and metadata.
+    fn module(module_security: &str, tool_security: &str, metadata: &str) -> String {
+        format!(r#"
+            [[modules]]
+            name = "guest"
+            enabled = true
+            source = {{ type = "local", path = "guest.wasm" }}
+            {metadata}
+
+            [modules.security]
+            {module_security}
+
+            [[modules.tools]]
+            name = "run"
+            function_name = "run_export"
+
+            [modules.tools.security]
+            {tool_security}
+        "#)
+    }
+
+    fn tool(kind: ToolKind) -> DiscoveredTool {
+        DiscoveredTool { function_name: "run_export".to_string(), ..test_support::tool("guest", "run", kind) }
+    }
+
+    fn shell(config: &Config) -> ShellSettings {
+        EffectivePolicy::resolve(config, &tool(ToolKind::ShellExec)).shell.unwrap()
+    }
+
+    #[test]
+    fn layers_are_picked_from_most_to_least_specific() {
+        let tool = ToolSecurityConfig { timeout_secs: Some(3), ..Default::default() };
+        let module = ToolSecurityConfig { timeout_secs: Some(2), max_output_bytes: Some(20), ..Default::default() };
+        let global = ToolSecurityConfig {
+            timeout_secs: Some(1),
+            max_output_bytes: Some(10),
+            max_response_chars: Some(5),
+            ..Default::default()
+        };
+        let layers = Layers { tool: Some(&tool), module: Some(&module), global: Some(&global) };
+
+        let timeout = layers.pick(|s| s.timeout_secs, 0);
+        assert_eq!((timeout.value, timeout.source), (3, PolicySource::Tool));
+        let output = layers.pick(|s| s.max_output_bytes, 0);
+        assert_eq!((output.value, output.source), (20, PolicySource::Module));
+        let chars = layers.pick(|s| s.max_response_chars, 0);
+        assert_eq!((chars.value, chars.source), (5, PolicySource::Global));
+        let confirm = layers.pick(|s| s.confirm, ConfirmMode::Never);
+        assert_eq!((confirm.value, confirm.source), (ConfirmMode::Never, PolicySource::Default));
+
+        let empty = Layers { tool: None, module: None, global: None };
+        assert_eq!(empty.pick(|s| s.timeout_secs, 7).source, PolicySource::Default);
+    }
+
+    #[test]
+    fn each_setting_is_resolved_on_its_own() {
+        let config = config(&format!(
+            "[security]\ntimeout_secs = 1\nmax_output_bytes = 10\nconfirm = \"always\"\nallowed_env = [\"LANG\"]\n{}",
+            module("timeout_secs = 2\nmax_output_bytes = 20", "timeout_secs = 3", ""),
+        ));
+        let policy = EffectivePolicy::resolve(&config, &tool(ToolKind::ShellExec));
+        assert_eq!((policy.confirm.value, policy.confirm.source), (ConfirmMode::Always, PolicySource::Global));
+
+        let shell = policy.shell.unwrap();
+        assert_eq!((shell.timeout_secs.value, shell.timeout_secs.source), (3, PolicySource::Tool));
+        assert_eq!((shell.max_output_bytes.value, shell.max_output_bytes.source), (20, PolicySource::Module));
+        assert_eq!((shell.allowed_env.value, shell.allowed_env.source), (vec!["LANG".to_string()], PolicySource::Global));
+        assert_eq!(shell.command_rules.source, PolicySource::Default);
+        assert_eq!(shell.sandbox.source, PolicySource::Default);
+    }
+
+    #[test]
+    fn structured_allowed_commands_beat_the_csv_metadata() {
+        let csv = "metadata = { allowed_commands_csv = \"wc, ls\" }";
+
+        let from_tool = config(&module("allowed_commands = [\"cat\"]", "allowed_commands = [\"echo\"]", csv));
+        let allowed = shell(&from_tool).allowed_commands;
+        assert_eq!((allowed.value, allowed.source), (vec!["echo".to_string()], PolicySource::Tool));
+
+        let from_module = config(&module("allowed_commands = [\"cat\"]", "", csv));
+        let allowed = shell(&from_module).allowed_commands;
+        assert_eq!((allowed.value, allowed.source), (vec!["cat".to_string()], PolicySource::Module));
+
+        // An empty structured list is unset, so the CSV applies
+        let empty = config(&module("allowed_commands = []", "", csv));
+        let allowed = shell(&empty).allowed_commands;
+        assert_eq!((allowed.value, allowed.source), (vec!["wc".to_string(), "ls".to_string()], PolicySource::ModuleMetadata));
+    }
+
+    #[test]
+    fn csv_metadata_beats_global_allowed_commands() {
+        let global = "[security]\nallowed_commands = [\"uname\"]\n";
+        let csv = "metadata = { allowed_commands_csv = \"wc\" }";
+
+        let with_csv = config(&format!("{}{}", global, module("", "", csv)));
+        let allowed = shell(&with_csv).allowed_commands;
+        assert_eq!((allowed.value, allowed.source), (vec!["wc".to_string()], PolicySource::ModuleMetadata));
+
+        let without_csv = config(&format!("{}{}", global, module("", "", "")));
+        let allowed = shell(&without_csv).allowed_commands;
+        assert_eq!((allowed.value, allowed.source), (vec!["uname".to_string()], PolicySource::Global));
+
+        let allowed = shell(&config(&module("", "", ""))).allowed_commands;
+        assert_eq!(allowed.source, PolicySource::Default);
+        assert_eq!(allowed.value, DEFAULT_ALLOWED_COMMANDS.map(str::to_string).to_vec());
+    }
+
+    #[test]
+    fn response_limits_apply_only_to_http_kinds() {
+        let limited = config(&module("", "max_response_chars = 42", ""));
+        assert!(EffectivePolicy::resolve(&limited, &tool(ToolKind::Wasm)).max_response_chars.is_none());
+        assert!(EffectivePolicy::resolve(&limited, &tool(ToolKind::Fetch)).shell.is_none());
+
+        let limit = EffectivePolicy::resolve(&limited, &tool(ToolKind::Http)).max_response_chars.unwrap();
+        assert_eq!((limit.value, limit.source), (42, PolicySource::Tool));
+        let preview = EffectivePolicy::resolve(&config(&module("", "", "")), &tool(ToolKind::HttpGet));
+        assert_eq!(preview.max_response_chars(0), DEFAULT_PREVIEW_CHARS);
+    }
+}
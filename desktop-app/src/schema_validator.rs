@@ -0,0 +1,211 @@
+use serde_json::Value;
+
+/// Validate `instance` against a tool's JSON schema and collect every violation.
+///
+/// Supports the subset of JSON Schema used for tool input: `type`, `properties`,
+/// `required`, `additionalProperties`, `enum`, `const`, numeric ranges, string
+/// length and array `items`/length. Unknown keywords are ignored.
+pub fn validate(schema: &Value, instance: &Value) -> Vec<String> {
+    let mut violations = Vec::new();
+    validate_at(schema, instance, "$", &mut violations);
+    violations
+}
+
+fn validate_at(schema: &Value, instance: &Value, path: &str, violations: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        // `true`/`false` schemas
+        if schema == &Value::Bool(false) {
+            violations.push(format!("{}: no value is allowed here", path));
+        }
+        return;
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(|t| t.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| matches_type(t, instance)) {
+            violations.push(format!("{}: expected {}, got {}", path, types.join(" or "), type_name(instance)));
+            // Further keywords would only repeat the type mismatch
+            return;
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(|v| v.as_array())
+        && !allowed.iter().any(|v| json_equal(v, instance))
+    {
+        let options: Vec<String> = allowed.iter().map(|v| v.to_string()).collect();
+        violations.push(format!("{}: must be one of {}", path, options.join(", ")));
+    }
+
+    if let Some(expected) = schema.get("const")
+        && !json_equal(expected, instance)
+    {
+        violations.push(format!("{}: must be {}", path, expected));
+    }
+
+    match instance {
+        Value::Number(n) => validate_number(schema, n.as_f64().unwrap_or(f64::NAN), path, violations),
+        Value::String(s) => validate_string(schema, s, path, violations),
+        Value::Array(items) => validate_array(schema, items, path, violations),
+        Value::Object(_) => validate_object(schema, instance, path, violations),
+        _ => {}
+    }
+}
+
+fn validate_number(schema: &serde_json::Map<String, Value>, n: f64, path: &str, violations: &mut Vec<String>) {
+    if let Some(min) = schema.get("minimum").and_then(|v| v.as_f64())
+        && n < min
+    {
+        violations.push(format!("{}: {} is less than the minimum of {}", path, n, min));
+    }
+    if let Some(max) = schema.get("maximum").and_then(|v| v.as_f64())
+        && n > max
+    {
+        violations.push(format!("{}: {} is greater than the maximum of {}", path, n, max));
+    }
+    if let Some(min) = schema.get("exclusiveMinimum").and_then(|v| v.as_f64())
+        && n <= min
+    {
+        violations.push(format!("{}: {} must be greater than {}", path, n, min));
+    }
+    if let Some(max) = schema.get("exclusiveMaximum").and_then(|v| v.as_f64())
+        && n >= max
+    {
+        violations.push(format!("{}: {} must be less than {}", path, n, max));
+    }
+}
+
+fn validate_string(schema: &serde_json::Map<String, Value>, s: &str, path: &str, violations: &mut Vec<String>) {
+    let length = s.chars().count() as u64;
+    if let Some(min) = schema.get("minLength").and_then(|v| v.as_u64())
+        && length < min
+    {
+        violations.push(format!("{}: string is shorter than {} characters", path, min));
+    }
+    if let Some(max) = schema.get("maxLength").and_then(|v| v.as_u64())
+        && length > max
+    {
+        violations.push(format!("{}: string is longer than {} characters", path, max));
+    }
+}
+
+fn validate_array(schema: &serde_json::Map<String, Value>, items: &[Value], path: &str, violations: &mut Vec<String>) {
+    let count = items.len() as u64;
+    if let Some(min) = schema.get("minItems").and_then(|v| v.as_u64())
+        && count < min
+    {
+        violations.push(format!("{}: array has fewer than {} items", path, min));
+    }
+    if let Some(max) = schema.get("maxItems").and_then(|v| v.as_u64())
+        && count > max
+    {
+        violations.push(format!("{}: array has more than {} items", path, max));
+    }
+    if let Some(item_schema) = schema.get("items") {
+        for (index, item) in items.iter().enumerate() {
+            validate_at(item_schema, item, &format!("{}[{}]", path, index), violations);
+        }
+    }
+}
+
+fn validate_object(schema: &serde_json::Map<String, Value>, instance: &Value, path: &str, violations: &mut Vec<String>) {
+    let Some(object) = instance.as_object() else {
+        return;
+    };
+    let properties = schema.get("properties").and_then(|v| v.as_object());
+
+    if let Some(required) = schema.get("required").and_then(|v| v.as_array()) {
+        for name in required.iter().filter_map(|v| v.as_str()) {
+            if !object.contains_key(name) {
+                violations.push(format!("{}: missing required property '{}'", path, name));
+            }
+        }
+    }
+
+    for (name, value) in object {
+        let property_path = format!("{}.{}", path, name);
+        match properties.and_then(|p| p.get(name)) {
+            Some(property_schema) => validate_at(property_schema, value, &property_path, violations),
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => {
+                    violations.push(format!("{}: unexpected property '{}'", path, name));
+                }
+                Some(additional @ Value::Object(_)) => {
+                    validate_at(additional, value, &property_path, violations);
+                }
+                _ => {}
+            },
+        }
+    }
+}
+
+fn matches_type(expected: &str, instance: &Value) -> bool {
+    match expected {
+        "object" => instance.is_object(),
+        "array" => instance.is_array(),
+        "string" => instance.is_string(),
+        "boolean" => instance.is_boolean(),
+        "null" => instance.is_null(),
+        "number" => instance.is_number(),
+        // Integral by value, as JSON Schema defines it: `1.0` is an integer
+        "integer" => match instance {
+            Value::Number(n) => n.is_i64() || n.is_u64() || n.as_f64().is_some_and(|f| f.fract() == 0.0),
+            _ => false,
+        },
+        _ => true,
+    }
+}
+
+fn type_name(instance: &Value) -> &'static str {
+    match instance {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Equality where `1` and `1.0` are the same number, as JSON Schema requires.
+fn json_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64() == y.as_f64(),
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn integers_are_integral_by_value() {
+        let schema = json!({"type": "object", "properties": {"n": {"type": "integer"}}});
+        assert!(validate(&schema, &json!({"n": 1})).is_empty());
+        assert!(validate(&schema, &json!({"n": u64::MAX})).is_empty());
+        assert!(validate(&schema, &json!({"n": 1.0})).is_empty());
+        assert!(validate(&schema, &json!({"n": -3e2})).is_empty());
+        assert_eq!(validate(&schema, &json!({"n": 1.5})), vec!["$.n: expected integer, got number"]);
+        assert_eq!(validate(&schema, &json!({"n": "1"})), vec!["$.n: expected integer, got string"]);
+    }
+
+    #[test]
+    fn violations_are_collected_with_their_paths() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"a": {"type": "integer", "minimum": 0}, "b": {"type": "string", "maxLength": 2}},
+            "required": ["a", "c"],
+            "additionalProperties": false
+        });
+        let violations = validate(&schema, &json!({"a": -1, "b": "long", "d": true}));
+        assert_eq!(violations.len(), 4, "{:?}", violations);
+        assert!(violations.iter().any(|v| v.starts_with("$.a:")));
+        assert!(violations.iter().any(|v| v.starts_with("$.b:")));
+    }
+}
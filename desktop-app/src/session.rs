@@ -0,0 +1,128 @@
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::auth::Principal;
+
+type Reply = std::result::Result<Value, Value>;
+
+/// One client connection, as seen by the request handlers.
+///
+/// Outgoing messages go through the connection's writer task, so a handler can send the
+/// client requests of its own (such as `elicitation/create`) and wait for the reply while
+/// the connection's reader keeps running.
+pub struct Session {
+    id: String,
+    outbound: mpsc::UnboundedSender<String>,
+    /// Server-initiated requests waiting for a reply; `None` once the client has gone
+    pending: Mutex<Option<HashMap<u64, oneshot::Sender<Reply>>>>,
+    next_id: AtomicU64,
+    client_info: Mutex<Value>,
+    client_capabilities: Mutex<Value>,
+    /// `None` until the client has authenticated
+    principal: Mutex<Option<Principal>>,
+}
+
+impl Session {
+    pub fn new(outbound: mpsc::UnboundedSender<String>, principal: Option<Principal>) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            outbound,
+            pending: Mutex::new(Some(HashMap::new())),
+            next_id: AtomicU64::new(1),
+            client_info: Mutex::new(Value::Null),
+            client_capabilities: Mutex::new(Value::Null),
+            principal: Mutex::new(principal),
+        }
+    }
+
+    /// Identifies this connection in the audit log.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Remember who the client is and what it declared in `initialize`.
+    pub fn set_client(&self, info: Value, capabilities: Value) {
+        *self.client_info.lock().unwrap() = info;
+        *self.client_capabilities.lock().unwrap() = capabilities;
+    }
+
+    pub fn principal(&self) -> Option<Principal> {
+        self.principal.lock().unwrap().clone()
+    }
+
+    pub fn set_principal(&self, principal: Principal) {
+        *self.principal.lock().unwrap() = Some(principal);
+    }
+
+    /// The client's `clientInfo`, or null before `initialize`.
+    pub fn client_info(&self) -> Value {
+        self.client_info.lock().unwrap().clone()
+    }
+
+    /// Whether the client declared a capability such as `elicitation`.
+    pub fn client_supports(&self, capability: &str) -> bool {
+        self.client_capabilities.lock().unwrap().get(capability).is_some_and(|value| !value.is_null())
+    }
+
+    /// Queue a message for the client.
+    pub fn send(&self, message: &impl Serialize) -> Result<()> {
+        let line = serde_json::to_string(message)?;
+        self.outbound.send(line).map_err(|_| anyhow::anyhow!("Client connection is closed"))
+    }
+
+    /// Send the client a request and wait for its result.
+    pub async fn request(&self, method: &str, params: Value, timeout: Duration) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        match self.pending.lock().unwrap().as_mut() {
+            Some(pending) => pending.insert(id, sender),
+            None => return Err(anyhow::anyhow!("Client connection is closed")),
+        };
+        self.send(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))?;
+
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(Ok(result))) => Ok(result),
+            Ok(Ok(Err(error))) => Err(anyhow::anyhow!("Client rejected {}: {}", method, error)),
+            Ok(Err(_)) => Err(anyhow::anyhow!("Client connection closed before answering {}", method)),
+            Err(_) => {
+                if let Some(pending) = self.pending.lock().unwrap().as_mut() {
+                    pending.remove(&id);
+                }
+                Err(anyhow::anyhow!("Client did not answer {} within {}s", method, timeout.as_secs()))
+            }
+        }
+    }
+
+    /// Hand a client message to the request waiting for it.
+    /// Returns false when the message is not a reply to one of ours.
+    pub fn resolve(&self, message: &Value) -> bool {
+        if message.get("method").is_some() || !(message.get("result").is_some() || message.get("error").is_some()) {
+            return false;
+        }
+        let Some(id) = message.get("id").and_then(Value::as_u64) else {
+            return false;
+        };
+        let Some(sender) = self.pending.lock().unwrap().as_mut().and_then(|pending| pending.remove(&id)) else {
+            eprintln!("Ignoring reply to unknown request {}", id);
+            return true;
+        };
+        let reply = match message.get("error") {
+            Some(error) => Err(error.clone()),
+            None => Ok(message["result"].clone()),
+        };
+        // The handler may have timed out meanwhile
+        let _ = sender.send(reply);
+        true
+    }
+
+    /// Fail every waiting request and refuse new ones; called when the client stops sending.
+    pub fn close(&self) {
+        self.pending.lock().unwrap().take();
+    }
+}
@@ -0,0 +1,486 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+use crate::child_sandbox;
+use crate::config::{CommandRule, ShellSandboxConfig};
+use crate::fs_sandbox::FsSandbox;
+
+/// Seconds a command may run unless `timeout_secs` is set.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 10;
+
+/// Bytes kept from each of stdout and stderr unless `max_output_bytes` is set.
+pub const DEFAULT_MAX_OUTPUT_BYTES: usize = 4096;
+
+/// Server environment variables passed to commands unless `allowed_env` is set.
+pub const DEFAULT_ALLOWED_ENV: [&str; 5] = ["PATH", "HOME", "LANG", "LC_ALL", "TZ"];
+
+/// Variables passed through from the server that callers may never set themselves.
+pub const PROTECTED_ENV: [&str; 1] = ["PATH"];
+
+/// A command ready to run. `argv[0]` is executed directly; no shell is involved.
+#[derive(Debug, Clone)]
+pub struct ShellRequest {
+    pub argv: Vec<String>,
+    pub cwd: PathBuf,
+    /// The complete environment of the child; nothing else is inherited
+    pub env: BTreeMap<String, String>,
+    pub stdin: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ShellLimits {
+    pub timeout: Duration,
+    pub max_output_bytes: usize,
+}
+
+/// Host-side checks applied to a command after the module's validator accepted it.
+#[derive(Debug, Clone, Copy)]
+pub struct ShellPolicy<'a> {
+    pub allowed_commands: &'a [String],
+    /// Argument rule for the requested program, if one is configured
+    pub rule: Option<&'a CommandRule>,
+    /// Roots that path operands and the working directory must lie in
+    pub sandbox: &'a FsSandbox,
+    pub limits: ShellLimits,
+    /// Kernel-level confinement of the child process, if configured
+    pub isolation: Option<&'a ShellSandboxConfig>,
+}
+
+/// Captured result of a command started by `run`.
+#[derive(Debug, Clone)]
+pub struct ShellOutput {
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+    pub stdout_truncated: bool,
+    pub stderr_truncated: bool,
+}
+
+impl ShellOutput {
+    pub fn truncated(&self) -> bool {
+        self.stdout_truncated || self.stderr_truncated
+    }
+
+    pub fn to_text(&self) -> String {
+        let label = |truncated: bool| if truncated { " (truncated)" } else { "" };
+        format!(
+            "Shell execution completed.\nExit code: {}\n\nSTDOUT{}:\n{}\n\nSTDERR{}:\n{}",
+            self.exit_code,
+            label(self.stdout_truncated),
+            self.stdout,
+            label(self.stderr_truncated),
+            self.stderr
+        )
+    }
+}
+
+/// Split a command line into arguments the way a POSIX shell would, without expanding anything.
+///
+/// Whitespace separates arguments; single quotes keep their contents literally; double
+/// quotes allow `\"`, `\\`, `\$` and `` \` `` escapes; a backslash outside quotes escapes
+/// the next character.
+pub fn split_command(command: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    // Distinguishes `''` (an empty argument) from no argument at all
+    let mut in_arg = false;
+    let mut chars = command.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            c if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            '\'' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err(anyhow::anyhow!("Unterminated single quote in command")),
+                    }
+                }
+            }
+            '"' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => current.push(c),
+                            Some(c) => {
+                                current.push('\\');
+                                current.push(c);
+                            }
+                            None => return Err(anyhow::anyhow!("Unterminated double quote in command")),
+                        },
+                        Some(c) => current.push(c),
+                        None => return Err(anyhow::anyhow!("Unterminated double quote in command")),
+                    }
+                }
+            }
+            '\\' => {
+                in_arg = true;
+                match chars.next() {
+                    Some(c) => current.push(c),
+                    None => return Err(anyhow::anyhow!("Command ends with a dangling backslash")),
+                }
+            }
+            c => {
+                in_arg = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+/// Quote arguments back into a single command line that `split_command` parses to the same argv.
+pub fn join_command(argv: &[String]) -> String {
+    argv.iter()
+        .map(|arg| {
+            let plain = !arg.is_empty()
+                && arg.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '/' | '-' | '_' | '=' | ',' | ':' | '+' | '@' | '%'));
+            if plain {
+                arg.clone()
+            } else {
+                format!("'{}'", arg.replace('\'', r"'\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Check a command's arguments against its configured rule.
+///
+/// Relative path operands are resolved against the command's working directory. Values
+/// attached to flags (`--output=FILE`, `-oFILE`) are checked like operands. A rule that
+/// checks operands but lists no `allowed_flags` accepts no flags at all, since a flag
+/// could otherwise carry a path or value past the checks.
+pub fn check_arguments(rule: &CommandRule, request: &ShellRequest, sandbox: &FsSandbox) -> Result<()> {
+    let (program, args) = request.argv.split_first().ok_or_else(|| anyhow::anyhow!("Empty command"))?;
+
+    if let Some(max) = rule.max_args
+        && args.len() > max
+    {
+        return Err(anyhow::anyhow!("'{}' accepts at most {} arguments, got {}", program, max, args.len()));
+    }
+
+    let pattern = rule.arg_pattern.as_deref()
+        .map(|pattern| regex::Regex::new(&format!("^(?:{})$", pattern)))
+        .transpose()
+        .with_context(|| format!("Invalid arg_pattern for '{}'", program))?;
+    let check_operand = |arg: &str| -> Result<()> {
+        if let Some(pattern) = &pattern
+            && !pattern.is_match(arg)
+        {
+            return Err(anyhow::anyhow!("Argument '{}' of '{}' does not match the allowed pattern", arg, program));
+        }
+        if let Some(access) = rule.path_args {
+            let path = request.cwd.join(arg);
+            sandbox.resolve(&path.to_string_lossy(), access)
+                .with_context(|| format!("Argument '{}' of '{}' is not an allowed path", arg, program))?;
+        }
+        Ok(())
+    };
+    let checks_operands = pattern.is_some() || rule.path_args.is_some();
+
+    let mut operands_only = false;
+    for arg in args {
+        if !operands_only && arg == "--" {
+            operands_only = true;
+            continue;
+        }
+        if !operands_only && arg.len() > 1 && arg.starts_with('-') {
+            let values = match &rule.allowed_flags {
+                Some(allowed) => flag_values(arg, allowed)
+                    .ok_or_else(|| anyhow::anyhow!("Flag '{}' is not allowed for '{}'", arg, program))?,
+                None if checks_operands => {
+                    return Err(anyhow::anyhow!(
+                        "Flag '{}' is not allowed for '{}': its rule checks operands but lists no allowed_flags",
+                        arg,
+                        program
+                    ));
+                }
+                None => Vec::new(),
+            };
+            for value in values {
+                check_operand(value)?;
+            }
+            continue;
+        }
+        check_operand(arg)?;
+    }
+    Ok(())
+}
+
+/// Whether a flag is allowed and, if so, the values that may be attached to it: the
+/// `value` of `--name=value`, or for `-lofile` each suffix following an allowed letter,
+/// since any of `-l` and `-o` may be the one taking a value. A cluster made only of
+/// allowed letters, like `-la`, carries no value.
+fn flag_values<'a>(flag: &'a str, allowed: &[String]) -> Option<Vec<&'a str>> {
+    if allowed.iter().any(|a| a == flag) {
+        return Some(Vec::new());
+    }
+    if let Some(long) = flag.strip_prefix("--") {
+        let (name, value) = match long.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (long, None),
+        };
+        return allowed.iter().any(|a| a.strip_prefix("--") == Some(name)).then(|| value.into_iter().collect());
+    }
+
+    let letters = &flag[1..];
+    let short = |c: char| allowed.iter().any(|a| a.strip_prefix('-').is_some_and(|s| s.chars().eq([c])));
+    if letters.chars().all(short) {
+        return Some(Vec::new());
+    }
+    let values: Vec<&str> = letters.char_indices()
+        .take_while(|(_, c)| short(*c))
+        .map(|(i, c)| &letters[i + c.len_utf8()..])
+        .collect();
+    (!values.is_empty()).then_some(values)
+}
+
+/// Run a command to completion, killing it if it outlives the timeout.
+///
+/// Output beyond the limit is read and discarded so a chatty command cannot block on a
+/// full pipe; what is kept is cut at a character boundary.
+pub async fn run(request: &ShellRequest, policy: &ShellPolicy<'_>) -> Result<ShellOutput> {
+    let (program, args) = request.argv.split_first().ok_or_else(|| anyhow::anyhow!("Empty command"))?;
+    let limits = policy.limits;
+
+    let mut command = tokio::process::Command::new(resolve_program(program)?);
+    #[cfg(unix)]
+    command.arg0(program);
+    command
+        .args(args)
+        .current_dir(&request.cwd)
+        .env_clear()
+        .envs(&request.env)
+        .kill_on_drop(true)
+        .stdin(if request.stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // Removed when this function returns, after the child has exited or been killed
+    let _scratch = match policy.isolation {
+        Some(config) => child_sandbox::apply(&mut command, config)?,
+        None => None,
+    };
+
+    let mut child = command.spawn()
+        .with_context(|| format!("Failed to spawn command: {}", program))?;
+
+    if let (Some(mut pipe), Some(input)) = (child.stdin.take(), request.stdin.clone()) {
+        tokio::spawn(async move {
+            // The command may exit without reading its input; that is not an error
+            let _ = pipe.write_all(&input).await;
+        });
+    }
+
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let max = limits.max_output_bytes;
+    let finished = tokio::time::timeout(limits.timeout, async {
+        tokio::join!(read_capped(stdout, max), read_capped(stderr, max), child.wait())
+    })
+    .await;
+
+    let (stdout, stderr, status) = match finished {
+        Ok(results) => results,
+        Err(_) => return Err(anyhow::anyhow!("Command timed out after {}s", limits.timeout.as_secs())),
+    };
+    let (stdout, stdout_truncated) = stdout.context("Failed to read command output")?;
+    let (stderr, stderr_truncated) = stderr.context("Failed to read command output")?;
+    let status = status.context("Failed to wait for command output")?;
+
+    Ok(ShellOutput {
+        exit_code: status.code().unwrap_or(-1),
+        stdout: decode(stdout, stdout_truncated),
+        stderr: decode(stderr, stderr_truncated),
+        stdout_truncated,
+        stderr_truncated,
+    })
+}
+
+/// Find a bare program name on the server's own `PATH`, so neither the command's
+/// environment nor its working directory decides which binary an allowed name runs.
+fn resolve_program(program: &str) -> Result<PathBuf> {
+    if program.contains('/') {
+        return Ok(PathBuf::from(program));
+    }
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path)
+        .filter(|dir| dir.is_absolute())
+        .map(|dir| dir.join(program))
+        .find(|candidate| is_executable(candidate))
+        .ok_or_else(|| anyhow::anyhow!("Command not found on the server's PATH: {}", program))
+}
+
+fn is_executable(path: &std::path::Path) -> bool {
+    let Ok(meta) = std::fs::metadata(path) else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        meta.is_file() && meta.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        meta.is_file()
+    }
+}
+
+async fn read_capped<R: AsyncRead + Unpin>(reader: Option<R>, max: usize) -> std::io::Result<(Vec<u8>, bool)> {
+    let Some(mut reader) = reader else {
+        return Ok((Vec::new(), false));
+    };
+    let mut kept = Vec::new();
+    let mut truncated = false;
+    let mut buffer = [0u8; 8192];
+    loop {
+        let n = reader.read(&mut buffer).await?;
+        if n == 0 {
+            return Ok((kept, truncated));
+        }
+        let room = max - kept.len();
+        if n > room {
+            truncated = true;
+        }
+        kept.extend_from_slice(&buffer[..n.min(room)]);
+    }
+}
+
+fn decode(mut bytes: Vec<u8>, truncated: bool) -> String {
+    // The byte limit may have split the last character
+    if truncated
+        && let Err(e) = std::str::from_utf8(&bytes)
+        && e.error_len().is_none()
+    {
+        bytes.truncate(e.valid_up_to());
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FsAccess;
+    use crate::test_support::SandboxFixture;
+
+    fn fixture() -> SandboxFixture {
+        let fixture = SandboxFixture::read_write();
+        fixture.write("inside.txt", "inside");
+        fixture
+    }
+
+    fn check(fixture: &SandboxFixture, rule: &CommandRule, argv: &[&str]) -> Result<()> {
+        let request = ShellRequest {
+            argv: argv.iter().map(|arg| arg.to_string()).collect(),
+            cwd: fixture.base.clone(),
+            env: BTreeMap::new(),
+            stdin: None,
+        };
+        check_arguments(rule, &request, &fixture.sandbox)
+    }
+
+    fn path_rule() -> CommandRule {
+        CommandRule {
+            allowed_flags: Some(["-l", "-a", "-o", "--output", "--file"].map(String::from).to_vec()),
+            path_args: Some(FsAccess::ReadOnly),
+            ..CommandRule::default()
+        }
+    }
+
+    #[test]
+    fn operands_and_flag_values_inside_roots_are_accepted() {
+        let fixture = fixture();
+        for argv in [
+            &["cat", "inside.txt"][..],
+            &["cat", "-la", "inside.txt"],
+            &["cat", "--output=inside.txt"],
+            &["cat", "-oinside.txt"],
+            &["cat", "--file", "inside.txt"],
+        ] {
+            check(&fixture, &path_rule(), argv).unwrap_or_else(|e| panic!("{:?}: {:#}", argv, e));
+        }
+    }
+
+    #[test]
+    fn flag_values_outside_roots_are_rejected() {
+        let fixture = fixture();
+        for argv in [
+            &["cat", "--output=/etc/passwd"][..],
+            &["cat", "-o/etc/passwd"],
+            &["cat", "-lo/etc/passwd"],
+            &["cat", "--file", "/etc/passwd"],
+            &["cat", "../inside.txt"],
+            &["cat", "--", "-o/etc/passwd"],
+        ] {
+            assert!(check(&fixture, &path_rule(), argv).is_err(), "{:?} should be rejected", argv);
+        }
+    }
+
+    #[test]
+    fn attached_values_after_flag_clusters_are_checked() {
+        let fixture = fixture();
+        // `-lo/etc/passwd` is `-l -o /etc/passwd` to getopt, even where `o/etc/passwd` could be created
+        let rule = CommandRule { path_args: Some(FsAccess::ReadWrite), ..path_rule() };
+        assert!(check(&fixture, &rule, &["tee", "-lo/etc/passwd"]).is_err());
+        check(&fixture, &rule, &["tee", "-loout.txt"]).unwrap();
+    }
+
+    #[test]
+    fn bare_programs_resolve_on_the_server_path() {
+        let sh = resolve_program("sh").unwrap();
+        assert!(sh.is_absolute(), "{}", sh.display());
+        assert!(resolve_program("wasmforge-no-such-program").is_err());
+        assert_eq!(resolve_program("./local").unwrap(), PathBuf::from("./local"));
+    }
+
+    #[test]
+    fn unlisted_flags_are_rejected() {
+        let fixture = fixture();
+        assert!(check(&fixture, &path_rule(), &["cat", "-x"]).is_err());
+        assert!(check(&fixture, &path_rule(), &["cat", "--exec=rm"]).is_err());
+        assert!(check(&fixture, &path_rule(), &["cat", "-lx"]).is_err());
+    }
+
+    #[test]
+    fn rules_checking_operands_without_allowed_flags_accept_no_flags() {
+        let fixture = fixture();
+        let rule = CommandRule { path_args: Some(FsAccess::ReadOnly), ..CommandRule::default() };
+        assert!(check(&fixture, &rule, &["cat", "-n", "inside.txt"]).is_err());
+        assert!(check(&fixture, &rule, &["cat", "--output=/etc/passwd"]).is_err());
+        check(&fixture, &rule, &["cat", "inside.txt"]).unwrap();
+
+        // Without operand checks there is nothing for a flag to slip past
+        let rule = CommandRule { max_args: Some(2), ..CommandRule::default() };
+        check(&fixture, &rule, &["ls", "-la", "/etc"]).unwrap();
+        assert!(check(&fixture, &rule, &["ls", "-l", "-a", "/etc"]).is_err());
+    }
+
+    #[test]
+    fn flag_values_must_match_the_pattern() {
+        let fixture = fixture();
+        let rule = CommandRule {
+            allowed_flags: Some(vec!["--name".to_string(), "-n".to_string()]),
+            arg_pattern: Some("[a-z]+".to_string()),
+            ..CommandRule::default()
+        };
+        check(&fixture, &rule, &["greet", "--name=alice", "bob"]).unwrap();
+        assert!(check(&fixture, &rule, &["greet", "--name=$(id)"]).is_err());
+        assert!(check(&fixture, &rule, &["greet", "-nALICE"]).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+use std::path::{Path, PathBuf};
+
+use crate::config::{FilesystemConfig, FsAccess, FsRoot, ToolAnnotations};
+use crate::fs_sandbox::FsSandbox;
+use crate::tool_discovery::DiscoveredTool;
+use crate::tool_kinds::ToolKind;
+use crate::wasm_executor::FuncSignature;
+
+/// A temporary directory with a sandbox over roots inside it, removed on drop.
+pub struct SandboxFixture {
+    /// Canonical, so tests compare against what the sandbox resolves even if the temp dir
+    /// is behind a symlink
+    pub base: PathBuf,
+    pub sandbox: FsSandbox,
+}
+
+impl SandboxFixture {
+    /// Create each root below a fresh directory; `""` grants the directory itself.
+    pub fn new(roots: &[(&str, FsAccess)], max_read_bytes: Option<u64>) -> Self {
+        let base = std::env::temp_dir().join(format!("wasmforge-test-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&base).unwrap();
+        let base = base.canonicalize().unwrap();
+        let roots = roots.iter()
+            .map(|(relative, access)| {
+                let path = base.join(relative);
+                std::fs::create_dir_all(&path).unwrap();
+                FsRoot { path, access: *access }
+            })
+            .collect();
+        let sandbox = FsSandbox::new(Some(&FilesystemConfig { roots, max_read_bytes }));
+        Self { base, sandbox }
+    }
+
+    /// A single read-write root at the fixture's directory.
+    pub fn read_write() -> Self {
+        Self::new(&[("", FsAccess::ReadWrite)], None)
+    }
+
+    pub fn path(&self, relative: &str) -> String {
+        self.base.join(relative).to_string_lossy().into_owned()
+    }
+
+    /// Write a file, creating its parent directories.
+    pub fn write(&self, relative: &str, contents: &str) -> PathBuf {
+        let path = self.base.join(relative);
+        std::fs::create_dir_all(path.parent().unwrap_or(Path::new("/"))).unwrap();
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for SandboxFixture {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.base);
+    }
+}
+
+/// A discovered tool of the given kind, exporting a function of the same name.
+pub fn tool(module_name: &str, name: &str, kind: ToolKind) -> DiscoveredTool {
+    DiscoveredTool {
+        name: name.to_string(),
+        title: None,
+        module_name: module_name.to_string(),
+        function_name: name.to_string(),
+        description: String::new(),
+        schema: serde_json::json!({"type": "object"}),
+        output_schema: None,
+        annotations: ToolAnnotations::default(),
+        kind,
+        params: Vec::new(),
+        layout: Vec::new(),
+        arguments: Default::default(),
+        capabilities: Vec::new(),
+        signature: FuncSignature { params: Vec::new(), results: Vec::new() },
+        pattern: "numeric".to_string(),
+    }
+}
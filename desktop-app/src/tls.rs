@@ -0,0 +1,291 @@
+use anyhow::{Context, Result};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio_rustls::TlsAcceptor;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use crate::auth::Principal;
+use crate::config::{ClientCertConfig, TlsConfig};
+use crate::fs_sandbox::expand_home;
+
+/// Modification time and size of a file, to notice replacements
+type Stamp = Option<(SystemTime, u64)>;
+
+/// TLS for the TCP transport. Before each handshake the certificate, key and client CA
+/// are checked for changes and re-read, so renewing them needs no restart.
+pub struct TlsReloader {
+    cert: PathBuf,
+    key: PathBuf,
+    client_ca: Option<PathBuf>,
+    clients: Vec<ClientCertConfig>,
+    /// The acceptor in use and the file stamps it was built from
+    current: Mutex<(TlsAcceptor, Vec<Stamp>)>,
+}
+
+impl TlsReloader {
+    /// Read the configured files; unreadable or mismatched ones are a startup error.
+    pub fn load(config: &TlsConfig) -> Result<Self> {
+        let cert = expand_home(&config.cert);
+        let key = expand_home(&config.key);
+        let client_ca = config.client_ca.as_deref().map(expand_home);
+        let acceptor = TlsAcceptor::from(Arc::new(server_config(&cert, &key, client_ca.as_deref())?));
+        let stamps = stamps(&cert, &key, client_ca.as_deref());
+        Ok(Self {
+            current: Mutex::new((acceptor, stamps)),
+            cert,
+            key,
+            client_ca,
+            clients: config.clients.clone(),
+        })
+    }
+
+    /// Whether clients must present a certificate signed by `client_ca`.
+    pub fn requires_client_cert(&self) -> bool {
+        self.client_ca.is_some()
+    }
+
+    /// Acceptor for the next connection. Changed files are re-read first; if they do not
+    /// form a valid configuration (say, the certificate was replaced before its key) the
+    /// previous one stays in use until the files change again.
+    pub fn acceptor(&self) -> TlsAcceptor {
+        let stamps = stamps(&self.cert, &self.key, self.client_ca.as_deref());
+        let mut current = self.current.lock().unwrap();
+        if stamps != current.1 {
+            current.1 = stamps;
+            match server_config(&self.cert, &self.key, self.client_ca.as_deref()) {
+                Ok(config) => {
+                    current.0 = TlsAcceptor::from(Arc::new(config));
+                    eprintln!("🔄 Reloaded TLS certificate {}", self.cert.display());
+                }
+                Err(e) => eprintln!("⚠️  Keeping the previous TLS certificate: {:#}", e),
+            }
+        }
+        current.0.clone()
+    }
+
+    /// Principal for a verified client certificate, from the first `[[tls.clients]]` entry
+    /// matching its subject. `None` without a certificate; an error names an unmatched subject.
+    pub fn client_principal(&self, certs: Option<&[CertificateDer<'_>]>) -> Result<Option<Principal>> {
+        let Some(leaf) = certs.and_then(<[_]>::first) else {
+            return Ok(None);
+        };
+        let (_, cert) = X509Certificate::from_der(leaf).context("Failed to parse client certificate")?;
+        let subject = cert.subject().to_string();
+        let common_name = cert.subject().iter_common_name().next().and_then(|cn| cn.as_str().ok());
+
+        let entry = self.clients.iter().find(|entry| {
+            entry.subject == "*" || entry.subject == subject || Some(entry.subject.as_str()) == common_name
+        });
+        match entry {
+            Some(entry) => Ok(Some(Principal::new(common_name.unwrap_or(&subject), entry.scopes.clone()))),
+            None => Err(anyhow::anyhow!("Client certificate '{}' matches no [[tls.clients]] entry", subject)),
+        }
+    }
+}
+
+fn server_config(cert_path: &Path, key_path: &Path, client_ca: Option<&Path>) -> Result<ServerConfig> {
+    let chain = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("Failed to read TLS certificate: {}", cert_path.display()))?;
+    if chain.is_empty() {
+        return Err(anyhow::anyhow!("No certificate in {}", cert_path.display()));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .with_context(|| format!("Failed to read TLS key: {}", key_path.display()))?;
+
+    let builder = ServerConfig::builder_with_provider(provider()).with_safe_default_protocol_versions()?;
+    let builder = match client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in CertificateDer::pem_file_iter(path)
+                .with_context(|| format!("Failed to read client CA: {}", path.display()))?
+            {
+                let cert = cert.with_context(|| format!("Failed to read client CA: {}", path.display()))?;
+                roots.add(cert).with_context(|| format!("Invalid client CA certificate in {}", path.display()))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider())
+                .build()
+                .with_context(|| format!("No usable client CA in {}", path.display()))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    builder
+        .with_single_cert(chain, key)
+        .with_context(|| format!("TLS key {} does not match certificate {}", key_path.display(), cert_path.display()))
+}
+
+fn stamps(cert: &Path, key: &Path, client_ca: Option<&Path>) -> Vec<Stamp> {
+    [Some(cert), Some(key), client_ca]
+        .into_iter()
+        .flatten()
+        .map(|path| {
+            let meta = std::fs::metadata(path).ok()?;
+            Some((meta.modified().ok()?, meta.len()))
+        })
+        .collect()
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, Issuer, KeyPair};
+    use rustls::pki_types::ServerName;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::TlsConnector;
+
+    struct Generated {
+        cert: String,
+        key: String,
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wasmforge-tls-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn params(common_name: &str) -> CertificateParams {
+        let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        params.distinguished_name.push(DnType::CommonName, common_name);
+        params
+    }
+
+    fn self_signed(common_name: &str) -> Generated {
+        let key = KeyPair::generate().unwrap();
+        let cert = params(common_name).self_signed(&key).unwrap();
+        Generated { cert: cert.pem(), key: key.serialize_pem() }
+    }
+
+    fn signed_by(common_name: &str, issuer: &Issuer<'_, KeyPair>) -> Generated {
+        let key = KeyPair::generate().unwrap();
+        let cert = params(common_name).signed_by(&key, issuer).unwrap();
+        Generated { cert: cert.pem(), key: key.serialize_pem() }
+    }
+
+    fn write(dir: &Path, name: &str, generated: &Generated) -> (PathBuf, PathBuf) {
+        let (cert, key) = (dir.join(format!("{}.pem", name)), dir.join(format!("{}.key", name)));
+        std::fs::write(&cert, &generated.cert).unwrap();
+        std::fs::write(&key, &generated.key).unwrap();
+        (cert, key)
+    }
+
+    fn client(trusted: &str, identity: Option<&Generated>) -> rustls::ClientConfig {
+        let mut roots = RootCertStore::empty();
+        roots.add(CertificateDer::from_pem_slice(trusted.as_bytes()).unwrap()).unwrap();
+        let builder = rustls::ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        match identity {
+            Some(identity) => builder
+                .with_client_auth_cert(
+                    vec![CertificateDer::from_pem_slice(identity.cert.as_bytes()).unwrap()],
+                    PrivateKeyDer::from_pem_slice(identity.key.as_bytes()).unwrap(),
+                )
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        }
+    }
+
+    /// Echo one message over a fresh connection; returns the client certificates the server saw.
+    async fn exchange(acceptor: TlsAcceptor, client: rustls::ClientConfig) -> Result<Vec<CertificateDer<'static>>> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = async {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = acceptor.accept(stream).await?;
+            let mut message = [0u8; 4];
+            stream.read_exact(&mut message).await?;
+            stream.write_all(&message).await?;
+            stream.flush().await?;
+            Ok::<_, anyhow::Error>(stream.get_ref().1.peer_certificates().map(<[_]>::to_vec).unwrap_or_default())
+        };
+        let client = async {
+            let stream = tokio::net::TcpStream::connect(addr).await?;
+            let mut stream = TlsConnector::from(Arc::new(client)).connect(ServerName::try_from("localhost")?, stream).await?;
+            stream.write_all(b"ping").await?;
+            let mut reply = [0u8; 4];
+            stream.read_exact(&mut reply).await?;
+            assert_eq!(&reply, b"ping");
+            Ok::<_, anyhow::Error>(())
+        };
+        let (server, client) = tokio::join!(server, client);
+        client?;
+        server
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serves_certificate_and_reloads_it_when_replaced() {
+        let dir = temp_dir();
+        let first = self_signed("first");
+        let (cert, key) = write(&dir, "server", &first);
+        let tls = TlsReloader::load(&TlsConfig { cert, key, client_ca: None, clients: Vec::new() }).unwrap();
+
+        let peer = exchange(tls.acceptor(), client(&first.cert, None)).await.unwrap();
+        assert!(peer.is_empty());
+
+        // File timestamps are only as fine as the kernel tick
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let second = self_signed("second");
+        write(&dir, "server", &second);
+        assert!(exchange(tls.acceptor(), client(&first.cert, None)).await.is_err());
+        exchange(tls.acceptor(), client(&second.cert, None)).await.unwrap();
+
+        // A key that no longer matches keeps the working certificate in service
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        std::fs::write(dir.join("server.key"), self_signed("stray").key).unwrap();
+        exchange(tls.acceptor(), client(&second.cert, None)).await.unwrap();
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn client_certificates_map_to_principals() {
+        let dir = temp_dir();
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = params("WasmForge Test CA");
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+        std::fs::write(dir.join("ca.pem"), ca_cert.pem()).unwrap();
+        let ca = Issuer::new(ca_params, ca_key);
+
+        let server = self_signed("server");
+        let (cert, key) = write(&dir, "server", &server);
+        let tls = TlsReloader::load(&TlsConfig {
+            cert,
+            key,
+            client_ca: Some(dir.join("ca.pem")),
+            clients: vec![ClientCertConfig { subject: "laptop".to_string(), scopes: vec!["test-module::add".to_string()] }],
+        })
+        .unwrap();
+        assert!(tls.requires_client_cert());
+
+        let laptop = signed_by("laptop", &ca);
+        let peer = exchange(tls.acceptor(), client(&server.cert, Some(&laptop))).await.unwrap();
+        let principal = tls.client_principal(Some(&peer)).unwrap().unwrap();
+        assert_eq!(principal.name, "laptop");
+
+        let stranger = signed_by("stranger", &ca);
+        let peer = exchange(tls.acceptor(), client(&server.cert, Some(&stranger))).await.unwrap();
+        let error = tls.client_principal(Some(&peer)).unwrap_err();
+        assert!(error.to_string().contains("CN=stranger"), "{}", error);
+
+        // Certificates the CA did not sign, and missing ones, fail the handshake
+        let rogue = self_signed("laptop");
+        assert!(exchange(tls.acceptor(), client(&server.cert, Some(&rogue))).await.is_err());
+        assert!(exchange(tls.acceptor(), client(&server.cert, None)).await.is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,24 +1,152 @@
 use anyhow::Result;
+use base64::Engine;
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-use crate::config::{ModuleConfig, ToolConfig};
-use crate::wasm_executor::{WasmExecutor, FuncSignature};
+use crate::config::{ModuleConfig, ToolAnnotations, ToolConfig};
+use crate::manifest::{ManifestTool, ModuleManifest};
+use crate::tool_kinds::ToolKind;
+use crate::wasm_executor::{WasmExecutor, FuncSignature, ALLOC_EXPORT};
+use wasmtime::ValType;
 
 #[derive(Debug, Clone)]
 pub struct DiscoveredTool {
     pub name: String,
+    pub title: Option<String>,
     pub module_name: String,
     pub function_name: String,
     pub description: String,
     pub schema: Value,
+    pub output_schema: Option<Value>,
+    pub annotations: ToolAnnotations,
+    pub kind: ToolKind,
+    /// Argument names passed to the WASM function's parameters (or host inputs), in order
+    pub params: Vec<String>,
+    /// How each of `params` is passed to a `wasm` kind function
+    pub layout: Vec<WasmParam>,
+    /// Host input -> argument name mapping for host-backed kinds
+    pub arguments: HashMap<String, String>,
+    /// Host capabilities the tool exercises
+    pub capabilities: Vec<String>,
+    #[allow(dead_code)]
     pub signature: FuncSignature,
     pub pattern: String,
 }
 
+impl DiscoveredTool {
+    /// Argument carrying a host input, honouring the declared mapping.
+    pub fn argument_name<'a>(&'a self, input: &'a str) -> &'a str {
+        self.arguments.get(input).map_or(input, String::as_str)
+    }
+}
+
+/// How one named argument is lowered onto WASM parameters.
+#[derive(Debug, Clone)]
+pub enum WasmParam {
+    /// A (ptr, len) pair holding UTF-8 text, or decoded bytes when the schema
+    /// declares `"contentEncoding": "base64"`
+    Buffer { base64: bool },
+    Scalar(ValType),
+}
+
+/// A tool declared by a module's manifest and/or its `[[modules.tools]]` config.
+#[derive(Debug, Clone, Default)]
+struct ToolDeclaration {
+    name: String,
+    function: String,
+    kind: Option<ToolKind>,
+    pattern: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    input_schema: Option<Value>,
+    output_schema: Option<Value>,
+    params: Option<Vec<String>>,
+    arguments: Option<HashMap<String, String>>,
+    annotations: Option<ToolAnnotations>,
+    capabilities: Vec<String>,
+    /// Capabilities the config grants this tool
+    granted: Vec<String>,
+}
+
+impl ToolDeclaration {
+    fn from_manifest(tool: &ManifestTool) -> Self {
+        Self {
+            name: tool.name.clone(),
+            function: tool.function.clone(),
+            kind: tool.kind,
+            pattern: tool.pattern.clone(),
+            title: tool.title.clone(),
+            description: tool.description.clone(),
+            input_schema: tool.input_schema.clone(),
+            output_schema: tool.output_schema.clone(),
+            params: tool.params.clone(),
+            arguments: tool.arguments.clone(),
+            annotations: tool.annotations.clone(),
+            capabilities: tool.capabilities.clone(),
+            granted: Vec::new(),
+        }
+    }
+
+    fn from_config(tool: &ToolConfig) -> Self {
+        Self {
+            name: tool.name.clone(),
+            function: tool.function_name.clone(),
+            ..Self::default()
+        }
+        .overlay(tool)
+    }
+
+    /// Apply config values on top of this declaration; config wins field by field.
+    fn overlay(mut self, tool: &ToolConfig) -> Self {
+        // A kind set in the config is the user's grant of the capability it needs
+        if let Some(capability) = tool.kind.and_then(|kind| kind.capability()) {
+            self.declare(capability);
+            self.grant(capability);
+        }
+        // So is naming the commands the tool may run
+        if tool.security.as_ref()
+            .and_then(|security| security.allowed_commands.as_ref())
+            .is_some_and(|list| !list.is_empty())
+        {
+            self.grant("shell");
+        }
+        Self {
+            name: self.name,
+            function: tool.function_name.clone(),
+            kind: tool.kind.or(self.kind),
+            pattern: tool.pattern.clone().or(self.pattern),
+            title: tool.title.clone().or(self.title),
+            description: tool.description.clone().or(self.description),
+            input_schema: tool.parameters.clone().or(self.input_schema),
+            output_schema: tool.output_schema.clone().or(self.output_schema),
+            params: tool.params.clone().or(self.params),
+            arguments: tool.arguments.clone().or(self.arguments),
+            annotations: match (&tool.annotations, &self.annotations) {
+                (Some(config), Some(manifest)) => Some(config.or(manifest)),
+                (config, manifest) => config.clone().or(manifest.clone()),
+            },
+            capabilities: self.capabilities,
+            granted: self.granted,
+        }
+    }
+
+    fn declare(&mut self, capability: &str) {
+        if !self.capabilities.iter().any(|c| c == capability) {
+            self.capabilities.push(capability.to_string());
+        }
+    }
+
+    fn grant(&mut self, capability: &str) {
+        if !self.granted.iter().any(|c| c == capability) {
+            self.granted.push(capability.to_string());
+        }
+    }
+}
+
 pub struct ToolDiscovery {
     discovered_tools: HashMap<String, DiscoveredTool>,
-    tool_configs: HashMap<String, HashMap<String, ToolConfig>>, // module_name -> tool_name -> config
+    tool_configs: HashMap<String, Vec<ToolConfig>>, // module_name -> tool configs
+    module_grants: HashMap<String, Vec<&'static str>>, // module_name -> granted capabilities
 }
 
 impl ToolDiscovery {
@@ -26,17 +154,15 @@ impl ToolDiscovery {
         Self {
             discovered_tools: HashMap::new(),
             tool_configs: HashMap::new(),
+            module_grants: HashMap::new(),
         }
     }
 
     pub fn load_tool_configs(&mut self, modules: &[ModuleConfig]) {
         for module in modules {
+            self.module_grants.insert(module.name.clone(), module.granted_capabilities());
             if let Some(tools) = &module.tools {
-                let mut module_tools = HashMap::new();
-                for tool in tools {
-                    module_tools.insert(tool.name.clone(), tool.clone());
-                }
-                self.tool_configs.insert(module.name.clone(), module_tools);
+                self.tool_configs.insert(module.name.clone(), tools.clone());
             }
         }
     }
@@ -47,193 +173,355 @@ impl ToolDiscovery {
         let all_functions = executor.get_all_functions();
         
         for (module_name, functions) in all_functions {
-            // Check if this module has both validate_url and process_response functions
-            let has_validate_url = functions.contains(&"validate_url".to_string());
-            let has_process_response = functions.contains(&"process_response".to_string());
-            
-            // Create special "fetch" tool if both functions are present
-            if has_validate_url && has_process_response {
-                let fetch_tool = DiscoveredTool {
-                    name: "fetch".to_string(),
-                    module_name: module_name.clone(),
-                    function_name: "fetch".to_string(), // Virtual function name
-                    description: format!("Fetch content from a URL using WASM validation and processing (from module: {})", module_name),
-                    schema: serde_json::json!({
-                        "type": "object",
-                        "properties": {
-                            "url": {"type": "string", "description": "The URL to fetch"}
-                        },
-                        "required": ["url"]
-                    }),
-                    signature: FuncSignature {
-                        params: vec![wasmtime::ValType::I32, wasmtime::ValType::I32],
-                        results: vec![wasmtime::ValType::I32],
-                    },
-                    pattern: "ptr_len_to_i32".to_string(),
+            let declarations = self.declarations_for(&module_name, executor.get_module_manifest(&module_name));
+            let http_granted = executor.http_granted(&module_name);
+
+            for declaration in &declarations {
+                if !functions.contains(&declaration.function) {
+                    eprintln!("✗ Module '{}' declares tool '{}' for missing export '{}'", module_name, declaration.name, declaration.function);
+                    continue;
+                }
+                let Ok(signature) = executor.get_function_signature(&module_name, &declaration.function) else {
+                    continue;
                 };
-                
-                self.discovered_tools.insert("fetch".to_string(), fetch_tool);
-                discovered_count += 1;
+                if let Some(tool) = self.analyze_function(&module_name, declaration, signature, &functions, http_granted) {
+                    let tool_key = format!("{}::{}", module_name, tool.name);
+                    self.discovered_tools.insert(tool_key, tool);
+                    discovered_count += 1;
+                }
             }
-            
-            for function_name in functions {
-                if let Ok(signature) = executor.get_function_signature(&module_name, &function_name) {
-                    if let Some(tool) = self.analyze_function(&module_name, &function_name, signature)? {
-                        let tool_key = format!("{}::{}", module_name, function_name);
-                        self.discovered_tools.insert(tool_key, tool);
-                        discovered_count += 1;
-                    }
+
+            // Exports nobody declared are exposed as plain WASM calls when their signature allows it
+            for function_name in &functions {
+                if declarations.iter().any(|d| &d.function == function_name) {
+                    continue;
+                }
+                let declaration = ToolDeclaration {
+                    name: function_name.clone(),
+                    function: function_name.clone(),
+                    ..ToolDeclaration::default()
+                };
+                if let Ok(signature) = executor.get_function_signature(&module_name, function_name)
+                    && let Some(tool) = self.analyze_function(&module_name, &declaration, signature, &functions, http_granted)
+                {
+                    let tool_key = format!("{}::{}", module_name, tool.name);
+                    self.discovered_tools.insert(tool_key, tool);
+                    discovered_count += 1;
                 }
             }
         }
 
-        println!("✓ Discovered {} tools from WASM modules", discovered_count);
+        eprintln!("✓ Discovered {} tools from WASM modules", discovered_count);
         Ok(discovered_count)
     }
 
+    /// Merge the embedded manifest with config entries.
+    ///
+    /// A config entry refines the manifest tool with the same name or, when it does
+    /// not set a `kind`, the only manifest tool backed by the same function.
+    /// Anything else declares a new tool.
+    fn declarations_for(&self, module_name: &str, manifest: Option<&ModuleManifest>) -> Vec<ToolDeclaration> {
+        let mut declarations: Vec<ToolDeclaration> = manifest
+            .map(|m| m.tools.iter().map(ToolDeclaration::from_manifest).collect())
+            .unwrap_or_default();
+
+        for tool_config in self.tool_configs.get(module_name).into_iter().flatten() {
+            let by_name = declarations.iter().position(|d| d.name == tool_config.name);
+            let by_function = || {
+                if tool_config.kind.is_some() {
+                    return None;
+                }
+                let mut matches = declarations.iter().enumerate().filter(|(_, d)| d.function == tool_config.function_name);
+                match (matches.next(), matches.next()) {
+                    (Some((index, _)), None) => Some(index),
+                    _ => None,
+                }
+            };
+            match by_name.or_else(by_function) {
+                Some(index) => {
+                    let declaration = std::mem::take(&mut declarations[index]);
+                    declarations[index] = declaration.overlay(tool_config);
+                }
+                None => declarations.push(ToolDeclaration::from_config(tool_config)),
+            }
+        }
+
+        for grant in self.module_grants.get(module_name).into_iter().flatten() {
+            declarations.iter_mut().for_each(|declaration| declaration.grant(grant));
+        }
+        declarations
+    }
+
     fn analyze_function(
         &self,
         module_name: &str,
-        function_name: &str,
+        declaration: &ToolDeclaration,
         signature: FuncSignature,
-    ) -> Result<Option<DiscoveredTool>> {
+        exports: &[String],
+        http_granted: bool,
+    ) -> Option<DiscoveredTool> {
+        let function_name = declaration.function.as_str();
+
         // Skip internal or system functions
         if function_name.starts_with('_') || function_name.starts_with("__") {
-            return Ok(None);
+            return None;
         }
 
-        // Determine the function pattern and create appropriate schema
-        // First check if this is a pointer/length function by name
-        let (pattern, schema, description) = if matches!(function_name, "validate_url" | "process_response" | "prepare_http_get" | "prepare_file_read" | "prepare_file_write" | "prepare_shell_exec" | "prepare_recommend_mcps") && signature.matches_pattern("ptr_len_to_i32") {
-            // Handle different ptr_len_to_i32 function types with appropriate schemas
-            let (schema, description) = match function_name {
-                "prepare_http_get" => (
-                    json!({
-                        "type": "object",
-                        "properties": {
-                            "url": {"type": "string", "description": "The URL to fetch via HTTP GET request"}
-                        },
-                        "required": ["url"]
-                    }),
-                    self.generate_description(module_name, function_name, "Fetch content from a URL using async HTTP GET with WASM validation")
-                ),
-                "prepare_file_read" => (
-                    json!({
-                        "type": "object",
-                        "properties": {
-                            "path": {"type": "string", "description": "The file path to read"}
-                        },
-                        "required": ["path"]
-                    }),
-                    self.generate_description(module_name, function_name, "Read file content with WASM path validation")
-                ),
-                "prepare_file_write" => (
-                    json!({
-                        "type": "object",
-                        "properties": {
-                            "path": {"type": "string", "description": "The file path to write to"},
-                            "content": {"type": "string", "description": "The content to write to the file"}
-                        },
-                        "required": ["path", "content"]
-                    }),
-                    self.generate_description(module_name, function_name, "Write content to file with WASM path validation")
-                ),
-                "prepare_shell_exec" => (
-                    json!({
-                        "type": "object",
-                        "properties": {
-                            "command": {"type": "string", "description": "The shell command to execute (validated by WASM and host)"}
-                        },
-                        "required": ["command"]
-                    }),
-                    self.generate_description(module_name, function_name, "Execute a simple shell command with WASM validation")
-                ),
-                "prepare_recommend_mcps" => (
-                    json!({
-                        "type": "object",
-                        "properties": {
-                            "task": {"type": "string", "description": "Describe your task and we'll recommend suitable tools"}
-                        },
-                        "required": ["task"]
-                    }),
-                    self.generate_description(module_name, function_name, "Recommend relevant MCP tools based on a task description")
-                ),
-                _ => (
-                    json!({
-                        "type": "object",
-                        "properties": {
-                            "data": {"type": "string", "description": "Data to process"}
-                        },
-                        "required": ["data"]
-                    }),
-                    self.generate_description(module_name, function_name, "Processes string data and returns an integer status")
-                )
-            };
-            
+        // Skip host ABI exports such as the result buffer accessors
+        if function_name.starts_with("wasmforge_") {
+            return None;
+        }
+
+        let kind = declaration.kind.unwrap_or_default();
+        // Manifests may only claim host behaviour they declare, and only the config grants it
+        if let Some(capability) = kind.capability() {
+            if !declaration.capabilities.iter().any(|c| c == capability) {
+                eprintln!(
+                    "✗ Skipping {}::{}: the {} kind needs the '{}' capability, which the module's manifest does not declare",
+                    module_name, declaration.name, kind.as_str(), capability
+                );
+                return None;
+            }
+            if !declaration.granted.iter().any(|c| c == capability) {
+                let grant = match capability {
+                    "http" => "a [modules.http] allow-list",
+                    "fs.read" => "a [[modules.filesystem.roots]] entry",
+                    "fs.write" => "a read_write [[modules.filesystem.roots]] entry",
+                    _ => "allowed_commands in [modules.security]",
+                };
+                eprintln!(
+                    "✗ Skipping {}::{}: the module's config does not grant the '{}' capability; add {}",
+                    module_name, declaration.name, capability, grant
+                );
+                return None;
+            }
+        }
+        let arguments = declaration.arguments.clone().unwrap_or_default();
+        let argument_name = |input: &str| arguments.get(input).cloned().unwrap_or_else(|| input.to_string());
+
+        let (pattern, schema, description, params, layout) = if kind.is_host_backed() {
+            // Host-backed kinds call the export as a validator over (ptr, len) pairs
+            let max_inputs = kind.host_inputs().len().max(1);
+            if !signature.buffer_pairs().is_some_and(|pairs| (1..=max_inputs).contains(&pairs)) {
+                eprintln!(
+                    "✗ Skipping {}::{}: the {} kind needs a validator taking 1 to {} (ptr, len) pairs and returning i32",
+                    module_name, declaration.name, kind.as_str(), max_inputs
+                );
+                return None;
+            }
+            if kind == ToolKind::Fetch && !exports.iter().any(|e| e == "process_response") {
+                eprintln!("✗ Skipping {}::{}: the fetch kind needs a process_response export", module_name, declaration.name);
+                return None;
+            }
+            let inputs: Vec<String> = kind.host_inputs().iter().map(|input| argument_name(input)).collect();
             (
-                "ptr_len_to_i32".to_string(),
-                schema,
-                description
+                "validator".to_string(),
+                kind.default_schema(argument_name),
+                format!("{} (from module: {})", kind.default_description(), module_name),
+                inputs,
+                Vec::new(),
             )
-        } else if signature.matches_pattern("i32_i32_to_i32") {
+        } else if let Some(pattern) = &declaration.pattern {
+            // JSON in, JSON out: the module owns its argument names and schema
+            if pattern != "json" {
+                eprintln!("✗ Skipping {}::{}: unknown pattern '{}'", module_name, declaration.name, pattern);
+                return None;
+            }
+            if signature.buffer_pairs() != Some(1) {
+                eprintln!("✗ Skipping {}::{}: the json pattern needs a (ptr, len) -> i32 function", module_name, declaration.name);
+                return None;
+            }
             (
-                "i32_i32_to_i32".to_string(),
-                json!({
-                    "type": "object",
-                    "properties": {
-                        "a": {"type": "number", "description": "First integer parameter"},
-                        "b": {"type": "number", "description": "Second integer parameter"}
-                    },
-                    "required": ["a", "b"]
-                }),
-                self.generate_description(module_name, function_name, "Takes two integers and returns an integer")
+                "json".to_string(),
+                json!({"type": "object"}),
+                self.generate_description(module_name, function_name, "Takes a JSON object and returns a JSON document"),
+                Vec::new(),
+                Vec::new(),
             )
-        } else if signature.matches_pattern("no_params_to_i32") {
+        } else {
+            if !signature.results.iter().all(|ty| matches!(ty, ValType::I32 | ValType::I64 | ValType::F32 | ValType::F64)) {
+                // Unsupported signature pattern
+                return None;
+            }
+
+            let params = match &declaration.params {
+                Some(declared) => declared.clone(),
+                None if signature.is_numeric() => Self::default_param_names(declaration.input_schema.as_ref(), signature.params.len()),
+                None => return None,
+            };
+            let Some(layout) = Self::plan_params(&params, declaration.input_schema.as_ref(), &signature) else {
+                eprintln!(
+                    "✗ Skipping {}::{}: declared parameters ({}) do not match the function's {} WASM parameter(s)",
+                    module_name, declaration.name, params.join(", "), signature.params.len()
+                );
+                return None;
+            };
+
+            let mut properties = serde_json::Map::new();
+            for (name, param) in params.iter().zip(&layout) {
+                let property = match param {
+                    WasmParam::Buffer { base64: false } => json!({"type": "string"}),
+                    WasmParam::Buffer { base64: true } => json!({"type": "string", "contentEncoding": "base64"}),
+                    WasmParam::Scalar(ty) => Self::numeric_schema(ty),
+                };
+                properties.insert(name.clone(), property);
+            }
+            let has_buffers = layout.iter().any(|p| matches!(p, WasmParam::Buffer { .. }));
+            let types = |tys: &[ValType]| tys.iter().map(Self::type_name).collect::<Vec<_>>().join(", ");
+            let default_description = if has_buffers {
+                "Processes string data and returns a value".to_string()
+            } else {
+                format!("Takes ({}) and returns ({})", types(&signature.params), types(&signature.results))
+            };
+
             (
-                "no_params_to_i32".to_string(),
+                if has_buffers { "buffers" } else { "numeric" }.to_string(),
                 json!({
                     "type": "object",
-                    "properties": {},
+                    "properties": properties,
+                    "required": params,
                     "additionalProperties": false
                 }),
-                self.generate_description(module_name, function_name, "Takes no parameters and returns an integer")
+                self.generate_description(module_name, function_name, &default_description),
+                params,
+                layout,
             )
-        } else {
-            // Unsupported signature pattern
-            return Ok(None);
         };
 
-        // Check if we have custom configuration for this tool
-        let (final_description, final_schema) = if let Some(module_tools) = self.tool_configs.get(module_name) {
-            if let Some(tool_config) = module_tools.get(function_name) {
-                (
-                    tool_config.description.clone().unwrap_or(description),
-                    tool_config.parameters.clone().unwrap_or(schema),
-                )
-            } else {
-                (description, schema)
-            }
-        } else {
-            (description, schema)
+        // Buffers are written wherever the module allocates them; there is no safe fixed spot
+        if matches!(pattern.as_str(), "validator" | "buffers" | "json") && !exports.iter().any(|e| e == ALLOC_EXPORT) {
+            eprintln!(
+                "✗ Skipping {}::{}: string arguments need a {}(len: i32) -> i32 export",
+                module_name, declaration.name, ALLOC_EXPORT
+            );
+            return None;
+        }
+
+        // Declared metadata overrides the defaults of the kind
+        let default_annotations = match kind {
+            ToolKind::Wasm => ToolKind::wasm_annotations(http_granted, &declaration.capabilities),
+            _ => kind.default_annotations(),
         };
+        let annotations = declaration.annotations.as_ref()
+            .map_or(default_annotations.clone(), |a| a.or(&default_annotations));
+        let capabilities = declaration.capabilities.clone();
 
         // Create namespaced tool name
         let tool_name = if module_name == "test-module" {
             // For backwards compatibility with test module
-            function_name.to_string()
+            declaration.name.clone()
         } else {
-            format!("{}_{}", module_name.replace('-', "_"), function_name)
+            format!("{}_{}", module_name.replace('-', "_"), declaration.name)
         };
 
-        Ok(Some(DiscoveredTool {
+        Some(DiscoveredTool {
             name: tool_name,
+            title: declaration.title.clone().or(kind.default_title().map(str::to_string)),
             module_name: module_name.to_string(),
             function_name: function_name.to_string(),
-            description: final_description,
-            schema: final_schema,
+            description: declaration.description.clone().unwrap_or(description),
+            schema: declaration.input_schema.clone().unwrap_or(schema),
+            output_schema: declaration.output_schema.clone()
+                .or_else(|| (kind == ToolKind::Wasm && pattern != "json").then(|| Self::numeric_output_schema(&signature.results)))
+                .or_else(|| kind.default_output_schema()),
+            annotations,
+            kind,
+            params,
+            layout,
+            arguments,
+            capabilities,
             signature,
             pattern,
-        }))
+        })
+    }
+
+    /// Parameter names for an undeclared numeric function: the schema's `required`
+    /// list when it names every parameter, otherwise `arg0..argN`.
+    fn default_param_names(schema: Option<&Value>, arity: usize) -> Vec<String> {
+        let required: Vec<String> = schema
+            .and_then(|s| s.get("required"))
+            .and_then(|r| r.as_array())
+            .map(|r| r.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+            .unwrap_or_default();
+        if required.len() == arity {
+            required
+        } else {
+            (0..arity).map(|i| format!("arg{}", i)).collect()
+        }
+    }
+
+    /// Map named arguments onto the function's parameters: arguments declared as
+    /// strings take a (ptr, len) pair of i32s, everything else one numeric parameter.
+    fn plan_params(params: &[String], schema: Option<&Value>, signature: &FuncSignature) -> Option<Vec<WasmParam>> {
+        let mut layout = Vec::with_capacity(params.len());
+        let mut wasm_params = signature.params.iter();
+
+        for name in params {
+            let property = schema.and_then(|s| s.pointer(&format!("/properties/{}", name)));
+            if property.and_then(|p| p.get("type")).and_then(|t| t.as_str()) == Some("string") {
+                let (Some(ValType::I32), Some(ValType::I32)) = (wasm_params.next(), wasm_params.next()) else {
+                    return None;
+                };
+                let base64 = property.and_then(|p| p.get("contentEncoding")).and_then(|e| e.as_str()) == Some("base64");
+                layout.push(WasmParam::Buffer { base64 });
+            } else {
+                match wasm_params.next() {
+                    Some(ty @ (ValType::I32 | ValType::I64 | ValType::F32 | ValType::F64)) => layout.push(WasmParam::Scalar(ty.clone())),
+                    _ => return None,
+                }
+            }
+        }
+
+        wasm_params.next().is_none().then_some(layout)
+    }
+
+    fn numeric_schema(ty: &ValType) -> Value {
+        match ty {
+            ValType::I32 => json!({"type": "integer", "minimum": i32::MIN, "maximum": i32::MAX}),
+            ValType::I64 => json!({"type": "integer", "minimum": i64::MIN, "maximum": i64::MAX}),
+            ValType::F32 => json!({"type": "number", "minimum": -f32::MAX, "maximum": f32::MAX}),
+            _ => json!({"type": "number"}),
+        }
+    }
+
+    fn numeric_output_schema(results: &[ValType]) -> Value {
+        let result_schema = |ty: &ValType| match ty {
+            ValType::I32 | ValType::I64 => json!({"type": "integer"}),
+            // NaN and infinities are reported as null
+            _ => json!({"type": ["number", "null"]}),
+        };
+        match results {
+            [] => json!({"type": "object", "properties": {}}),
+            [ty] => json!({
+                "type": "object",
+                "properties": {
+                    "result": result_schema(ty)
+                },
+                "required": ["result"]
+            }),
+            _ => json!({
+                "type": "object",
+                "properties": {
+                    "results": {
+                        "type": "array",
+                        "prefixItems": results.iter().map(result_schema).collect::<Vec<_>>(),
+                        "minItems": results.len(),
+                        "maxItems": results.len()
+                    }
+                },
+                "required": ["results"]
+            }),
+        }
+    }
+
+    fn type_name(ty: &ValType) -> &'static str {
+        match ty {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+            ValType::F32 => "f32",
+            ValType::F64 => "f64",
+            _ => "?",
+        }
     }
 
     fn generate_description(&self, module_name: &str, function_name: &str, default: &str) -> String {
@@ -243,13 +531,6 @@ impl ToolDiscovery {
             "subtract" | "sub" => "Subtract two numbers using WebAssembly",
             "multiply" | "mul" => "Multiply two numbers using WebAssembly",
             "divide" | "div" => "Divide two numbers using WebAssembly",
-            "validate_url" => "Validate URL format using WebAssembly",
-            "process_response" => "Process HTTP response using WebAssembly",
-            "prepare_http_get" => "Fetch content from a URL using async HTTP GET with WASM validation",
-            "prepare_file_read" => "Read file content with WASM path validation",
-            "prepare_file_write" => "Write content to file with WASM path validation",
-            "prepare_shell_exec" => "Execute a simple shell command with WASM validation",
-            "prepare_recommend_mcps" => "Recommend relevant MCP tools based on a task description",
             "hash" | "sha256" => "Calculate hash of input data",
             "encrypt" => "Encrypt data using WebAssembly",
             "decrypt" => "Decrypt data using WebAssembly",
@@ -265,10 +546,7 @@ impl ToolDiscovery {
         format!("{} (from module: {})", description, module_name)
     }
 
-    pub fn get_all_tools(&self) -> &HashMap<String, DiscoveredTool> {
-        &self.discovered_tools
-    }
-
+    #[allow(dead_code)]
     pub fn get_tool(&self, tool_name: &str) -> Option<&DiscoveredTool> {
         self.discovered_tools.get(tool_name)
     }
@@ -280,29 +558,81 @@ impl ToolDiscovery {
         }
 
         // Then try to find by tool name (without module prefix)
-        for tool in self.discovered_tools.values() {
-            if tool.name == name {
-                return Some(tool);
-            }
-        }
+        self.discovered_tools.values().find(|tool| tool.name == name)
+    }
 
-        None
+    pub fn tools_mut(&mut self) -> impl Iterator<Item = &mut DiscoveredTool> {
+        self.discovered_tools.values_mut()
     }
 
-    pub fn get_mcp_tools_schema(&self) -> Value {
+    /// All tools in a stable order: by module, then by tool name.
+    pub fn sorted_tools(&self) -> Vec<&DiscoveredTool> {
+        let mut tools: Vec<&DiscoveredTool> = self.discovered_tools.values().collect();
+        tools.sort_by(|a, b| (&a.module_name, &a.name).cmp(&(&b.module_name, &b.name)));
+        tools
+    }
+
+    /// One page of the `tools/list` result, limited to the tools `visible` accepts.
+    ///
+    /// The cursor is an opaque token naming the last tool of the previous page, so
+    /// pages stay consistent even if tools are added or removed between requests.
+    pub fn get_mcp_tools_schema(&self, cursor: Option<&str>, page_size: usize, visible: impl Fn(&DiscoveredTool) -> bool) -> Result<Value> {
+        let after = cursor.map(Self::decode_cursor).transpose()?;
+        let mut sorted = self.sorted_tools();
+        sorted.retain(|tool| visible(tool));
+        let start = match &after {
+            Some((module_name, name)) => sorted
+                .iter()
+                .position(|t| (&t.module_name, &t.name) > (module_name, name))
+                .unwrap_or(sorted.len()),
+            None => 0,
+        };
+        let page: Vec<&DiscoveredTool> = sorted.iter().skip(start).take(page_size.max(1)).copied().collect();
+        let next_cursor = match page.last() {
+            Some(last) if start + page.len() < sorted.len() => Some(Self::encode_cursor(last)),
+            _ => None,
+        };
+
         let mut tools = Vec::new();
 
-        for tool in self.discovered_tools.values() {
-            tools.push(json!({
+        for tool in page {
+            let mut entry = json!({
                 "name": tool.name,
                 "description": tool.description,
                 "inputSchema": tool.schema
-            }));
+            });
+            if let Some(title) = &tool.title {
+                entry["title"] = json!(title);
+            }
+            if let Some(output_schema) = &tool.output_schema {
+                entry["outputSchema"] = output_schema.clone();
+            }
+            entry["annotations"] = tool.annotations.to_json();
+            tools.push(entry);
         }
 
-        json!({ "tools": tools })
+        let mut result = json!({ "tools": tools });
+        if let Some(next_cursor) = next_cursor {
+            result["nextCursor"] = json!(next_cursor);
+        }
+        Ok(result)
+    }
+
+    fn encode_cursor(tool: &DiscoveredTool) -> String {
+        let key = json!([tool.module_name, tool.name]).to_string();
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(key)
     }
 
+    fn decode_cursor(cursor: &str) -> Result<(String, String)> {
+        let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(cursor)
+            .map_err(|_| anyhow::anyhow!("Invalid cursor"))?;
+        let (module_name, name): (String, String) = serde_json::from_slice(&bytes)
+            .map_err(|_| anyhow::anyhow!("Invalid cursor"))?;
+        Ok((module_name, name))
+    }
+
+    #[allow(dead_code)]
     pub fn get_tools_by_module(&self, module_name: &str) -> Vec<&DiscoveredTool> {
         self.discovered_tools
             .values()
@@ -310,43 +640,155 @@ impl ToolDiscovery {
             .collect()
     }
 
+    #[allow(dead_code)]
     pub fn get_tool_count(&self) -> usize {
         self.discovered_tools.len()
     }
 
+    #[allow(dead_code)]
     pub fn clear(&mut self) {
         self.discovered_tools.clear();
     }
 
     pub fn print_discovered_tools(&self) {
         if self.discovered_tools.is_empty() {
-            println!("No tools discovered");
+            eprintln!("No tools discovered");
             return;
         }
 
-        println!("\n📋 Discovered Tools:");
-        println!("┌─────────────────────────────────────────────────────────────────┐");
+        eprintln!("\n📋 Discovered Tools:");
+        eprintln!("┌─────────────────────────────────────────────────────────────────┐");
         
-        let mut tools_by_module: HashMap<String, Vec<&DiscoveredTool>> = HashMap::new();
-        for tool in self.discovered_tools.values() {
+        let mut tools_by_module: BTreeMap<String, Vec<&DiscoveredTool>> = BTreeMap::new();
+        for tool in self.sorted_tools() {
             tools_by_module
                 .entry(tool.module_name.clone())
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(tool);
         }
 
         for (module_name, tools) in tools_by_module {
-            println!("│ 📦 Module: {:<52} │", module_name);
-            println!("├─────────────────────────────────────────────────────────────────┤");
+            eprintln!("│ 📦 Module: {:<52} │", module_name);
+            eprintln!("├─────────────────────────────────────────────────────────────────┤");
             
             for tool in tools {
-                println!("│ 🔧 {:<15} {} │", tool.name, tool.description);
-                println!("│    └─ Pattern: {:<47} │", tool.pattern);
+                eprintln!("│ 🔧 {:<15} {} │", tool.name, tool.description);
+                eprintln!("│    └─ Kind: {:<50} │", format!("{} ({})", tool.kind.as_str(), tool.pattern));
+                if !tool.capabilities.is_empty() {
+                    eprintln!("│    └─ Capabilities: {:<42} │", tool.capabilities.join(", "));
+                }
             }
-            println!("├─────────────────────────────────────────────────────────────────┤");
+            eprintln!("├─────────────────────────────────────────────────────────────────┤");
         }
-        println!("└─────────────────────────────────────────────────────────────────┘");
-        println!("Total: {} tools", self.discovered_tools.len());
+        eprintln!("└─────────────────────────────────────────────────────────────────┘");
+        eprintln!("Total: {} tools", self.discovered_tools.len());
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HttpConfig;
+
+    /// Module with an undeclared `double` export and a `fetch_page` export whose
+    /// embedded manifest declares the `http` capability.
+    const MODULE_WAT: &str = r#"
+        (module
+          (@custom "wasmforge" "{\"tools\": [{\"name\": \"fetch_page\", \"function\": \"fetch_page\", \"capabilities\": [\"http\"]}]}")
+          (memory (export "memory") 1)
+          (func (export "double") (param i32) (result i32) (i32.mul (local.get 0) (i32.const 2)))
+          (func (export "fetch_page") (param i32) (result i32) (local.get 0)))
+    "#;
+
+    fn annotations(http: Option<HttpConfig>) -> HashMap<String, ToolAnnotations> {
+        let mut executor = WasmExecutor::new().unwrap();
+        executor.load_module_bytes("annotated", &wat::parse_str(MODULE_WAT).unwrap(), http).unwrap();
+        let mut discovery = ToolDiscovery::new();
+        discovery.discover_tools_from_executor(&executor).unwrap();
+        discovery.sorted_tools().into_iter().map(|tool| (tool.function_name.clone(), tool.annotations.clone())).collect()
+    }
+
+    #[test]
+    fn wasm_tools_without_egress_are_read_only() {
+        let annotations = annotations(None);
+        assert_eq!(annotations["double"], ToolAnnotations::new(true, false, true, false));
+        assert_eq!(annotations["fetch_page"], ToolAnnotations::new(false, true, false, true));
+    }
+
+    #[test]
+    fn wasm_tools_of_modules_granted_http_are_open_world() {
+        let annotations = annotations(Some(HttpConfig::default()));
+        assert_eq!(annotations["double"], ToolAnnotations::new(false, true, false, true));
+        assert_eq!(annotations["fetch_page"], ToolAnnotations::new(false, true, false, true));
+    }
+
+    /// Module whose manifest declares two `file_read` tools over one validator, only
+    /// one of them with the `fs.read` capability.
+    const KINDS_WAT: &str = r#"
+        (module
+          (@custom "wasmforge" "{\"tools\": [{\"name\": \"declared\", \"function\": \"check\", \"kind\": \"file_read\", \"capabilities\": [\"fs.read\"]}, {\"name\": \"undeclared\", \"function\": \"check\", \"kind\": \"file_read\"}]}")
+          (memory (export "memory") 1)
+          (func (export "wasmforge_alloc") (param i32) (result i32) (i32.const 1024))
+          (func (export "check") (param i32 i32) (result i32) (i32.const 1)))
+    "#;
+
+    /// Tools discovered in `KINDS_WAT` under the given `[[modules]]` entry of the config.
+    fn discover_kinds(module: &str) -> Vec<String> {
+        let mut executor = WasmExecutor::new().unwrap();
+        executor.load_module_bytes("kinds", &wat::parse_str(KINDS_WAT).unwrap(), None).unwrap();
+        let module: ModuleConfig = toml::from_str(&format!(
+            "name = \"kinds\"\nenabled = true\nsource = {{ type = \"local\", path = \"kinds.wasm\" }}\n{}",
+            module
+        )).unwrap();
+        let mut discovery = ToolDiscovery::new();
+        discovery.load_tool_configs(&[module]);
+        discovery.discover_tools_from_executor(&executor).unwrap();
+        discovery.sorted_tools().into_iter().map(|tool| tool.name.clone()).collect()
+    }
+
+    const READ_ROOT: &str = "[[filesystem.roots]]\npath = \"/tmp\"\naccess = \"read_only\"\n";
+
+    #[test]
+    fn manifest_host_kinds_need_their_capability() {
+        assert_eq!(discover_kinds(READ_ROOT), ["kinds_declared"]);
+    }
+
+    #[test]
+    fn manifest_host_kinds_need_a_config_grant() {
+        assert!(discover_kinds("").is_empty());
+        // `[modules.security]` grants shell, not file access
+        assert!(discover_kinds("[security]\nallowed_commands = [\"ls\"]\n").is_empty());
+    }
+
+    #[test]
+    fn config_kinds_grant_their_capability() {
+        let config = "[[tools]]\nname = \"undeclared\"\nfunction_name = \"check\"\nkind = \"file_read\"\n";
+        assert_eq!(discover_kinds(config), ["kinds_undeclared"]);
+        assert_eq!(discover_kinds(&format!("{}{}", READ_ROOT, config)), ["kinds_declared", "kinds_undeclared"]);
+    }
+
+    #[test]
+    fn module_config_grants_capabilities() {
+        let grants = |toml: &str| {
+            let module: ModuleConfig = toml::from_str(&format!(
+                "name = \"m\"\nenabled = true\nsource = {{ type = \"local\", path = \"m.wasm\" }}\n{}",
+                toml
+            )).unwrap();
+            module.granted_capabilities()
+        };
+        assert!(grants("").is_empty());
+        assert_eq!(grants(READ_ROOT), ["fs.read"]);
+        assert_eq!(grants("[[filesystem.roots]]\npath = \"/tmp\"\naccess = \"read_write\"\n"), ["fs.read", "fs.write"]);
+        assert_eq!(grants("[http]\n"), ["http"]);
+        assert_eq!(grants("metadata = { allowed_commands_csv = \"ls\" }\n"), ["shell"]);
+        assert!(grants("[security]\nallowed_commands = []\n").is_empty());
+    }
+
+    #[test]
+    fn declared_capabilities_beyond_reading_files_have_side_effects() {
+        let capabilities = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        assert_eq!(ToolKind::wasm_annotations(false, &capabilities(&["fs.read"])), ToolAnnotations::new(true, false, true, false));
+        assert_eq!(ToolKind::wasm_annotations(false, &capabilities(&["fs.write"])), ToolAnnotations::new(false, true, false, false));
+    }
+}
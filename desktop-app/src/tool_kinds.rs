@@ -0,0 +1,978 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use std::collections::BTreeMap;
+
+use crate::audit::Usage;
+use crate::auth::Principal;
+use crate::config::{Config, ToolAnnotations};
+use crate::fs_sandbox::FsSandbox;
+use crate::config::FsAccess;
+use crate::fs_tools::{self, ReadRange, WriteMode, WriteOptions};
+use crate::policy::{EffectivePolicy, DEFAULT_HTTP_BODY_CHARS, DEFAULT_PREVIEW_CHARS};
+use crate::shell::{self, ShellLimits, ShellPolicy, ShellRequest};
+use crate::tool_discovery::{DiscoveredTool, ToolDiscovery, WasmParam};
+use crate::tool_result::{integral_i64, required_str, required_val, truncate_chars, val_to_json, InvalidParams, ToolContent, ToolResult};
+use crate::wasm_executor::{HttpRequestSpec, WasmArg, WasmExecutor};
+
+const HTTP_METHODS: [&str; 6] = ["GET", "POST", "PUT", "PATCH", "DELETE", "HEAD"];
+
+/// Registry of the ways a tool can be executed.
+///
+/// `Wasm` tools are a plain call into the module. Every other kind is host-backed:
+/// the module's export validates the request and the host performs the operation.
+/// Modules opt into a host kind explicitly through their manifest or config
+/// (`kind = "http_get"`); nothing is inferred from export names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolKind {
+    #[default]
+    Wasm,
+    HttpGet,
+    /// Any-method HTTP request returning status, headers and body
+    Http,
+    FileRead,
+    FileWrite,
+    ListDir,
+    FileStat,
+    Glob,
+    Grep,
+    FileAppend,
+    MakeDir,
+    FileMove,
+    FileDelete,
+    ShellExec,
+    /// Validate, HTTP GET, then post-process the body with the module's `process_response`
+    Fetch,
+    RecommendTools,
+}
+
+/// Everything a tool kind may need while executing.
+pub struct ToolContext<'a> {
+    pub executor: &'a mut WasmExecutor,
+    pub config: &'a Config,
+    pub discovery: &'a ToolDiscovery,
+    /// The module's filesystem roots plus any paths approved for this call
+    pub sandbox: &'a FsSandbox,
+    /// Host activity recorded in the audit log
+    pub usage: &'a mut Usage,
+    /// The caller; tools outside its scopes are never recommended
+    pub principal: &'a Principal,
+}
+
+impl ToolKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ToolKind::Wasm => "wasm",
+            ToolKind::HttpGet => "http_get",
+            ToolKind::Http => "http",
+            ToolKind::FileRead => "file_read",
+            ToolKind::FileWrite => "file_write",
+            ToolKind::ListDir => "list_dir",
+            ToolKind::FileStat => "file_stat",
+            ToolKind::Glob => "glob",
+            ToolKind::Grep => "grep",
+            ToolKind::FileAppend => "file_append",
+            ToolKind::MakeDir => "make_dir",
+            ToolKind::FileMove => "file_move",
+            ToolKind::FileDelete => "file_delete",
+            ToolKind::ShellExec => "shell_exec",
+            ToolKind::Fetch => "fetch",
+            ToolKind::RecommendTools => "recommend_tools",
+        }
+    }
+
+    pub fn is_host_backed(&self) -> bool {
+        *self != ToolKind::Wasm
+    }
+
+    /// Named inputs the host reads from the arguments, in the order the module's
+    /// validator receives them.
+    pub fn host_inputs(&self) -> &'static [&'static str] {
+        match self {
+            ToolKind::Wasm => &[],
+            ToolKind::HttpGet | ToolKind::Fetch => &["url"],
+            ToolKind::Http => &["url", "method", "headers", "body"],
+            ToolKind::FileRead | ToolKind::ListDir | ToolKind::FileStat | ToolKind::MakeDir | ToolKind::FileDelete => &["path"],
+            ToolKind::FileWrite | ToolKind::FileAppend => &["path", "content"],
+            ToolKind::Glob | ToolKind::Grep => &["pattern", "path"],
+            ToolKind::FileMove => &["source", "destination"],
+            ToolKind::ShellExec => &["command"],
+            ToolKind::RecommendTools => &["task"],
+        }
+    }
+
+    /// Host inputs a caller must supply; the rest are optional.
+    pub fn required_inputs(&self) -> &'static [&'static str] {
+        match self {
+            ToolKind::Http => &["url"],
+            ToolKind::Glob | ToolKind::Grep => &["pattern"],
+            // Either `command` or the `argv` option
+            ToolKind::ShellExec => &[],
+            _ => self.host_inputs(),
+        }
+    }
+
+    /// Optional arguments the host reads without passing them to the validator.
+    pub fn options(&self) -> &'static [&'static str] {
+        match self {
+            ToolKind::FileRead => &["offset", "length", "start_line", "end_line"],
+            ToolKind::FileWrite => &["mode", "backup", "diff", "expected_sha256"],
+            ToolKind::ShellExec => &["argv", "cwd", "env", "stdin"],
+            _ => &[],
+        }
+    }
+
+    /// Host capability a kind exercises on the module's behalf.
+    pub fn capability(&self) -> Option<&'static str> {
+        match self {
+            ToolKind::HttpGet | ToolKind::Http | ToolKind::Fetch => Some("http"),
+            ToolKind::FileRead | ToolKind::ListDir | ToolKind::FileStat | ToolKind::Glob | ToolKind::Grep => Some("fs.read"),
+            ToolKind::FileWrite | ToolKind::FileAppend | ToolKind::MakeDir | ToolKind::FileMove | ToolKind::FileDelete => Some("fs.write"),
+            ToolKind::ShellExec => Some("shell"),
+            ToolKind::Wasm | ToolKind::RecommendTools => None,
+        }
+    }
+
+    pub fn default_title(&self) -> Option<&'static str> {
+        match self {
+            ToolKind::Wasm => None,
+            ToolKind::HttpGet => Some("HTTP GET"),
+            ToolKind::Http => Some("HTTP Request"),
+            ToolKind::FileRead => Some("Read File"),
+            ToolKind::FileWrite => Some("Write File"),
+            ToolKind::ListDir => Some("List Directory"),
+            ToolKind::FileStat => Some("File Info"),
+            ToolKind::Glob => Some("Find Files"),
+            ToolKind::Grep => Some("Search File Contents"),
+            ToolKind::FileAppend => Some("Append to File"),
+            ToolKind::MakeDir => Some("Create Directory"),
+            ToolKind::FileMove => Some("Move File"),
+            ToolKind::FileDelete => Some("Delete File"),
+            ToolKind::ShellExec => Some("Run Shell Command"),
+            ToolKind::Fetch => Some("Fetch URL"),
+            ToolKind::RecommendTools => Some("Recommend Tools"),
+        }
+    }
+
+    pub fn default_description(&self) -> &'static str {
+        match self {
+            ToolKind::Wasm => "Call a WebAssembly function",
+            ToolKind::HttpGet => "Fetch content from a URL using async HTTP GET with WASM validation",
+            ToolKind::Http => "Send an HTTP request (GET, POST, PUT, PATCH, DELETE or HEAD) with WASM validation and return its status, headers and body",
+            ToolKind::FileRead => "Read file content with WASM path validation",
+            ToolKind::FileWrite => "Write content to file with WASM path validation",
+            ToolKind::ListDir => "List the entries of a directory with WASM path validation",
+            ToolKind::FileStat => "Show the type, size, modification time and permissions of a path with WASM path validation",
+            ToolKind::Glob => "Find files whose relative path matches a glob pattern with WASM validation",
+            ToolKind::Grep => "Search text files for lines matching a regular expression with WASM validation",
+            ToolKind::FileAppend => "Append content to a file, creating it if needed, with WASM path validation",
+            ToolKind::MakeDir => "Create a directory and any missing parents with WASM path validation",
+            ToolKind::FileMove => "Move or rename a file or directory with WASM path validation",
+            ToolKind::FileDelete => "Delete a file or empty directory with WASM path validation",
+            ToolKind::ShellExec => "Execute a simple shell command with WASM validation",
+            ToolKind::Fetch => "Fetch content from a URL using WASM validation and processing",
+            ToolKind::RecommendTools => "Recommend relevant MCP tools based on a task description",
+        }
+    }
+
+    /// Annotation defaults matching what the host does on the tool's behalf.
+    pub fn default_annotations(&self) -> ToolAnnotations {
+        match self {
+            // read_only, destructive, idempotent, open_world
+            ToolKind::HttpGet | ToolKind::Fetch => ToolAnnotations::new(true, false, true, true),
+            ToolKind::Http => ToolAnnotations::new(false, true, false, true),
+            ToolKind::FileRead | ToolKind::ListDir | ToolKind::FileStat | ToolKind::Glob | ToolKind::Grep => ToolAnnotations::new(true, false, true, false),
+            // Appends and backups make repeated writes differ
+            ToolKind::FileWrite => ToolAnnotations::new(false, true, false, false),
+            ToolKind::FileDelete => ToolAnnotations::new(false, true, true, false),
+            ToolKind::FileAppend => ToolAnnotations::new(false, false, false, false),
+            ToolKind::MakeDir => ToolAnnotations::new(false, false, true, false),
+            ToolKind::FileMove => ToolAnnotations::new(false, true, false, false),
+            ToolKind::ShellExec => ToolAnnotations::new(false, true, false, true),
+            ToolKind::Wasm | ToolKind::RecommendTools => ToolAnnotations::new(true, false, true, false),
+        }
+    }
+
+    /// Annotation defaults for a plain WASM tool. Any export of a module granted
+    /// `[modules.http]` can reach the network, and declared capabilities beyond `fs.read`
+    /// mean side effects.
+    pub fn wasm_annotations(http_granted: bool, capabilities: &[String]) -> ToolAnnotations {
+        let open_world = http_granted || capabilities.iter().any(|c| c == "http");
+        let read_only = !open_world && capabilities.iter().all(|c| c == "fs.read");
+        ToolAnnotations::new(read_only, !read_only, read_only, open_world)
+    }
+
+    /// Input schema for a host kind, using the argument names the tool maps its inputs to.
+    pub fn default_schema(&self, argument_name: impl Fn(&str) -> String) -> Value {
+        let mut properties = Map::new();
+        for input in self.host_inputs() {
+            let property = match (self, *input) {
+                (ToolKind::HttpGet, "url") => json!({"type": "string", "minLength": 1, "maxLength": 2048, "description": "The URL to fetch via HTTP GET request"}),
+                (ToolKind::Http, "url") => json!({"type": "string", "minLength": 1, "maxLength": 2048, "description": "The URL to send the request to"}),
+                (ToolKind::Http, "method") => json!({"type": "string", "enum": HTTP_METHODS, "default": "GET", "description": "HTTP method"}),
+                (ToolKind::Http, "headers") => json!({"type": "object", "additionalProperties": {"type": "string"}, "description": "Request headers"}),
+                (ToolKind::Http, "body") => json!({"description": "Request body: a string is sent as-is, any other JSON value is sent as JSON"}),
+                (ToolKind::Fetch, "url") => json!({"type": "string", "minLength": 1, "description": "The URL to fetch"}),
+                (ToolKind::FileRead, "path") => json!({"type": "string", "minLength": 1, "maxLength": 1024, "description": "The file path to read"}),
+                (ToolKind::FileWrite, "path") => json!({"type": "string", "minLength": 1, "maxLength": 1024, "description": "The file path to write to"}),
+                (ToolKind::FileWrite, "content") => json!({"type": "string", "description": "The content to write to the file"}),
+                (ToolKind::ListDir, "path") => json!({"type": "string", "minLength": 1, "maxLength": 1024, "description": "The directory to list"}),
+                (ToolKind::FileStat, "path") => json!({"type": "string", "minLength": 1, "maxLength": 1024, "description": "The file or directory to inspect"}),
+                (ToolKind::Glob, "pattern") => json!({"type": "string", "minLength": 1, "maxLength": 256, "description": "Glob pattern relative to the search directory, e.g. **/*.md"}),
+                (ToolKind::Grep, "pattern") => json!({"type": "string", "minLength": 1, "maxLength": 256, "description": "Regular expression to search for"}),
+                (ToolKind::Glob, "path") => json!({"type": "string", "maxLength": 1024, "description": "Directory to search (defaults to the first readable root)"}),
+                (ToolKind::Grep, "path") => json!({"type": "string", "maxLength": 1024, "description": "File or directory to search (defaults to the first readable root)"}),
+                (ToolKind::FileAppend, "path") => json!({"type": "string", "minLength": 1, "maxLength": 1024, "description": "The file to append to"}),
+                (ToolKind::FileAppend, "content") => json!({"type": "string", "description": "The content to append"}),
+                (ToolKind::MakeDir, "path") => json!({"type": "string", "minLength": 1, "maxLength": 1024, "description": "The directory to create"}),
+                (ToolKind::FileMove, "source") => json!({"type": "string", "minLength": 1, "maxLength": 1024, "description": "The file or directory to move"}),
+                (ToolKind::FileMove, "destination") => json!({"type": "string", "minLength": 1, "maxLength": 1024, "description": "The new path; must not exist yet"}),
+                (ToolKind::FileDelete, "path") => json!({"type": "string", "minLength": 1, "maxLength": 1024, "description": "The file or empty directory to delete"}),
+                (ToolKind::ShellExec, "command") => json!({"type": "string", "minLength": 1, "maxLength": 200, "description": "The command line to execute; quotes group arguments but nothing is expanded (validated by WASM and host)"}),
+                (ToolKind::RecommendTools, "task") => json!({"type": "string", "minLength": 1, "maxLength": 500, "description": "Describe your task and we'll recommend suitable tools"}),
+                _ => json!({"type": "string"}),
+            };
+            properties.insert(argument_name(input), property);
+        }
+        for option in self.options() {
+            let property = match (self, *option) {
+                (ToolKind::FileRead, "offset") => json!({"type": "integer", "minimum": 0, "description": "Byte offset to start reading at"}),
+                (ToolKind::FileRead, "length") => json!({"type": "integer", "minimum": 0, "description": "Number of bytes to read from offset"}),
+                (ToolKind::FileRead, "start_line") => json!({"type": "integer", "minimum": 1, "description": "First line to read (1-based)"}),
+                (ToolKind::FileRead, "end_line") => json!({"type": "integer", "minimum": 1, "description": "Last line to read (inclusive)"}),
+                (ToolKind::FileWrite, "mode") => json!({"type": "string", "enum": ["create_new", "overwrite", "append"], "default": "overwrite", "description": "create_new fails if the file exists; overwrite replaces it atomically; append adds to the end"}),
+                (ToolKind::FileWrite, "backup") => json!({"type": "boolean", "default": false, "description": "Copy the previous contents to <file>.bak first"}),
+                (ToolKind::FileWrite, "diff") => json!({"type": "boolean", "default": false, "description": "Return a line diff against the previous contents"}),
+                (ToolKind::FileWrite, "expected_sha256") => json!({"type": "string", "pattern": "^[0-9a-fA-F]{64}$", "description": "Only write if the current contents have this SHA-256, as reported by file_read"}),
+                (ToolKind::ShellExec, "argv") => json!({"type": "array", "items": {"type": "string"}, "minItems": 1, "maxItems": 64, "description": "Program and arguments, instead of command"}),
+                (ToolKind::ShellExec, "cwd") => json!({"type": "string", "maxLength": 1024, "description": "Working directory inside the module's filesystem roots (defaults to the first readable root)"}),
+                (ToolKind::ShellExec, "env") => json!({"type": "object", "additionalProperties": {"type": "string"}, "description": "Environment variables to set; only allow-listed names are accepted"}),
+                (ToolKind::ShellExec, "stdin") => json!({"type": "string", "description": "Text written to the command's standard input"}),
+                _ => json!({}),
+            };
+            properties.insert(argument_name(option), property);
+        }
+        let required: Vec<String> = self.required_inputs().iter().map(|input| argument_name(input)).collect();
+
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+            "additionalProperties": false
+        })
+    }
+
+    /// Output schema for kinds returning `structuredContent`.
+    pub fn default_output_schema(&self) -> Option<Value> {
+        match self {
+            ToolKind::ShellExec => Some(json!({
+                "type": "object",
+                "properties": {
+                    "exitCode": {"type": "integer"},
+                    "stdout": {"type": "string"},
+                    "stderr": {"type": "string"},
+                    "truncated": {"type": "boolean"}
+                },
+                "required": ["exitCode", "stdout", "stderr"]
+            })),
+            ToolKind::FileWrite => Some(json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string"},
+                    "bytesWritten": {"type": "integer"},
+                    "sha256": {"type": "string"},
+                    "backup": {"type": "string"},
+                    "diff": {"type": "string"}
+                },
+                "required": ["path", "bytesWritten"]
+            })),
+            ToolKind::ListDir => Some(json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string"},
+                    "entries": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "name": {"type": "string"},
+                                "type": {"type": "string", "enum": ["file", "dir", "symlink", "other"]},
+                                "size": {"type": "integer"}
+                            },
+                            "required": ["name", "type", "size"]
+                        }
+                    },
+                    "truncated": {"type": "boolean"}
+                },
+                "required": ["path", "entries", "truncated"]
+            })),
+            ToolKind::FileStat => Some(json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string"},
+                    "type": {"type": "string", "enum": ["file", "dir", "symlink", "other"]},
+                    "size": {"type": "integer"},
+                    "readonly": {"type": "boolean"},
+                    "modified": {"type": "integer", "description": "Seconds since the Unix epoch"},
+                    "mode": {"type": "string", "description": "Unix permission bits in octal"}
+                },
+                "required": ["path", "type", "size", "readonly"]
+            })),
+            ToolKind::Glob => Some(json!({
+                "type": "object",
+                "properties": {
+                    "base": {"type": "string"},
+                    "matches": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {"path": {"type": "string"}, "type": {"type": "string"}},
+                            "required": ["path", "type"]
+                        }
+                    },
+                    "truncated": {"type": "boolean"}
+                },
+                "required": ["base", "matches", "truncated"]
+            })),
+            ToolKind::Grep => Some(json!({
+                "type": "object",
+                "properties": {
+                    "base": {"type": "string"},
+                    "matches": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {"path": {"type": "string"}, "line": {"type": "integer"}, "text": {"type": "string"}},
+                            "required": ["path", "line", "text"]
+                        }
+                    },
+                    "filesSearched": {"type": "integer"},
+                    "truncated": {"type": "boolean"}
+                },
+                "required": ["base", "matches", "filesSearched", "truncated"]
+            })),
+            ToolKind::Http => Some(json!({
+                "type": "object",
+                "properties": {
+                    "status": {"type": "integer"},
+                    "headers": {"type": "object", "additionalProperties": {"type": "string"}},
+                    "body": {"type": "string"},
+                    "truncated": {"type": "boolean"},
+                    "bodyBytes": {"type": "integer"}
+                },
+                "required": ["status", "headers", "bodyBytes"]
+            })),
+            ToolKind::RecommendTools => Some(json!({
+                "type": "object",
+                "properties": {
+                    "categories": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "name": {"type": "string"},
+                                "description": {"type": "string"},
+                                "methods": {"type": "array"}
+                            },
+                            "required": ["name", "description", "methods"]
+                        }
+                    }
+                },
+                "required": ["categories"]
+            })),
+            _ => None,
+        }
+    }
+
+    pub async fn execute(&self, tool: &DiscoveredTool, arguments: &Value, ctx: ToolContext<'_>) -> Result<ToolResult> {
+        match self {
+            ToolKind::Wasm => execute_wasm(tool, arguments, ctx.executor),
+            ToolKind::HttpGet => execute_http_get(tool, arguments, ctx.executor, ctx.config).await,
+            ToolKind::Http => execute_http(tool, arguments, ctx.executor, ctx.config).await,
+            ToolKind::FileRead => execute_file_read(tool, arguments, ctx.executor, ctx.sandbox, ctx.usage).await,
+            ToolKind::FileWrite => execute_file_write(tool, arguments, ctx.executor, ctx.sandbox, ctx.usage).await,
+            ToolKind::ListDir | ToolKind::FileStat | ToolKind::Glob | ToolKind::Grep
+            | ToolKind::FileAppend | ToolKind::MakeDir | ToolKind::FileMove | ToolKind::FileDelete => {
+                execute_fs(*self, tool, arguments, ctx.executor, ctx.sandbox, ctx.usage).await
+            }
+            ToolKind::ShellExec => execute_shell(tool, arguments, ctx.executor, ctx.config, ctx.sandbox, ctx.usage).await,
+            ToolKind::Fetch => execute_fetch(tool, arguments, ctx.executor, ctx.config).await,
+            ToolKind::RecommendTools => execute_recommend(tool, arguments, ctx.executor, ctx.discovery, ctx.principal),
+        }
+    }
+}
+
+fn execute_wasm(tool: &DiscoveredTool, arguments: &Value, executor: &mut WasmExecutor) -> Result<ToolResult> {
+    if tool.pattern == "json" {
+        return execute_json(tool, arguments, executor);
+    }
+
+    let mut args = Vec::with_capacity(tool.params.len());
+    for (name, param) in tool.params.iter().zip(&tool.layout) {
+        args.push(match param {
+            WasmParam::Buffer { base64: false } => WasmArg::Bytes(required_str(arguments, name)?.as_bytes().to_vec()),
+            WasmParam::Buffer { base64: true } => {
+                let encoded = required_str(arguments, name)?;
+                let bytes = base64::engine::general_purpose::STANDARD.decode(encoded)
+                    .map_err(|e| InvalidParams::single(format!("Parameter '{}' is not valid base64: {}", name, e)))?;
+                WasmArg::Bytes(bytes)
+            }
+            WasmParam::Scalar(ty) => WasmArg::Value(required_val(arguments, name, ty)?),
+        });
+    }
+
+    let results = executor.call_function_with_args(&tool.module_name, &tool.function_name, &args)?;
+    let values: Vec<Value> = results.iter().map(val_to_json).collect();
+
+    let label = if tool.pattern == "buffers" { "WASM processing result" } else { "WASM calculation result" };
+    let (structured, text) = match values.as_slice() {
+        [] => (json!({}), "WASM function returned no value".to_string()),
+        [value] => (json!({ "result": value }), format!("{}: {}", label, value)),
+        _ => (json!({ "results": values }), format!("{}s: {}", label, Value::Array(values.clone()))),
+    };
+    Ok(ToolResult::structured(
+        structured,
+        format!("{} (from {}::{})", text, tool.module_name, tool.function_name),
+    ))
+}
+
+/// Largest serialized arguments object handed to a `json` pattern tool.
+const MAX_JSON_ARGUMENT_BYTES: usize = 1024 * 1024;
+
+/// JSON-in/JSON-out call: the arguments object is passed as one (ptr, len) buffer, the
+/// function returns `0` on success and publishes its JSON reply through the result buffer.
+fn execute_json(tool: &DiscoveredTool, arguments: &Value, executor: &mut WasmExecutor) -> Result<ToolResult> {
+    let input = serde_json::to_vec(arguments)?;
+    if input.len() > MAX_JSON_ARGUMENT_BYTES {
+        return Ok(ToolResult::error(format!(
+            "Arguments of {} bytes exceed the {} byte limit for {}",
+            input.len(), MAX_JSON_ARGUMENT_BYTES, tool.function_name
+        )));
+    }
+    let status = executor.call_function_ptr_len_to_i32(&tool.module_name, &tool.function_name, &input)?;
+
+    let reply = match executor.take_guest_output(&tool.module_name)? {
+        Some(output) => {
+            let essence = output.mime_type.split(';').next().unwrap_or("").trim();
+            if essence != "application/json" && !essence.ends_with("+json") {
+                return Err(anyhow::anyhow!("{} returned '{}' instead of application/json", tool.function_name, output.mime_type));
+            }
+            serde_json::from_slice::<Value>(&output.data)
+                .with_context(|| format!("{} returned invalid JSON", tool.function_name))?
+        }
+        None => Value::Null,
+    };
+
+    if status != 0 {
+        let message = reply.get("error").and_then(|e| e.as_str()).map(str::to_string)
+            .unwrap_or_else(|| reply.to_string());
+        return Ok(ToolResult::error(format!("{} failed with status {}: {}", tool.function_name, status, message)));
+    }
+
+    let text = serde_json::to_string_pretty(&reply)?;
+    Ok(if reply.is_object() {
+        ToolResult::structured(reply, text)
+    } else {
+        ToolResult::text(text)
+    })
+}
+
+fn max_response_chars(config: &Config, tool: &DiscoveredTool, default: usize) -> usize {
+    EffectivePolicy::resolve(config, tool).max_response_chars(default)
+}
+
+/// Text preview of a body, cut at a character boundary.
+fn preview(content: &str, max_chars: usize) -> String {
+    match truncate_chars(content, max_chars) {
+        (head, true) => format!("{}...", head),
+        (head, false) => head.to_string(),
+    }
+}
+
+async fn execute_http_get(tool: &DiscoveredTool, arguments: &Value, executor: &mut WasmExecutor, config: &Config) -> Result<ToolResult> {
+    let url = required_str(arguments, tool.argument_name("url"))?;
+    let max_chars = max_response_chars(config, tool, DEFAULT_PREVIEW_CHARS);
+
+    let fetched = executor.http_get_with_validation(&tool.module_name, &tool.function_name, url).await?;
+    let content_length = fetched.data.len();
+
+    Ok(match ToolContent::from_bytes(fetched.data, &fetched.mime_type, url) {
+        ToolContent::Text { text: content } => {
+            ToolResult::text(format!("HTTP GET successful!\nURL: {}\nContent length: {} bytes\n\nContent preview (first {} chars):\n{}",
+                url,
+                content_length,
+                max_chars,
+                preview(&content, max_chars)
+            ))
+        }
+        // Images, audio and other binary bodies are passed through as-is
+        binary => ToolResult::from_content(vec![
+            ToolContent::Text {
+                text: format!("HTTP GET successful!\nURL: {}\nContent-Type: {}\nContent length: {} bytes",
+                    url, fetched.mime_type, content_length),
+            },
+            binary,
+        ]),
+    })
+}
+
+async fn execute_http(tool: &DiscoveredTool, arguments: &Value, executor: &mut WasmExecutor, config: &Config) -> Result<ToolResult> {
+    let url = required_str(arguments, tool.argument_name("url"))?;
+
+    let method = match arguments.get(tool.argument_name("method")) {
+        None | Some(Value::Null) => "GET".to_string(),
+        Some(Value::String(method)) if HTTP_METHODS.contains(&method.to_ascii_uppercase().as_str()) => method.to_ascii_uppercase(),
+        Some(other) => return Err(InvalidParams::single(format!(
+            "Parameter '{}' must be one of {}, got {}", tool.argument_name("method"), HTTP_METHODS.join(", "), other
+        )).into()),
+    };
+
+    let mut headers = BTreeMap::new();
+    match arguments.get(tool.argument_name("headers")) {
+        None | Some(Value::Null) => {}
+        Some(Value::Object(map)) => {
+            for (name, value) in map {
+                let value = value.as_str().ok_or_else(|| InvalidParams::single(format!("Header '{}' must be a string", name)))?;
+                headers.insert(name.clone(), value.to_string());
+            }
+        }
+        Some(_) => return Err(InvalidParams::single(format!("Parameter '{}' must be an object", tool.argument_name("headers"))).into()),
+    }
+
+    let body = match arguments.get(tool.argument_name("body")) {
+        None | Some(Value::Null) => None,
+        Some(Value::String(text)) => Some(text.as_bytes().to_vec()),
+        Some(value) => {
+            if !headers.keys().any(|name| name.eq_ignore_ascii_case("content-type")) {
+                headers.insert("Content-Type".to_string(), "application/json".to_string());
+            }
+            Some(serde_json::to_vec(value)?)
+        }
+    };
+
+    let request = HttpRequestSpec { method, url: url.to_string(), headers, body };
+    let response = executor.http_request_with_validation(&tool.module_name, &tool.function_name, &request).await?;
+
+    let body_bytes = response.content.data.len();
+    let header_map: Map<String, Value> = response.headers.iter()
+        .map(|(name, value)| (name.clone(), json!(value)))
+        .collect();
+    let header_lines: Vec<String> = response.headers.iter()
+        .map(|(name, value)| format!("{}: {}", name, value))
+        .collect();
+    let summary = format!("HTTP {} {}\nStatus: {}\nContent-Type: {}\nContent length: {} bytes\n\nHeaders:\n{}",
+        request.method, url, response.status, response.content.mime_type, body_bytes, header_lines.join("\n"));
+    let mut structured = json!({
+        "status": response.status,
+        "headers": header_map,
+        "bodyBytes": body_bytes
+    });
+
+    let max_chars = max_response_chars(config, tool, DEFAULT_HTTP_BODY_CHARS);
+    Ok(match ToolContent::from_bytes(response.content.data, &response.content.mime_type, url) {
+        ToolContent::Text { text } => {
+            let (body, truncated) = truncate_chars(&text, max_chars);
+            structured["body"] = json!(body);
+            structured["truncated"] = json!(truncated);
+            let label = if truncated { format!("Body (first {} chars)", max_chars) } else { "Body".to_string() };
+            ToolResult::structured(structured, format!("{}\n\n{}:\n{}", summary, label, body))
+        }
+        // Binary bodies are passed through as image, audio or resource content
+        binary => {
+            let mut result = ToolResult::structured(structured, summary);
+            result.push(binary);
+            result
+        }
+    })
+}
+
+/// Filesystem roots the module's file tools are confined to.
+pub fn fs_sandbox(config: &Config, tool: &DiscoveredTool) -> FsSandbox {
+    FsSandbox::new(config.find_module(&tool.module_name).and_then(|module| module.filesystem.as_ref()))
+}
+
+/// Optional non-negative integer argument.
+fn optional_u64(arguments: &Value, key: &str) -> Result<Option<u64>> {
+    match arguments.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => integral_i64(value)
+            .and_then(|n| u64::try_from(n).ok())
+            .or_else(|| value.as_u64())
+            .map(Some)
+            .ok_or_else(|| InvalidParams::single(format!("Parameter '{}' must be a non-negative integer", key)).into()),
+    }
+}
+
+/// Byte or line range requested through the `file_read` options.
+fn read_range(tool: &DiscoveredTool, arguments: &Value) -> Result<ReadRange> {
+    let offset = optional_u64(arguments, tool.argument_name("offset"))?;
+    let length = optional_u64(arguments, tool.argument_name("length"))?;
+    let start_line = optional_u64(arguments, tool.argument_name("start_line"))?;
+    let end_line = optional_u64(arguments, tool.argument_name("end_line"))?;
+
+    match (offset.is_some() || length.is_some(), start_line.is_some() || end_line.is_some()) {
+        (true, true) => Err(InvalidParams::single("Use either offset/length or start_line/end_line, not both").into()),
+        (true, false) => Ok(ReadRange::Bytes { offset: offset.unwrap_or(0), length }),
+        (false, true) => {
+            let start = start_line.unwrap_or(1);
+            if start == 0 || end_line.is_some_and(|end| end < start) {
+                return Err(InvalidParams::single("Line numbers start at 1 and end_line must not precede start_line").into());
+            }
+            Ok(ReadRange::Lines { start, end: end_line })
+        }
+        (false, false) => Ok(ReadRange::Whole),
+    }
+}
+
+async fn execute_file_read(tool: &DiscoveredTool, arguments: &Value, executor: &mut WasmExecutor, sandbox: &FsSandbox, usage: &mut Usage) -> Result<ToolResult> {
+    let file_path = required_str(arguments, tool.argument_name("path"))?;
+    let range = read_range(tool, arguments)?;
+
+    let file = executor.read_file_with_validation(&tool.module_name, &tool.function_name, file_path, range, sandbox).await?;
+    usage.bytes_read += file.data.len() as u64;
+
+    let range_text = match range {
+        // The hash of a whole file can be passed back as `expected_sha256` when writing
+        ReadRange::Whole => format!("\nSHA-256: {}", fs_tools::sha256_hex(&file.data)),
+        ReadRange::Bytes { offset, .. } => format!("\nRange: bytes {}-{} of {}", offset, offset + file.data.len() as u64, file.total_size),
+        ReadRange::Lines { start, end } => format!("\nRange: lines {}-{}", start, end.map_or("end".to_string(), |end| end.to_string())),
+    };
+    let uri = format!("file://{}", file.path.display());
+    let content_length = file.data.len();
+
+    Ok(match ToolContent::from_bytes(file.data, &file.mime_type, &uri) {
+        ToolContent::Text { text: content } => {
+            ToolResult::text(format!("File read successful!\nPath: {}\nContent length: {} bytes{}\n\nContent:\n{}",
+                file_path,
+                content_length,
+                range_text,
+                content
+            ))
+        }
+        // Binary files are returned as image, audio or base64 resource blobs
+        binary => ToolResult::from_content(vec![
+            ToolContent::Text {
+                text: format!("File read successful!\nPath: {}\nContent-Type: {}\nContent length: {} bytes{}",
+                    file_path, file.mime_type, content_length, range_text),
+            },
+            binary,
+        ]),
+    })
+}
+
+fn optional_bool(arguments: &Value, key: &str) -> Result<bool> {
+    match arguments.get(key) {
+        None | Some(Value::Null) => Ok(false),
+        Some(Value::Bool(flag)) => Ok(*flag),
+        Some(_) => Err(InvalidParams::single(format!("Parameter '{}' must be a boolean", key)).into()),
+    }
+}
+
+/// Mode, backup, diff and precondition requested through the `file_write` options.
+fn write_options(tool: &DiscoveredTool, arguments: &Value) -> Result<WriteOptions> {
+    let mode = match arguments.get(tool.argument_name("mode")) {
+        None | Some(Value::Null) => WriteMode::default(),
+        Some(value) => serde_json::from_value(value.clone()).map_err(|_| InvalidParams::single(format!(
+            "Parameter '{}' must be one of create_new, overwrite, append", tool.argument_name("mode")
+        )))?,
+    };
+    let expected_sha256 = match arguments.get(tool.argument_name("expected_sha256")) {
+        None | Some(Value::Null) => None,
+        Some(_) => Some(required_str(arguments, tool.argument_name("expected_sha256"))?.to_string()),
+    };
+
+    Ok(WriteOptions {
+        mode,
+        backup: optional_bool(arguments, tool.argument_name("backup"))?,
+        diff: optional_bool(arguments, tool.argument_name("diff"))?,
+        expected_sha256,
+    })
+}
+
+async fn execute_file_write(tool: &DiscoveredTool, arguments: &Value, executor: &mut WasmExecutor, sandbox: &FsSandbox, usage: &mut Usage) -> Result<ToolResult> {
+    let file_path = required_str(arguments, tool.argument_name("path"))?;
+    let content = required_str(arguments, tool.argument_name("content"))?;
+    let options = write_options(tool, arguments)?;
+
+    let written = executor.write_file_with_validation(&tool.module_name, &tool.function_name, file_path, content, &options, sandbox).await?;
+    usage.bytes_written += content.len() as u64;
+
+    let mut text = format!("File write successful!\nPath: {}\nContent length: {} bytes\nResult: Successfully wrote {} bytes to {}",
+        file_path,
+        content.len(),
+        content.len(),
+        written.path.display()
+    );
+    let mut structured = json!({
+        "path": written.path.display().to_string(),
+        "bytesWritten": content.len()
+    });
+    if let Some(sha256) = &written.sha256 {
+        text.push_str(&format!("\nSHA-256: {}", sha256));
+        structured["sha256"] = json!(sha256);
+    }
+    if let Some(backup) = &written.backup {
+        text.push_str(&format!("\nBackup: {}", backup.display()));
+        structured["backup"] = json!(backup.display().to_string());
+    }
+    if let Some(diff) = &written.diff {
+        text.push_str(&format!("\n\nDiff:\n{}", diff));
+        structured["diff"] = json!(diff);
+    }
+
+    let mut tool_result = ToolResult::structured(structured, text);
+    tool_result.push(ToolContent::ResourceLink {
+        uri: format!("file://{}", written.path.display()),
+        name: file_path.to_string(),
+        mime_type: None,
+        description: Some("File written by this tool".to_string()),
+    });
+    Ok(tool_result)
+}
+
+/// Directory, search and file management kinds: the module validates every host input,
+/// then the host performs the operation inside the module's filesystem roots.
+async fn execute_fs(kind: ToolKind, tool: &DiscoveredTool, arguments: &Value, executor: &mut WasmExecutor, sandbox: &FsSandbox, usage: &mut Usage) -> Result<ToolResult> {
+    // Optional inputs that were not supplied reach the validator as empty buffers
+    let mut inputs = Vec::with_capacity(kind.host_inputs().len());
+    for input in kind.host_inputs() {
+        let name = tool.argument_name(input);
+        inputs.push(if kind.required_inputs().contains(input) {
+            required_str(arguments, name)?
+        } else {
+            match arguments.get(name) {
+                None | Some(Value::Null) => "",
+                Some(_) => required_str(arguments, name)?,
+            }
+        });
+    }
+
+    let buffers: Vec<&[u8]> = inputs.iter().map(|input| input.as_bytes()).collect();
+    if !executor.validate_inputs(&tool.module_name, &tool.function_name, &buffers)? {
+        return Err(anyhow::anyhow!("{} rejected by WASM validation: {}", kind.as_str(), inputs[0]));
+    }
+
+    let structured = match kind {
+        ToolKind::ListDir => fs_tools::list_dir(sandbox, inputs[0]).await?,
+        ToolKind::FileStat => fs_tools::stat(sandbox, inputs[0]).await?,
+        ToolKind::Glob => fs_tools::glob(sandbox, inputs[0], inputs[1]).await?,
+        ToolKind::Grep => fs_tools::grep(sandbox, inputs[0], inputs[1]).await?,
+        ToolKind::FileAppend => {
+            let appended = fs_tools::append(sandbox, inputs[0], inputs[1]).await?;
+            usage.bytes_written += inputs[1].len() as u64;
+            return Ok(ToolResult::text(format!("Appended {} bytes to {}", inputs[1].len(), appended.display())));
+        }
+        ToolKind::MakeDir => {
+            let created = fs_tools::make_dir(sandbox, inputs[0]).await?;
+            return Ok(ToolResult::text(format!("Created directory {}", created.display())));
+        }
+        ToolKind::FileMove => {
+            let (from, to) = fs_tools::move_entry(sandbox, inputs[0], inputs[1]).await?;
+            return Ok(ToolResult::text(format!("Moved {} to {}", from.display(), to.display())));
+        }
+        ToolKind::FileDelete => {
+            let deleted = fs_tools::delete(sandbox, inputs[0]).await?;
+            return Ok(ToolResult::text(format!("Deleted {}", deleted.display())));
+        }
+        _ => unreachable!("{} is not a filesystem kind", kind.as_str()),
+    };
+
+    let text = serde_json::to_string_pretty(&structured)?;
+    Ok(ToolResult::structured(structured, text))
+}
+
+/// Argument vector from either the `command` line or the `argv` option, but not both.
+pub fn shell_argv(tool: &DiscoveredTool, arguments: &Value) -> Result<Vec<String>> {
+    let command_name = tool.argument_name("command");
+    let argv_name = tool.argument_name("argv");
+    let argv = match (arguments.get(command_name), arguments.get(argv_name)) {
+        (Some(Value::String(command)), None) => shell::split_command(command).map_err(|e| InvalidParams::single(e.to_string()))?,
+        (None, Some(Value::Array(items))) => items.iter()
+            .map(|item| item.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| InvalidParams::single(format!("Parameter '{}' must be an array of strings", argv_name)))?,
+        _ => return Err(InvalidParams::single(format!("Provide either '{}' or '{}'", command_name, argv_name)).into()),
+    };
+    if argv.is_empty() {
+        return Err(InvalidParams::single("Empty command").into());
+    }
+    Ok(argv)
+}
+
+/// Environment of a command: allow-listed server variables, overridden by the caller's
+/// except for `shell::PROTECTED_ENV`.
+fn shell_env(tool: &DiscoveredTool, arguments: &Value, allowed_env: &[String]) -> Result<BTreeMap<String, String>> {
+    let mut env: BTreeMap<String, String> = allowed_env.iter()
+        .filter_map(|name| std::env::var(name).ok().map(|value| (name.clone(), value)))
+        .collect();
+
+    match arguments.get(tool.argument_name("env")) {
+        None | Some(Value::Null) => {}
+        Some(Value::Object(requested)) => {
+            for (name, value) in requested {
+                if !allowed_env.contains(name) || shell::PROTECTED_ENV.contains(&name.as_str()) {
+                    return Err(InvalidParams::single(format!("Environment variable '{}' is not allowed", name)).into());
+                }
+                let value = value.as_str().ok_or_else(|| InvalidParams::single(format!("Environment variable '{}' must be a string", name)))?;
+                env.insert(name.clone(), value.to_string());
+            }
+        }
+        Some(_) => return Err(InvalidParams::single(format!("Parameter '{}' must be an object", tool.argument_name("env"))).into()),
+    }
+    Ok(env)
+}
+
+async fn execute_shell(tool: &DiscoveredTool, arguments: &Value, executor: &mut WasmExecutor, config: &Config, sandbox: &FsSandbox, usage: &mut Usage) -> Result<ToolResult> {
+    // Shell execution with dual validation and config-driven allow-list
+    let argv = shell_argv(tool, arguments)?;
+
+    // Allow-list and limits merged from tool, module and global config
+    let Some(settings) = EffectivePolicy::resolve(config, tool).shell else {
+        unreachable!("shell settings are resolved for every shell_exec tool");
+    };
+    let limits = ShellLimits {
+        timeout: std::time::Duration::from_secs(settings.timeout_secs.value),
+        max_output_bytes: settings.max_output_bytes.value,
+    };
+
+    // Commands run inside the module's filesystem roots, never in the server's own directory
+    let cwd = match arguments.get(tool.argument_name("cwd")) {
+        None | Some(Value::Null) => ".",
+        Some(_) => required_str(arguments, tool.argument_name("cwd"))?,
+    };
+    let cwd = sandbox.resolve(cwd, FsAccess::ReadOnly)?;
+    if !cwd.is_dir() {
+        return Err(anyhow::anyhow!("Working directory '{}' is not a directory", cwd.display()));
+    }
+
+    let stdin = match arguments.get(tool.argument_name("stdin")) {
+        None | Some(Value::Null) => None,
+        Some(_) => Some(required_str(arguments, tool.argument_name("stdin"))?.as_bytes().to_vec()),
+    };
+
+    let request = ShellRequest {
+        argv,
+        cwd,
+        env: shell_env(tool, arguments, &settings.allowed_env.value)?,
+        stdin,
+    };
+    let policy = ShellPolicy {
+        allowed_commands: &settings.allowed_commands.value,
+        rule: settings.command_rules.value.get(&request.argv[0]),
+        sandbox,
+        limits,
+        isolation: settings.sandbox.value.as_ref(),
+    };
+    executor.validate_shell_command(&tool.module_name, &tool.function_name, &request, &policy)?;
+    usage.commands.push(request.argv.clone());
+    let output = shell::run(&request, &policy).await?;
+
+    // A non-zero exit is a tool failure the model should see, not a protocol error
+    Ok(ToolResult::structured(
+        json!({
+            "exitCode": output.exit_code,
+            "stdout": output.stdout,
+            "stderr": output.stderr,
+            "truncated": output.truncated()
+        }),
+        output.to_text(),
+    )
+    .with_error(output.exit_code != 0))
+}
+
+async fn execute_fetch(tool: &DiscoveredTool, arguments: &Value, executor: &mut WasmExecutor, config: &Config) -> Result<ToolResult> {
+    let url = required_str(arguments, tool.argument_name("url"))?;
+    let max_chars = max_response_chars(config, tool, DEFAULT_PREVIEW_CHARS);
+
+    let content = executor.fetch_url_with_validation(&tool.module_name, &tool.function_name, url).await?;
+
+    Ok(ToolResult::text(format!("URL: {}\n\nContent (first {} chars):\n{}",
+        url,
+        max_chars,
+        preview(&content, max_chars)
+    )))
+}
+
+fn execute_recommend(tool: &DiscoveredTool, arguments: &Value, executor: &mut WasmExecutor, discovery: &ToolDiscovery, principal: &Principal) -> Result<ToolResult> {
+    let task = required_str(arguments, tool.argument_name("task"))?;
+
+    // Validate via WASM first
+    let ok = executor.validate_inputs(&tool.module_name, &tool.function_name, &[task.as_bytes()])?;
+    if !ok { return Err(anyhow::anyhow!("Task rejected by WASM validation")); }
+
+    // Build recommendations from discovered tools
+    let query = task.to_lowercase();
+    let mut tools = discovery.sorted_tools();
+    tools.retain(|t| principal.allows(t));
+
+    let mut categories: Vec<Value> = Vec::new();
+
+    // Helpers to collect methods by tool kind
+    let has_kind = |kinds: &[ToolKind]| tools.iter().any(|t| kinds.contains(&t.kind));
+    let collect_methods = |kinds: &[ToolKind]| -> Vec<Value> {
+        tools.iter()
+            .filter(|t| kinds.contains(&t.kind))
+            .map(|t| json!({
+                "name": t.name,
+                "inputSchema": t.schema
+            }))
+            .collect()
+    };
+
+    // Scoring keywords
+    let contains_any = |words: &[&str]| words.iter().any(|w| query.contains(w));
+
+    let families: [(&str, &str, &[ToolKind], &[&str]); 3] = [
+        (
+            "web_browser",
+            "Fetch content and call web APIs over HTTP with WASM validation",
+            &[ToolKind::HttpGet, ToolKind::Http],
+            &["download","fetch","http","https","url","get","retrieve","request","api","post","upload"],
+        ),
+        (
+            "file_ops",
+            "Read, write, search and manage files with WASM path validation",
+            &[
+                ToolKind::FileRead, ToolKind::FileWrite, ToolKind::ListDir, ToolKind::FileStat, ToolKind::Glob,
+                ToolKind::Grep, ToolKind::FileAppend, ToolKind::MakeDir, ToolKind::FileMove, ToolKind::FileDelete,
+            ],
+            &["save","file","write","read","open","load","store","folder","directory","list","find","search","grep","append","move","rename","delete"],
+        ),
+        (
+            "shell_executor",
+            "Execute simple whitelisted shell commands with WASM validation",
+            &[ToolKind::ShellExec],
+            &["shell","bash","command","execute","run","ls","echo","cat","wc","uname","terminal"],
+        ),
+    ];
+
+    for (name, description, kinds, keywords) in families {
+        if has_kind(kinds) && contains_any(keywords) {
+            categories.push(json!({
+                "name": name,
+                "description": description,
+                "methods": collect_methods(kinds)
+            }));
+        }
+    }
+
+    // Fallback: if none matched, include the three families if present
+    if categories.is_empty() {
+        for (name, description, kinds, _) in families {
+            if has_kind(kinds) {
+                categories.push(json!({
+                    "name": name,
+                    "description": description,
+                    "methods": collect_methods(kinds)
+                }));
+            }
+        }
+    }
+
+    let structured = json!({ "categories": categories });
+    let json_text = serde_json::to_string_pretty(&structured)?;
+    Ok(ToolResult::structured(structured, json_text))
+}
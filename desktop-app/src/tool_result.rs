@@ -0,0 +1,355 @@
+use base64::Engine;
+use serde_json::{json, Value};
+use std::fmt;
+use std::path::Path;
+use wasmtime::{Val, ValType};
+
+/// A single content block of an MCP `CallToolResult`.
+#[derive(Debug, Clone)]
+pub enum ToolContent {
+    Text { text: String },
+    Image { data: Vec<u8>, mime_type: String },
+    Audio { data: Vec<u8>, mime_type: String },
+    /// Embedded resource; `text` is used for textual bodies, otherwise `blob` is sent base64-encoded.
+    Resource { uri: String, mime_type: Option<String>, text: Option<String>, blob: Option<Vec<u8>> },
+    ResourceLink { uri: String, name: String, mime_type: Option<String>, description: Option<String> },
+}
+
+impl ToolContent {
+    /// Pick the content type for a raw payload based on its MIME type.
+    ///
+    /// Images and audio become `image`/`audio` blocks, UTF-8 text becomes a `text`
+    /// block and anything else is embedded as a `resource` blob under `uri`.
+    pub fn from_bytes(data: Vec<u8>, mime_type: &str, uri: &str) -> Self {
+        let essence = mime_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+        if essence.starts_with("image/") {
+            return ToolContent::Image { data, mime_type: essence };
+        }
+        if essence.starts_with("audio/") {
+            return ToolContent::Audio { data, mime_type: essence };
+        }
+        if is_textual_mime(&essence) {
+            match String::from_utf8(data) {
+                Ok(text) => return ToolContent::Text { text },
+                Err(e) => {
+                    return ToolContent::Resource {
+                        uri: uri.to_string(),
+                        mime_type: Some(essence),
+                        text: None,
+                        blob: Some(e.into_bytes()),
+                    };
+                }
+            }
+        }
+        ToolContent::Resource {
+            uri: uri.to_string(),
+            mime_type: if essence.is_empty() { None } else { Some(essence) },
+            text: None,
+            blob: Some(data),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let b64 = |data: &[u8]| base64::engine::general_purpose::STANDARD.encode(data);
+        match self {
+            ToolContent::Text { text } => json!({
+                "type": "text",
+                "text": text
+            }),
+            ToolContent::Image { data, mime_type } => json!({
+                "type": "image",
+                "data": b64(data),
+                "mimeType": mime_type
+            }),
+            ToolContent::Audio { data, mime_type } => json!({
+                "type": "audio",
+                "data": b64(data),
+                "mimeType": mime_type
+            }),
+            ToolContent::Resource { uri, mime_type, text, blob } => {
+                let mut resource = json!({ "uri": uri });
+                if let Some(mime_type) = mime_type {
+                    resource["mimeType"] = json!(mime_type);
+                }
+                if let Some(text) = text {
+                    resource["text"] = json!(text);
+                } else {
+                    resource["blob"] = json!(b64(blob.as_deref().unwrap_or_default()));
+                }
+                json!({
+                    "type": "resource",
+                    "resource": resource
+                })
+            }
+            ToolContent::ResourceLink { uri, name, mime_type, description } => {
+                let mut link = json!({
+                    "type": "resource_link",
+                    "uri": uri,
+                    "name": name
+                });
+                if let Some(mime_type) = mime_type {
+                    link["mimeType"] = json!(mime_type);
+                }
+                if let Some(description) = description {
+                    link["description"] = json!(description);
+                }
+                link
+            }
+        }
+    }
+}
+
+/// MIME types whose payload is shown to the model as plain text.
+pub fn is_textual_mime(essence: &str) -> bool {
+    essence.is_empty()
+        || essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || matches!(
+            essence,
+            "application/json"
+                | "application/xml"
+                | "application/javascript"
+                | "application/x-javascript"
+                | "application/yaml"
+                | "application/toml"
+                | "application/x-www-form-urlencoded"
+        )
+}
+
+/// Guess a MIME type from magic bytes, then the file extension, then whether the data is UTF-8.
+pub fn detect_mime_type(data: &[u8], path: &Path) -> String {
+    let sniffed = match data {
+        [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, ..] => Some("image/png"),
+        [0xff, 0xd8, 0xff, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some("audio/wav"),
+        [b'I', b'D', b'3', ..] | [0xff, 0xfb, ..] => Some("audio/mpeg"),
+        [b'O', b'g', b'g', b'S', ..] => Some("audio/ogg"),
+        [b'%', b'P', b'D', b'F', b'-', ..] => Some("application/pdf"),
+        [b'P', b'K', 0x03, 0x04, ..] => Some("application/zip"),
+        [0x1f, 0x8b, ..] => Some("application/gzip"),
+        [0x00, b'a', b's', b'm', ..] => Some("application/wasm"),
+        _ => None,
+    };
+    if let Some(mime_type) = sniffed {
+        return mime_type.to_string();
+    }
+
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    let by_extension = match extension.as_str() {
+        "md" | "markdown" => Some("text/markdown"),
+        "html" | "htm" => Some("text/html"),
+        "css" => Some("text/css"),
+        "csv" => Some("text/csv"),
+        "json" => Some("application/json"),
+        "xml" => Some("application/xml"),
+        "yaml" | "yml" => Some("application/yaml"),
+        "toml" => Some("application/toml"),
+        "js" | "mjs" => Some("application/javascript"),
+        "svg" => Some("image/svg+xml"),
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "mp3" => Some("audio/mpeg"),
+        "wav" => Some("audio/wav"),
+        "pdf" => Some("application/pdf"),
+        _ => None,
+    };
+    match by_extension {
+        Some(mime_type) => mime_type.to_string(),
+        None if std::str::from_utf8(data).is_ok() => "text/plain".to_string(),
+        None => "application/octet-stream".to_string(),
+    }
+}
+
+/// Outcome of a tool invocation.
+///
+/// Failures that happen while the tool runs (validation rejections, HTTP errors,
+/// non-zero exit codes) are reported here with `is_error` set so the model can see
+/// them; JSON-RPC errors are reserved for protocol problems.
+#[derive(Debug, Clone)]
+pub struct ToolResult {
+    pub content: Vec<ToolContent>,
+    pub structured_content: Option<Value>,
+    pub is_error: bool,
+}
+
+impl ToolResult {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            content: vec![ToolContent::Text { text: text.into() }],
+            structured_content: None,
+            is_error: false,
+        }
+    }
+
+    /// Structured output plus a text rendering for clients that ignore `structuredContent`.
+    pub fn structured(value: Value, text: impl Into<String>) -> Self {
+        Self {
+            content: vec![ToolContent::Text { text: text.into() }],
+            structured_content: Some(value),
+            is_error: false,
+        }
+    }
+
+    pub fn error(text: impl Into<String>) -> Self {
+        Self {
+            content: vec![ToolContent::Text { text: text.into() }],
+            structured_content: None,
+            is_error: true,
+        }
+    }
+
+    /// Multiple content blocks, e.g. a text summary followed by an image.
+    pub fn from_content(content: Vec<ToolContent>) -> Self {
+        Self {
+            content,
+            structured_content: None,
+            is_error: false,
+        }
+    }
+
+    pub fn push(&mut self, content: ToolContent) {
+        self.content.push(content);
+    }
+
+    pub fn with_error(mut self, is_error: bool) -> Self {
+        self.is_error = is_error;
+        self
+    }
+
+    /// Text of the first text block, e.g. the message of an error result.
+    pub fn first_text(&self) -> Option<&str> {
+        self.content.iter().find_map(|content| match content {
+            ToolContent::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut result = json!({
+            "content": self.content.iter().map(|c| c.to_json()).collect::<Vec<_>>(),
+            "isError": self.is_error
+        });
+        if let Some(structured) = &self.structured_content {
+            result["structuredContent"] = structured.clone();
+        }
+        result
+    }
+}
+
+/// Error raised when tool arguments do not match what the tool expects.
+///
+/// `handle_tool_call` maps this to a JSON-RPC `-32602 Invalid params` error listing
+/// every violation instead of an `isError` result.
+#[derive(Debug)]
+pub struct InvalidParams(pub Vec<String>);
+
+impl InvalidParams {
+    pub fn single(message: impl Into<String>) -> Self {
+        Self(vec![message.into()])
+    }
+}
+
+impl fmt::Display for InvalidParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid params: {}", self.0.join("; "))
+    }
+}
+
+impl std::error::Error for InvalidParams {}
+
+pub fn required_str<'a>(arguments: &'a Value, key: &str) -> anyhow::Result<&'a str> {
+    arguments
+        .get(key)
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| InvalidParams::single(format!("Missing or invalid parameter '{}'", key)).into())
+}
+
+/// Cut `text` to at most `max_chars` characters without splitting a UTF-8 sequence,
+/// reporting whether anything was removed.
+pub fn truncate_chars(text: &str, max_chars: usize) -> (&str, bool) {
+    match text.char_indices().nth(max_chars) {
+        Some((index, _)) => (&text[..index], true),
+        None => (text, false),
+    }
+}
+
+/// Integer value of a JSON number; integral floats such as `1.0` count, as in JSON Schema.
+/// Fractions and floats outside the i64 range are refused.
+pub fn integral_i64(value: &Value) -> Option<i64> {
+    value.as_i64().or_else(|| {
+        let float = value.as_f64()?;
+        (float.fract() == 0.0 && float >= i64::MIN as f64 && float < i64::MAX as f64).then_some(float as i64)
+    })
+}
+
+/// Read an i32 argument, rejecting values that would wrap instead of truncating them.
+pub fn required_i32(arguments: &Value, key: &str) -> anyhow::Result<i32> {
+    let value = arguments
+        .get(key)
+        .and_then(integral_i64)
+        .ok_or_else(|| InvalidParams::single(format!("Missing or invalid parameter '{}': expected an integer", key)))?;
+    i32::try_from(value)
+        .map_err(|_| InvalidParams::single(format!("Parameter '{}' is out of range for i32: {}", key, value)).into())
+}
+
+/// Read a numeric argument as a WASM value of type `ty`, rejecting values the type cannot hold.
+pub fn required_val(arguments: &Value, key: &str, ty: &ValType) -> anyhow::Result<Val> {
+    match ty {
+        ValType::I32 => Ok(Val::I32(required_i32(arguments, key)?)),
+        ValType::I64 => {
+            let value = arguments
+                .get(key)
+                .and_then(integral_i64)
+                .ok_or_else(|| InvalidParams::single(format!("Missing or invalid parameter '{}': expected an integer in the i64 range", key)))?;
+            Ok(Val::I64(value))
+        }
+        ValType::F32 => {
+            let value = required_f64(arguments, key)?;
+            if value.abs() > f32::MAX as f64 {
+                return Err(InvalidParams::single(format!("Parameter '{}' is out of range for f32: {}", key, value)).into());
+            }
+            Ok(Val::F32((value as f32).to_bits()))
+        }
+        ValType::F64 => Ok(Val::F64(required_f64(arguments, key)?.to_bits())),
+        _ => Err(anyhow::anyhow!("Parameter '{}' has a non-numeric WASM type", key)),
+    }
+}
+
+fn required_f64(arguments: &Value, key: &str) -> anyhow::Result<f64> {
+    arguments
+        .get(key)
+        .and_then(|v| v.as_f64())
+        .ok_or_else(|| InvalidParams::single(format!("Missing or invalid parameter '{}': expected a number", key)).into())
+}
+
+/// JSON rendering of a numeric WASM value; non-finite floats become `null`.
+pub fn val_to_json(val: &Val) -> Value {
+    match val {
+        Val::I32(v) => json!(v),
+        Val::I64(v) => json!(v),
+        Val::F32(bits) => json!(f32::from_bits(*bits)),
+        Val::F64(bits) => json!(f64::from_bits(*bits)),
+        _ => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integral_floats_are_read_as_integers() {
+        let arguments = json!({"one": 1.0, "half": 1.5, "big": 3e9, "huge": 1e19, "neg": -2.0});
+        assert_eq!(required_i32(&arguments, "one").unwrap(), 1);
+        assert_eq!(required_i32(&arguments, "neg").unwrap(), -2);
+        assert!(required_i32(&arguments, "half").unwrap_err().to_string().contains("expected an integer"));
+        assert!(required_i32(&arguments, "big").unwrap_err().to_string().contains("out of range for i32"));
+
+        assert!(matches!(required_val(&arguments, "big", &ValType::I64).unwrap(), Val::I64(3_000_000_000)));
+        assert!(required_val(&arguments, "huge", &ValType::I64).is_err());
+        assert!(required_val(&arguments, "half", &ValType::I64).is_err());
+    }
+}
@@ -1,14 +1,42 @@
 use anyhow::{Context, Result};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use wasmtime::*;
 
+use crate::config::HttpConfig;
+use crate::fs_sandbox::FsSandbox;
+use crate::fs_tools::{self, FileContent, ReadRange, WriteOptions, WriteOutcome};
+use crate::host_http;
+use crate::manifest::{self, ModuleManifest};
 use crate::module_manager::{ModuleManager, ModuleMetadata};
+use crate::shell::{self, ShellPolicy, ShellRequest};
+
+/// Export that allocates guest memory for buffer arguments: `wasmforge_alloc(len) -> ptr`.
+pub const ALLOC_EXPORT: &str = "wasmforge_alloc";
+/// Optional export releasing those buffers after the call: `wasmforge_free(ptr, len)`.
+pub const FREE_EXPORT: &str = "wasmforge_free";
+/// Largest total of buffer arguments written into a guest for one call; leaves room
+/// for the path beside a full `file_write`.
+pub const MAX_INPUT_BYTES: usize = 11 * 1024 * 1024;
+/// Largest content `file_write` accepts.
+const MAX_WRITE_BYTES: usize = 10 * 1024 * 1024;
 
 pub struct LoadedModule {
-    pub metadata: ModuleMetadata,
+    /// Cache metadata; `None` for modules loaded straight from bytes
+    #[allow(dead_code)]
+    pub metadata: Option<ModuleMetadata>,
     pub module: Module,
     pub instance: Instance,
-    pub store: Store<()>,
+    pub store: Store<HostState>,
+    pub manifest: Option<ModuleManifest>,
+}
+
+/// Per-instance state visible to host functions.
+#[derive(Default)]
+pub struct HostState {
+    /// Egress policy from `[modules.http]`; host HTTP is only linked when set
+    pub http: Option<HttpConfig>,
+    /// Response of the last `http_request`, waiting for `http_response_read`
+    pub http_response: Vec<u8>,
 }
 
 pub struct WasmExecutor {
@@ -29,7 +57,7 @@ impl WasmExecutor {
         for (name, metadata) in module_manager.get_loaded_modules() {
             match self.load_module_from_metadata(module_manager, metadata).await {
                 Ok(_) => {
-                    println!("✓ WASM executor loaded module: {}", name);
+                    eprintln!("✓ WASM executor loaded module: {}", name);
                 }
                 Err(e) => {
                     eprintln!("✗ Failed to load module '{}' into executor: {}", name, e);
@@ -45,22 +73,61 @@ impl WasmExecutor {
         metadata: &ModuleMetadata,
     ) -> Result<()> {
         let wasm_bytes = module_manager.get_module_bytes(&metadata.name)?;
-        
-        let module = Module::new(&self.engine, &wasm_bytes)
-            .with_context(|| format!("Failed to compile WASM module: {}", metadata.name))?;
+        let http = module_manager.get_module_config(&metadata.name)
+            .and_then(|config| config.http.clone());
+
+        self.load_module_bytes(&metadata.name, &wasm_bytes, http)?;
+        if let Some(loaded) = self.modules.get_mut(&metadata.name) {
+            loaded.metadata = Some(metadata.clone());
+        }
+        Ok(())
+    }
+
+    /// Compile and instantiate a module, linking host imports it has been granted.
+    pub fn load_module_bytes(&mut self, name: &str, wasm_bytes: &[u8], http: Option<HttpConfig>) -> Result<()> {
+        let module = Module::new(&self.engine, wasm_bytes)
+            .with_context(|| format!("Failed to compile WASM module: {}", name))?;
+
+        let manifest = manifest::parse_manifest(wasm_bytes)
+            .with_context(|| format!("Invalid tool manifest in module: {}", name))?;
+        if let Some(manifest) = &manifest {
+            eprintln!("✓ Module '{}' embeds a manifest with {} tool(s)", name, manifest.tools.len());
+        }
 
-        let mut store = Store::new(&self.engine, ());
-        let instance = Instance::new(&mut store, &module, &[])
-            .with_context(|| format!("Failed to instantiate WASM module: {}", metadata.name))?;
+        if let Some(manifest) = &manifest
+            && let Some(import) = module.imports().find(|i| i.module() == host_http::IMPORT_MODULE)
+            && !manifest.tools.iter().any(|tool| tool.capabilities.iter().any(|c| c == "http"))
+        {
+            return Err(anyhow::anyhow!(
+                "Module '{}' imports {}::{} but its manifest declares no 'http' capability",
+                name, import.module(), import.name()
+            ));
+        }
+
+        let mut linker = Linker::new(&self.engine);
+        if http.is_some() {
+            host_http::add_to_linker(&mut linker)?;
+            eprintln!("✓ Module '{}' is granted host HTTP", name);
+        } else if let Some(import) = module.imports().find(|i| i.module() == host_http::IMPORT_MODULE) {
+            return Err(anyhow::anyhow!(
+                "Module '{}' imports {}::{} but has no [modules.http] grant in the config",
+                name, import.module(), import.name()
+            ));
+        }
+
+        let mut store = Store::new(&self.engine, HostState { http, ..Default::default() });
+        let instance = linker.instantiate(&mut store, &module)
+            .with_context(|| format!("Failed to instantiate WASM module: {}", name))?;
 
         let loaded_module = LoadedModule {
-            metadata: metadata.clone(),
+            metadata: None,
             module,
             instance,
             store,
+            manifest,
         };
 
-        self.modules.insert(metadata.name.clone(), loaded_module);
+        self.modules.insert(name.to_string(), loaded_module);
         Ok(())
     }
 
@@ -80,10 +147,19 @@ impl WasmExecutor {
         Ok(functions)
     }
 
+    /// Whether the module was granted host HTTP by `[modules.http]`.
+    pub fn http_granted(&self, module_name: &str) -> bool {
+        self.modules.get(module_name).is_some_and(|module| module.store.data().http.is_some())
+    }
+
+    pub fn get_module_manifest(&self, module_name: &str) -> Option<&ModuleManifest> {
+        self.modules.get(module_name)?.manifest.as_ref()
+    }
+
     pub fn get_all_functions(&self) -> HashMap<String, Vec<String>> {
         let mut all_functions = HashMap::new();
         
-        for (module_name, _) in &self.modules {
+        for module_name in self.modules.keys() {
             if let Ok(functions) = self.get_module_functions(module_name) {
                 all_functions.insert(module_name.clone(), functions);
             }
@@ -92,24 +168,38 @@ impl WasmExecutor {
         all_functions
     }
 
-    pub fn call_function_i32_i32_to_i32(
+    /// Call an export with arbitrary value-typed parameters and collect all of its results.
+    pub fn call_function(
         &mut self,
         module_name: &str,
         function_name: &str,
-        a: i32,
-        b: i32,
-    ) -> Result<i32> {
+        params: &[Val],
+    ) -> Result<Vec<Val>> {
         let module = self.modules.get_mut(module_name)
             .ok_or_else(|| anyhow::anyhow!("Module '{}' not loaded", module_name))?;
 
         let func = module.instance
-            .get_typed_func::<(i32, i32), i32>(&mut module.store, function_name)
-            .with_context(|| format!("Function '{}' not found in module '{}'", function_name, module_name))?;
+            .get_func(&mut module.store, function_name)
+            .ok_or_else(|| anyhow::anyhow!("Function '{}' not found in module '{}'", function_name, module_name))?;
 
-        let result = func.call(&mut module.store, (a, b))
+        let mut results = vec![Val::I32(0); func.ty(&module.store).results().len()];
+        func.call(&mut module.store, params, &mut results)
             .with_context(|| format!("Failed to call function '{}' in module '{}'", function_name, module_name))?;
 
-        Ok(result)
+        Ok(results)
+    }
+
+    /// Call an export with buffer and scalar arguments, lowering each buffer to a (ptr, len) pair.
+    pub fn call_function_with_args(
+        &mut self,
+        module_name: &str,
+        function_name: &str,
+        args: &[WasmArg],
+    ) -> Result<Vec<Val>> {
+        let (params, buffers) = self.lower_args(module_name, args)?;
+        let results = self.call_function(module_name, function_name, &params);
+        self.free_buffers(module_name, &buffers);
+        results
     }
 
     pub fn call_function_ptr_len_to_i32(
@@ -118,45 +208,166 @@ impl WasmExecutor {
         function_name: &str,
         data: &[u8],
     ) -> Result<i32> {
-        let module = self.modules.get_mut(module_name)
-            .ok_or_else(|| anyhow::anyhow!("Module '{}' not loaded", module_name))?;
+        let results = self.call_function_with_args(module_name, function_name, &[WasmArg::Bytes(data.to_vec())])?;
+        match results.as_slice() {
+            [Val::I32(result)] => Ok(*result),
+            _ => Err(anyhow::anyhow!("Function '{}' in module '{}' does not return a single i32", function_name, module_name)),
+        }
+    }
 
-        // Get memory export
-        let memory = module.instance
-            .get_memory(&mut module.store, "memory")
-            .ok_or_else(|| anyhow::anyhow!("No memory export found in module '{}'", module_name))?;
+    /// Run the validator of a host-backed tool and report whether it accepted the inputs.
+    ///
+    /// Validators take one (ptr, len) pair per input they check, in the order the tool
+    /// kind lists them, so a validator may look at just the first input or at all of them.
+    pub fn validate_inputs(&mut self, module_name: &str, validator: &str, inputs: &[&[u8]]) -> Result<bool> {
+        let pairs = self.get_function_signature(module_name, validator)?
+            .buffer_pairs()
+            .filter(|pairs| (1..=inputs.len()).contains(pairs))
+            .ok_or_else(|| anyhow::anyhow!(
+                "Validator '{}' in module '{}' must take 1 to {} (ptr, len) pairs and return i32",
+                validator, module_name, inputs.len()
+            ))?;
+        let args: Vec<WasmArg> = inputs[..pairs].iter().map(|input| WasmArg::Bytes(input.to_vec())).collect();
+
+        let results = self.call_function_with_args(module_name, validator, &args)?;
+        Ok(matches!(results.as_slice(), [Val::I32(1)]))
+    }
 
-        // Allocate space in WASM memory for the data
-        let data_ptr = 1024; // Simple fixed offset - in production, use proper allocation
-        memory.write(&mut module.store, data_ptr, data)
-            .context("Failed to write data to WASM memory")?;
+    /// Copy buffer arguments into guest memory, returning the call's parameters and the
+    /// buffers to release afterwards.
+    ///
+    /// Buffers go wherever the module's `wasmforge_alloc(len) -> ptr` puts them, so they
+    /// never land on live guest data; the total is capped before anything is written.
+    fn lower_args(&mut self, module_name: &str, args: &[WasmArg]) -> Result<(Vec<Val>, Vec<GuestBuffer>)> {
+        let total: usize = args.iter()
+            .map(|arg| match arg {
+                WasmArg::Bytes(data) => data.len(),
+                WasmArg::Value(_) => 0,
+            })
+            .sum();
+        if total > MAX_INPUT_BYTES {
+            return Err(anyhow::anyhow!("Arguments of {} bytes exceed the {} byte limit for WASM input", total, MAX_INPUT_BYTES));
+        }
 
-        let func = module.instance
-            .get_typed_func::<(i32, i32), i32>(&mut module.store, function_name)
-            .with_context(|| format!("Function '{}' not found in module '{}'", function_name, module_name))?;
+        let mut buffers = Vec::new();
+        let params = self.write_buffers(module_name, args, &mut buffers);
+        if params.is_err() {
+            self.free_buffers(module_name, &buffers);
+        }
+        Ok((params?, buffers))
+    }
 
-        let result = func.call(&mut module.store, (data_ptr as i32, data.len() as i32))
-            .with_context(|| format!("Failed to call function '{}' in module '{}'", function_name, module_name))?;
+    fn write_buffers(&mut self, module_name: &str, args: &[WasmArg], buffers: &mut Vec<GuestBuffer>) -> Result<Vec<Val>> {
+        let module = self.modules.get_mut(module_name)
+            .ok_or_else(|| anyhow::anyhow!("Module '{}' not loaded", module_name))?;
 
-        Ok(result)
+        let mut params = Vec::with_capacity(args.len());
+        for arg in args {
+            match arg {
+                WasmArg::Value(value) => params.push(*value),
+                WasmArg::Bytes(data) => {
+                    let alloc = module.instance
+                        .get_typed_func::<i32, i32>(&mut module.store, ALLOC_EXPORT)
+                        .map_err(|_| anyhow::anyhow!(
+                            "Module '{}' must export {}(len: i32) -> i32 to take string or bytes arguments",
+                            module_name, ALLOC_EXPORT
+                        ))?;
+                    let memory = module.instance
+                        .get_memory(&mut module.store, "memory")
+                        .ok_or_else(|| anyhow::anyhow!("No memory export found in module '{}'", module_name))?;
+                    let len = i32::try_from(data.len())
+                        .map_err(|_| anyhow::anyhow!("Argument of {} bytes does not fit in WASM memory", data.len()))?;
+
+                    let ptr = alloc.call(&mut module.store, len)
+                        .with_context(|| format!("Failed to call {}", ALLOC_EXPORT))?;
+                    if ptr == 0 {
+                        return Err(anyhow::anyhow!("{} in module '{}' could not allocate {} bytes", ALLOC_EXPORT, module_name, len));
+                    }
+                    buffers.push((ptr, len));
+                    memory.write(&mut module.store, ptr as u32 as usize, data)
+                        .with_context(|| format!("{} returned a buffer outside of WASM memory", ALLOC_EXPORT))?;
+
+                    params.push(Val::I32(ptr));
+                    params.push(Val::I32(len));
+                }
+            }
+        }
+
+        Ok(params)
     }
 
-    pub fn call_function_no_params_to_i32(
-        &mut self,
-        module_name: &str,
-        function_name: &str,
-    ) -> Result<i32> {
+    /// Hand argument buffers back through `wasmforge_free(ptr, len)` if the module exports it.
+    fn free_buffers(&mut self, module_name: &str, buffers: &[GuestBuffer]) {
+        let Some(module) = self.modules.get_mut(module_name) else {
+            return;
+        };
+        let Ok(free) = module.instance.get_typed_func::<(i32, i32), ()>(&mut module.store, FREE_EXPORT) else {
+            return;
+        };
+        for &(ptr, len) in buffers.iter().rev() {
+            if let Err(e) = free.call(&mut module.store, (ptr, len)) {
+                eprintln!("⚠️  {} failed in module '{}': {}", FREE_EXPORT, module_name, e);
+                return;
+            }
+        }
+    }
+
+    /// Read the payload a guest published through the result-buffer convention.
+    ///
+    /// A module may export `wasmforge_result_ptr() -> i32` and `wasmforge_result_len() -> i32`
+    /// pointing at a buffer holding a MIME type, a NUL byte and then the raw payload.
+    /// If it also exports `wasmforge_result_clear()`, the host calls it after reading so
+    /// the next invocation starts empty.
+    pub fn take_guest_output(&mut self, module_name: &str) -> Result<Option<GuestOutput>> {
         let module = self.modules.get_mut(module_name)
             .ok_or_else(|| anyhow::anyhow!("Module '{}' not loaded", module_name))?;
 
-        let func = module.instance
-            .get_typed_func::<(), i32>(&mut module.store, function_name)
-            .with_context(|| format!("Function '{}' not found in module '{}'", function_name, module_name))?;
+        let (Ok(ptr_func), Ok(len_func)) = (
+            module.instance.get_typed_func::<(), i32>(&mut module.store, "wasmforge_result_ptr"),
+            module.instance.get_typed_func::<(), i32>(&mut module.store, "wasmforge_result_len"),
+        ) else {
+            return Ok(None);
+        };
 
-        let result = func.call(&mut module.store, ())
-            .with_context(|| format!("Failed to call function '{}' in module '{}'", function_name, module_name))?;
+        let len = len_func.call(&mut module.store, ())
+            .context("Failed to call wasmforge_result_len")?;
+        if len <= 0 {
+            return Ok(None);
+        }
+        let ptr = ptr_func.call(&mut module.store, ())
+            .context("Failed to call wasmforge_result_ptr")?;
+
+        let memory = module.instance
+            .get_memory(&mut module.store, "memory")
+            .ok_or_else(|| anyhow::anyhow!("No memory export found in module '{}'", module_name))?;
+        let mut buffer = vec![0u8; len as usize];
+        memory.read(&module.store, ptr as u32 as usize, &mut buffer)
+            .context("Guest result buffer is outside of WASM memory")?;
+
+        if let Ok(clear_func) = module.instance.get_typed_func::<(), ()>(&mut module.store, "wasmforge_result_clear") {
+            clear_func.call(&mut module.store, ())
+                .context("Failed to call wasmforge_result_clear")?;
+        }
 
-        Ok(result)
+        let separator = buffer.iter().position(|b| *b == 0)
+            .ok_or_else(|| anyhow::anyhow!("Guest result buffer is missing the MIME type separator"))?;
+        let mime_type = std::str::from_utf8(&buffer[..separator])
+            .context("Guest result MIME type is not valid UTF-8")?
+            .to_string();
+        let data = buffer[separator + 1..].to_vec();
+
+        Ok(Some(GuestOutput { mime_type, data }))
+    }
+
+    /// Check a URL against the module's `[modules.http]` egress allow-list.
+    fn http_policy(&self, module_name: &str, url: &str) -> Result<(HttpConfig, reqwest::Url)> {
+        let module = self.modules.get(module_name)
+            .ok_or_else(|| anyhow::anyhow!("Module '{}' not loaded", module_name))?;
+        let policy = module.store.data().http.clone()
+            .ok_or_else(|| anyhow::anyhow!("HTTP is not granted to module '{}'; add a [modules.http] allow-list", module_name))?;
+        let url = reqwest::Url::parse(url).with_context(|| format!("Invalid URL: {}", url))?;
+        host_http::check_policy(&policy, &url).map_err(|reason| anyhow::anyhow!("{}: {}", reason, url))?;
+        Ok((policy, url))
     }
 
     pub fn get_function_signature(&self, module_name: &str, function_name: &str) -> Result<FuncSignature> {
@@ -165,13 +376,13 @@ impl WasmExecutor {
 
         // Find the function export
         for export in module.module.exports() {
-            if export.name() == function_name {
-                if let ExternType::Func(func_type) = export.ty() {
-                    return Ok(FuncSignature {
-                        params: func_type.params().collect(),
-                        results: func_type.results().collect(),
-                    });
-                }
+            if export.name() == function_name
+                && let ExternType::Func(func_type) = export.ty()
+            {
+                return Ok(FuncSignature {
+                    params: func_type.params().collect(),
+                    results: func_type.results().collect(),
+                });
             }
         }
 
@@ -181,23 +392,25 @@ impl WasmExecutor {
     pub async fn fetch_url_with_validation(
         &mut self,
         module_name: &str,
+        validator: &str,
         url: &str,
     ) -> Result<String> {
         // First validate URL using WASM
-        let is_valid = self.call_function_ptr_len_to_i32(
-            module_name,
-            "validate_url",
-            url.as_bytes(),
-        )? == 1;
+        let is_valid = self.validate_inputs(module_name, validator, &[url.as_bytes()])?;
 
         if !is_valid {
             return Err(anyhow::anyhow!("Invalid URL according to WASM validation"));
         }
 
         // Make HTTP request
-        let client = reqwest::Client::new();
-        let response = client.get(url).send().await?;
-        let text = response.text().await?;
+        let (policy, url) = self.http_policy(module_name, url)?;
+        let mut response = host_http::client(&policy)?.get(url.clone()).send().await
+            .with_context(|| format!("Failed to fetch URL: {}", url))?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("HTTP request failed with status: {}", response.status()));
+        }
+        let body = host_http::read_body(&mut response, policy.max_response_bytes()).await?;
+        let text = String::from_utf8_lossy(&body).into_owned();
 
         // Process response using WASM
         let status = self.call_function_ptr_len_to_i32(
@@ -217,105 +430,152 @@ impl WasmExecutor {
     pub async fn http_get_with_validation(
         &mut self,
         module_name: &str,
+        validator: &str,
         url: &str,
-    ) -> Result<String> {
+    ) -> Result<HttpContent> {
         // First, let WASM validate/prepare the request
-        let is_valid = self.call_function_ptr_len_to_i32(
-            module_name,
-            "prepare_http_get",
-            url.as_bytes(),
-        )? == 1;
+        let is_valid = self.validate_inputs(module_name, validator, &[url.as_bytes()])?;
 
         if !is_valid {
             return Err(anyhow::anyhow!("URL rejected by WASM validation: {}", url));
         }
 
         // Execute the HTTP request asynchronously on the host
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .user_agent("WasmForge/0.1.0")
-            .build()?;
-
-        let response = client.get(url).send().await
+        let (policy, url) = self.http_policy(module_name, url)?;
+        let mut response = host_http::client(&policy)?.get(url.clone()).send().await
             .with_context(|| format!("Failed to fetch URL: {}", url))?;
 
         if !response.status().is_success() {
             return Err(anyhow::anyhow!("HTTP request failed with status: {}", response.status()));
         }
 
-        let text = response.text().await
-            .context("Failed to read response body as text")?;
+        let mime_type = response.headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
 
-        Ok(text)
+        // Keep the raw bytes so binary bodies (images, audio, archives) are not mangled
+        let data = host_http::read_body(&mut response, policy.max_response_bytes()).await?;
+
+        let mime_type = mime_type.unwrap_or_else(|| {
+            if std::str::from_utf8(&data).is_ok() { "text/plain" } else { "application/octet-stream" }.to_string()
+        });
+
+        Ok(HttpContent { mime_type, data })
     }
 
-    // New async operation: File reading with WASM validation
-    pub async fn read_file_with_validation(
+    /// Send an HTTP request of any method after the module's validator accepts it.
+    ///
+    /// The validator sees the URL, the method, the headers as a JSON object and the body,
+    /// in that order. Every status is returned; only transport failures are errors.
+    pub async fn http_request_with_validation(
         &mut self,
         module_name: &str,
-        file_path: &str,
-    ) -> Result<String> {
-        // First, let WASM validate/prepare the file path
-        let is_safe = self.call_function_ptr_len_to_i32(
+        validator: &str,
+        request: &HttpRequestSpec,
+    ) -> Result<HttpResponse> {
+        let headers_json = serde_json::to_vec(&request.headers)?;
+        let body = request.body.as_deref().unwrap_or_default();
+        let is_valid = self.validate_inputs(
             module_name,
-            "prepare_file_read",
-            file_path.as_bytes(),
-        )? == 1;
+            validator,
+            &[request.url.as_bytes(), request.method.as_bytes(), &headers_json, body],
+        )?;
 
-        if !is_safe {
-            return Err(anyhow::anyhow!("File path rejected by WASM validation: {}", file_path));
+        if !is_valid {
+            return Err(anyhow::anyhow!("HTTP request rejected by WASM validation: {} {}", request.method, request.url));
         }
 
-        // Execute the file read asynchronously on the host
-        let content = tokio::fs::read_to_string(file_path).await
-            .with_context(|| format!("Failed to read file: {}", file_path))?;
+        let (policy, url) = self.http_policy(module_name, &request.url)?;
+        let method = reqwest::Method::from_bytes(request.method.as_bytes())
+            .with_context(|| format!("Invalid HTTP method: {}", request.method))?;
 
-        // Optional: validate content size
-        if content.len() > 1024 * 1024 {  // 1MB limit
-            return Err(anyhow::anyhow!("File too large: {} bytes", content.len()));
+        let mut builder = host_http::client(&policy)?.request(method, url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        if let Some(body) = &request.body {
+            builder = builder.body(body.clone());
         }
 
-        Ok(content)
+        let mut response = builder.send().await
+            .with_context(|| format!("Failed to send {} request to {}", request.method, request.url))?;
+
+        let status = response.status().as_u16();
+        let headers: Vec<(String, String)> = response.headers().iter()
+            .map(|(name, value)| (name.to_string(), String::from_utf8_lossy(value.as_bytes()).to_string()))
+            .collect();
+        let mime_type = response.headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+
+        let data = host_http::read_body(&mut response, policy.max_response_bytes()).await?;
+
+        let mime_type = mime_type.unwrap_or_else(|| {
+            if std::str::from_utf8(&data).is_ok() { "text/plain" } else { "application/octet-stream" }.to_string()
+        });
+
+        Ok(HttpResponse { status, headers, content: HttpContent { mime_type, data } })
     }
 
-    // New async operation: File writing with WASM validation
-    pub async fn write_file_with_validation(
+    // New async operation: File reading with WASM validation
+    pub async fn read_file_with_validation(
         &mut self,
         module_name: &str,
+        validator: &str,
         file_path: &str,
-        content: &str,
-    ) -> Result<String> {
+        range: ReadRange,
+        sandbox: &FsSandbox,
+    ) -> Result<FileContent> {
         // First, let WASM validate/prepare the file path
-        let is_safe = self.call_function_ptr_len_to_i32(
-            module_name,
-            "prepare_file_write",
-            file_path.as_bytes(),
-        )? == 1;
+        let is_safe = self.validate_inputs(module_name, validator, &[file_path.as_bytes()])?;
 
         if !is_safe {
-            return Err(anyhow::anyhow!("File path rejected by WASM validation for writing: {}", file_path));
+            return Err(anyhow::anyhow!("File path rejected by WASM validation: {}", file_path));
         }
 
-        // Additional safety check: content size limit
-        if content.len() > 10 * 1024 * 1024 {  // 10MB limit
+        // The host confines the path to the module's roots and checks the size before reading
+        fs_tools::read(sandbox, file_path, range).await
+    }
+
+    // New async operation: File writing with WASM validation
+    pub async fn write_file_with_validation(
+        &mut self,
+        module_name: &str,
+        validator: &str,
+        file_path: &str,
+        content: &str,
+        options: &WriteOptions,
+        sandbox: &FsSandbox,
+    ) -> Result<WriteOutcome> {
+        // Refuse oversized content before any of it is copied into the guest
+        if content.len() > MAX_WRITE_BYTES {
             return Err(anyhow::anyhow!("Content too large: {} bytes (max 10MB)", content.len()));
         }
 
-        // Execute the file write asynchronously on the host
-        tokio::fs::write(file_path, content).await
-            .with_context(|| format!("Failed to write file: {}", file_path))?;
+        // Let WASM validate the file path, and the content if the validator takes it
+        let is_safe = self.validate_inputs(module_name, validator, &[file_path.as_bytes(), content.as_bytes()])?;
 
-        Ok(format!("Successfully wrote {} bytes to {}", content.len(), file_path))
+        if !is_safe {
+            return Err(anyhow::anyhow!("File write rejected by WASM validation: {}", file_path));
+        }
+
+        // The host confines the path to the module's roots and writes atomically
+        fs_tools::write(sandbox, file_path, content.as_bytes(), options).await
     }
 
+    #[allow(dead_code)]
     pub fn get_loaded_modules(&self) -> Vec<&str> {
         self.modules.keys().map(|s| s.as_str()).collect()
     }
 
+    #[allow(dead_code)]
     pub fn is_module_loaded(&self, module_name: &str) -> bool {
         self.modules.contains_key(module_name)
     }
 
+    #[allow(dead_code)]
     pub fn unload_module(&mut self, module_name: &str) -> Result<()> {
         if self.modules.remove(module_name).is_some() {
             println!("Unloaded module: {}", module_name);
@@ -325,6 +585,7 @@ impl WasmExecutor {
         }
     }
 
+    #[allow(dead_code)]
     pub async fn reload_module(&mut self, module_manager: &ModuleManager, module_name: &str) -> Result<()> {
         // Unload existing module if present
         let _ = self.unload_module(module_name);
@@ -339,68 +600,76 @@ impl WasmExecutor {
         }
     }
 
-    // Execute a shell command validated by WASM and restricted by an allow-list
-    pub async fn execute_shell_with_validation(
+    // Check a command against the WASM validator, the allow-list and its argument rule;
+    // the caller runs it with `shell::run` once this passes
+    pub fn validate_shell_command(
         &mut self,
         module_name: &str,
-        command: &str,
-        allowed_commands: &[String],
-    ) -> Result<String> {
-        // First, let WASM validate the raw command text
-        let is_valid = self.call_function_ptr_len_to_i32(
-            module_name,
-            "prepare_shell_exec",
-            command.as_bytes(),
-        )? == 1;
+        validator: &str,
+        request: &ShellRequest,
+        policy: &ShellPolicy<'_>,
+    ) -> Result<()> {
+        let program = request.argv.first().ok_or_else(|| anyhow::anyhow!("Empty command"))?;
+
+        // The validator always sees the command line in its quoted form, however it was given
+        let command_line = shell::join_command(&request.argv);
+        let is_valid = self.validate_inputs(module_name, validator, &[command_line.as_bytes()])?;
 
         if !is_valid {
             return Err(anyhow::anyhow!("Command rejected by WASM validation"));
         }
 
-        // Server-side enforcement: allow-list and timeout
-        let tokens: Vec<String> = command.split_whitespace().map(|s| s.to_string()).collect();
-        if tokens.is_empty() {
-            return Err(anyhow::anyhow!("Empty command"));
+        // Server-side enforcement: allow-list, then the configured limits
+        if !policy.allowed_commands.iter().any(|c| c == program) {
+            return Err(anyhow::anyhow!("Command '{}' is not allowed", program));
         }
-
-        let program = &tokens[0];
-        let is_allowed = allowed_commands.iter().any(|c| c == program);
-        if !is_allowed {
-            return Err(anyhow::anyhow!(format!("Command '{}' is not allowed", program)));
+        if let Some(rule) = policy.rule {
+            shell::check_arguments(rule, request, policy.sandbox)?;
         }
+        Ok(())
+    }
+}
 
-        let args: Vec<&str> = tokens.iter().skip(1).map(|s| s.as_str()).collect();
-
-        use tokio::process::Command as TokioCommand;
-        use tokio::time::{timeout, Duration};
+/// A `(ptr, len)` buffer the host allocated in guest memory for one call.
+type GuestBuffer = (i32, i32);
 
-        let mut child = TokioCommand::new(program)
-            .args(&args)
-            .kill_on_drop(true)
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped())
-            .spawn()
-            .with_context(|| format!("Failed to spawn command: {}", program))?;
+/// A single logical argument of a WASM call.
+#[derive(Debug, Clone)]
+pub enum WasmArg {
+    /// Written into guest memory and passed as a (ptr, len) pair
+    Bytes(Vec<u8>),
+    Value(Val),
+}
 
-        let output = match timeout(Duration::from_secs(10), child.wait_with_output()).await {
-            Ok(res) => res.with_context(|| "Failed to wait for command output")?,
-            Err(_) => return Err(anyhow::anyhow!("Command timed out after 10s")),
-        };
+/// Payload published by a guest through the `wasmforge_result_*` exports.
+#[derive(Debug, Clone)]
+pub struct GuestOutput {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
 
-        let exit_code = output.status.code().unwrap_or(-1);
-        let mut stdout_text = String::from_utf8_lossy(&output.stdout).to_string();
-        let mut stderr_text = String::from_utf8_lossy(&output.stderr).to_string();
+/// Body of an HTTP response together with its declared content type.
+#[derive(Debug, Clone)]
+pub struct HttpContent {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
 
-        // Truncate large outputs
-        let max_len = 4096;
-        if stdout_text.len() > max_len { stdout_text.truncate(max_len); }
-        if stderr_text.len() > max_len { stderr_text.truncate(max_len); }
+/// Request assembled by the `http` tool kind.
+#[derive(Debug, Clone)]
+pub struct HttpRequestSpec {
+    pub method: String,
+    pub url: String,
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<Vec<u8>>,
+}
 
-        Ok(format!(
-            "Shell execution completed.\nExit code: {}\n\nSTDOUT (truncated):\n{}\n\nSTDERR (truncated):\n{}",
-            exit_code, stdout_text, stderr_text
-        ))
-    }
+/// Status, headers and body of a response to an `http` tool request.
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub content: HttpContent,
 }
 
 #[derive(Debug, Clone)]
@@ -410,36 +679,112 @@ pub struct FuncSignature {
 }
 
 impl FuncSignature {
+    #[allow(dead_code)]
     pub fn param_count(&self) -> usize {
         self.params.len()
     }
 
+    #[allow(dead_code)]
     pub fn result_count(&self) -> usize {
         self.results.len()
     }
 
-    pub fn matches_pattern(&self, pattern: &str) -> bool {
-        match pattern {
-            "i32_i32_to_i32" => {
-                self.params.len() == 2 
-                    && matches!(self.params[0], ValType::I32)
-                    && matches!(self.params[1], ValType::I32)
-                    && self.results.len() == 1
-                    && matches!(self.results[0], ValType::I32)
-            }
-            "ptr_len_to_i32" => {
-                self.params.len() == 2
-                    && matches!(self.params[0], ValType::I32)  // pointer
-                    && matches!(self.params[1], ValType::I32)  // length
-                    && self.results.len() == 1
-                    && matches!(self.results[0], ValType::I32)
-            }
-            "no_params_to_i32" => {
-                self.params.is_empty()
-                    && self.results.len() == 1
-                    && matches!(self.results[0], ValType::I32)
-            }
-            _ => false,
+    /// Whether every parameter and result is an `i32`, `i64`, `f32` or `f64`.
+    pub fn is_numeric(&self) -> bool {
+        self.params.iter().chain(&self.results).all(is_numeric)
+    }
+
+    /// Number of (ptr, len) pairs for a `(i32, i32)* -> i32` buffer function.
+    pub fn buffer_pairs(&self) -> Option<usize> {
+        let all_i32 = self.params.iter().all(|ty| matches!(ty, ValType::I32));
+        let returns_i32 = matches!(self.results.as_slice(), [ValType::I32]);
+        (all_i32 && returns_i32 && self.params.len() % 2 == 0).then_some(self.params.len() / 2)
+    }
+}
+
+fn is_numeric(ty: &ValType) -> bool {
+    matches!(ty, ValType::I32 | ValType::I64 | ValType::F32 | ValType::F64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bump allocator counting allocations and frees, plus a function reading the first
+    /// byte of each of its two buffers.
+    const ALLOC_WAT: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 1024))
+          (global $allocs (mut i32) (i32.const 0))
+          (global $frees (mut i32) (i32.const 0))
+          (func (export "wasmforge_alloc") (param $len i32) (result i32)
+            (global.set $allocs (i32.add (global.get $allocs) (i32.const 1)))
+            (global.get $next)
+            (global.set $next (i32.add (global.get $next) (local.get $len))))
+          (func (export "wasmforge_free") (param i32 i32)
+            (global.set $frees (i32.add (global.get $frees) (i32.const 1))))
+          (func (export "first_bytes") (param $a i32) (param i32) (param $b i32) (param i32) (result i32)
+            (i32.add (i32.load8_u (local.get $a)) (i32.mul (i32.load8_u (local.get $b)) (i32.const 256))))
+          (func (export "allocs") (result i32) (global.get $allocs))
+          (func (export "frees") (result i32) (global.get $frees)))
+    "#;
+
+    fn load(wat: &str) -> WasmExecutor {
+        let mut executor = WasmExecutor::new().unwrap();
+        executor.load_module_bytes("guest", &wat::parse_str(wat).unwrap(), None).unwrap();
+        executor
+    }
+
+    fn counter(executor: &mut WasmExecutor, name: &str) -> i32 {
+        match executor.call_function("guest", name, &[]).unwrap().as_slice() {
+            [Val::I32(value)] => *value,
+            other => panic!("unexpected results {:?}", other),
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn buffers_go_where_the_module_allocates_them_and_are_freed() {
+        let mut executor = load(ALLOC_WAT);
+        let args = [WasmArg::Bytes(b"a".to_vec()), WasmArg::Bytes(b"b".to_vec())];
+        let results = executor.call_function_with_args("guest", "first_bytes", &args).unwrap();
+        assert!(matches!(results.as_slice(), [Val::I32(value)] if *value == i32::from(b'a') + i32::from(b'b') * 256));
+        assert_eq!(counter(&mut executor, "allocs"), 2);
+        assert_eq!(counter(&mut executor, "frees"), 2);
+    }
+
+    #[test]
+    fn oversized_arguments_are_refused_before_allocating() {
+        let mut executor = load(ALLOC_WAT);
+        let args = [WasmArg::Bytes(vec![b'x'; MAX_INPUT_BYTES]), WasmArg::Bytes(b"y".to_vec())];
+        let error = executor.call_function_with_args("guest", "first_bytes", &args).unwrap_err();
+        assert!(error.to_string().contains("byte limit"), "{}", error);
+        assert_eq!(counter(&mut executor, "allocs"), 0);
+    }
+
+    #[test]
+    fn modules_without_an_allocator_take_no_buffers() {
+        let mut executor = load(r#"
+            (module
+              (memory (export "memory") 1)
+              (data (i32.const 1024) "live")
+              (func (export "len") (param i32 i32) (result i32) (local.get 1))
+              (func (export "peek") (result i32) (i32.load8_u (i32.const 1024))))
+        "#);
+        let error = executor.call_function_ptr_len_to_i32("guest", "len", b"overwrite").unwrap_err();
+        assert!(error.to_string().contains(ALLOC_EXPORT), "{}", error);
+        assert_eq!(counter(&mut executor, "peek"), i32::from(b'l'));
+    }
+
+    #[test]
+    fn failed_allocations_are_reported() {
+        let mut executor = load(r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "wasmforge_alloc") (param i32) (result i32) (i32.const 0))
+              (func (export "len") (param i32 i32) (result i32) (local.get 1)))
+        "#);
+        let error = executor.call_function_ptr_len_to_i32("guest", "len", b"data").unwrap_err();
+        assert!(error.to_string().contains("could not allocate"), "{}", error);
+    }
+}
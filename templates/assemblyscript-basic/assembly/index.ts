@@ -1,6 +1,16 @@
-// Minimal AssemblyScript exports without runtime allocations/imports
+// Minimal AssemblyScript exports without imports
 // Reads input directly from memory via load<u8>(ptr + i)
 
+// Argument buffers: the host copies string inputs into memory from wasmforge_alloc
+// and hands them back through wasmforge_free once the call returns
+export function wasmforge_alloc(len: i32): i32 {
+  return <i32>heap.alloc(<usize>max(len, 1));
+}
+
+export function wasmforge_free(ptr: i32, len: i32): void {
+  heap.free(<usize>ptr);
+}
+
 export function add(a: i32, b: i32): i32 {
   return a + b;
 }
@@ -1,3 +1,26 @@
+// Tool manifest read by the host; declares which exports back host-backed tool kinds
+#[unsafe(link_section = "wasmforge")]
+#[used]
+static WASMFORGE_MANIFEST: [u8; include_bytes!("../wasmforge.json").len()] = *include_bytes!("../wasmforge.json");
+
+// Argument buffers: the host copies string inputs into memory from wasmforge_alloc
+// and hands them back through wasmforge_free once the call returns
+#[unsafe(no_mangle)]
+pub extern "C" fn wasmforge_alloc(len: usize) -> *mut u8 {
+    match core::alloc::Layout::array::<u8>(len.max(1)) {
+        // Null on failure, which the host reports instead of writing anywhere
+        Ok(layout) => unsafe { std::alloc::alloc(layout) },
+        Err(_) => core::ptr::null_mut(),
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wasmforge_free(ptr: *mut u8, len: usize) {
+    if let (false, Ok(layout)) = (ptr.is_null(), core::alloc::Layout::array::<u8>(len.max(1))) {
+        unsafe { std::alloc::dealloc(ptr, layout) }
+    }
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn add(a: i32, b: i32) -> i32 {
     a + b
@@ -30,6 +53,16 @@ pub extern "C" fn prepare_http_get(url_ptr: *const u8, url_len: usize) -> i32 {
     } else { 0 }
 }
 
+#[unsafe(no_mangle)]
+pub extern "C" fn prepare_http_request(url_ptr: *const u8, url_len: usize, method_ptr: *const u8, method_len: usize) -> i32 {
+    if url_ptr.is_null() || url_len == 0 || method_ptr.is_null() || method_len == 0 { return 0; }
+    let url_bytes = unsafe { core::slice::from_raw_parts(url_ptr, url_len) };
+    let method_bytes = unsafe { core::slice::from_raw_parts(method_ptr, method_len) };
+    let (Ok(url_str), Ok(method)) = (core::str::from_utf8(url_bytes), core::str::from_utf8(method_bytes)) else { return 0; };
+    let valid_url = (url_str.starts_with("http://") || url_str.starts_with("https://")) && url_str.len() <= 2048;
+    if valid_url && matches!(method, "GET" | "POST" | "PUT" | "PATCH" | "DELETE" | "HEAD") { 1 } else { 0 }
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn prepare_file_read(path_ptr: *const u8, path_len: usize) -> i32 {
     if path_ptr.is_null() || path_len == 0 { return 0; }
@@ -64,7 +97,7 @@ pub extern "C" fn prepare_shell_exec(cmd_ptr: *const u8, cmd_len: usize) -> i32
     let cmd_bytes = unsafe { core::slice::from_raw_parts(cmd_ptr, cmd_len) };
     if let Ok(cmd_str) = core::str::from_utf8(cmd_bytes) {
         if cmd_str.contains('|') || cmd_str.contains('>') || cmd_str.contains('<') || cmd_str.contains(';') || cmd_str.contains('&') || cmd_str.contains('`') || cmd_str.contains('\n') { return 0; }
-        if !cmd_str.chars().all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, ' ' | '.' | '/' | '-' | '_' )) { return 0; }
+        if !cmd_str.chars().all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, ' ' | '.' | '/' | '-' | '_' | '\'' | '"' | '=' | ',' | ':')) { return 0; }
         1
     } else { 0 }
 }
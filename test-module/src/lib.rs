@@ -1,8 +1,23 @@
+// Tool manifest embedded in the `wasmforge` custom section so the host can
+// discover names, schemas and capabilities without extra configuration
+#[unsafe(link_section = "wasmforge")]
+#[used]
+static WASMFORGE_MANIFEST: [u8; include_bytes!("../wasmforge.json").len()] = *include_bytes!("../wasmforge.json");
 #[unsafe(no_mangle)]
 pub extern "C" fn add(a: i32, b: i32) -> i32 {
     a + b
 }
 
+#[unsafe(no_mangle)]
+pub extern "C" fn mul(a: f64, b: f64) -> f64 {
+    a * b
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn clamp(x: i64, lo: i64, hi: i64) -> i64 {
+    x.max(lo).min(hi)
+}
+
 // Simple URL validation function that returns 1 for valid HTTP/HTTPS URLs, 0 otherwise
 #[unsafe(no_mangle)]
 pub extern "C" fn validate_url(url_ptr: *const u8, url_len: usize) -> i32 {
@@ -60,6 +75,25 @@ pub extern "C" fn prepare_http_get(url_ptr: *const u8, url_len: usize) -> i32 {
     }
 }
 
+// Validate an HTTP request before the host sends it: HTTP/HTTPS URL of reasonable length
+// and a known method. Headers and body are left to the host.
+#[unsafe(no_mangle)]
+pub extern "C" fn prepare_http_request(url_ptr: *const u8, url_len: usize, method_ptr: *const u8, method_len: usize) -> i32 {
+    if url_ptr.is_null() || url_len == 0 || method_ptr.is_null() || method_len == 0 {
+        return 0;
+    }
+
+    let url_bytes = unsafe { core::slice::from_raw_parts(url_ptr, url_len) };
+    let method_bytes = unsafe { core::slice::from_raw_parts(method_ptr, method_len) };
+    let (Ok(url_str), Ok(method)) = (core::str::from_utf8(url_bytes), core::str::from_utf8(method_bytes)) else {
+        return 0; // Invalid UTF-8
+    };
+
+    let valid_url = (url_str.starts_with("http://") || url_str.starts_with("https://")) && url_str.len() <= 2048;
+    let valid_method = matches!(method, "GET" | "POST" | "PUT" | "PATCH" | "DELETE" | "HEAD");
+    if valid_url && valid_method { 1 } else { 0 }
+}
+
 // Validate file path for reading - returns 1 if path looks safe, 0 otherwise
 // The host will execute the actual file reading asynchronously
 #[unsafe(no_mangle)]
@@ -88,6 +122,7 @@ pub extern "C" fn prepare_file_read(path_ptr: *const u8, path_len: usize) -> i32
             let ext = &path_str[ext_pos+1..];
             match ext {
                 "txt" | "md" | "json" | "yaml" | "yml" | "toml" | "cfg" | "log" => 1,
+                "png" | "jpg" | "jpeg" | "gif" | "pdf" | "wasm" => 1, // Returned as base64 blobs
                 _ => 0 // Unsupported file type
             }
         } else {
@@ -98,13 +133,19 @@ pub extern "C" fn prepare_file_read(path_ptr: *const u8, path_len: usize) -> i32
     }
 }
 
-// Validate file path for writing - returns 1 if path looks safe, 0 otherwise
+// Validate file path and content for writing - returns 1 if both look safe, 0 otherwise
 // The host will execute the actual file writing asynchronously
 #[unsafe(no_mangle)]
-pub extern "C" fn prepare_file_write(path_ptr: *const u8, path_len: usize) -> i32 {
+pub extern "C" fn prepare_file_write(path_ptr: *const u8, path_len: usize, content_ptr: *const u8, content_len: usize) -> i32 {
     if path_ptr.is_null() || path_len == 0 {
         return 0;
     }
+
+    // Content must be text: valid UTF-8 without NUL bytes
+    let content_bytes = if content_len == 0 { &[][..] } else { unsafe { core::slice::from_raw_parts(content_ptr, content_len) } };
+    if content_bytes.contains(&0) || core::str::from_utf8(content_bytes).is_err() {
+        return 0;
+    }
     
     let path_bytes = unsafe { core::slice::from_raw_parts(path_ptr, path_len) };
     if let Ok(path_str) = core::str::from_utf8(path_bytes) {
@@ -149,6 +190,81 @@ pub extern "C" fn prepare_file_write(path_ptr: *const u8, path_len: usize) -> i3
     }
 }
 
+// Shared path checks for the directory and file management tools
+fn path_is_safe(path_bytes: &[u8]) -> bool {
+    match core::str::from_utf8(path_bytes) {
+        Ok(path_str) => {
+            !path_str.is_empty()
+                && path_str.len() <= 1024
+                && !path_str.contains("..")
+                && !path_str.starts_with("/etc/")
+                && !path_str.starts_with("/sys/")
+                && !path_str.starts_with("/proc/")
+        }
+        Err(_) => false,
+    }
+}
+
+// Validate a path for list_dir, file_stat, make_dir and file_delete - returns 1 if safe
+#[unsafe(no_mangle)]
+pub extern "C" fn prepare_path(path_ptr: *const u8, path_len: usize) -> i32 {
+    if path_ptr.is_null() || path_len == 0 {
+        return 0;
+    }
+    let path_bytes = unsafe { core::slice::from_raw_parts(path_ptr, path_len) };
+    if path_is_safe(path_bytes) { 1 } else { 0 }
+}
+
+// Validate a glob or grep request: a short UTF-8 pattern and an optional (empty) path
+#[unsafe(no_mangle)]
+pub extern "C" fn prepare_search(pattern_ptr: *const u8, pattern_len: usize, path_ptr: *const u8, path_len: usize) -> i32 {
+    if pattern_ptr.is_null() || pattern_len == 0 || pattern_len > 256 {
+        return 0;
+    }
+    let pattern_bytes = unsafe { core::slice::from_raw_parts(pattern_ptr, pattern_len) };
+    if core::str::from_utf8(pattern_bytes).is_err() {
+        return 0;
+    }
+    if path_len == 0 {
+        return 1; // Search the default root
+    }
+    let path_bytes = unsafe { core::slice::from_raw_parts(path_ptr, path_len) };
+    if path_is_safe(path_bytes) { 1 } else { 0 }
+}
+
+// Validate both sides of a move - returns 1 if both paths look safe
+#[unsafe(no_mangle)]
+pub extern "C" fn prepare_move(from_ptr: *const u8, from_len: usize, to_ptr: *const u8, to_len: usize) -> i32 {
+    if from_ptr.is_null() || from_len == 0 || to_ptr.is_null() || to_len == 0 {
+        return 0;
+    }
+    let from_bytes = unsafe { core::slice::from_raw_parts(from_ptr, from_len) };
+    let to_bytes = unsafe { core::slice::from_raw_parts(to_ptr, to_len) };
+    if path_is_safe(from_bytes) && path_is_safe(to_bytes) { 1 } else { 0 }
+}
+
+// Count non-overlapping occurrences of `needle` in `text`, stopping at `limit`
+#[unsafe(no_mangle)]
+pub extern "C" fn count_occurrences(text_ptr: *const u8, text_len: usize, needle_ptr: *const u8, needle_len: usize, limit: i32) -> i32 {
+    if text_ptr.is_null() || needle_ptr.is_null() || needle_len == 0 || limit <= 0 {
+        return 0;
+    }
+
+    let text = unsafe { core::slice::from_raw_parts(text_ptr, text_len) };
+    let needle = unsafe { core::slice::from_raw_parts(needle_ptr, needle_len) };
+    let mut count = 0;
+    let mut i = 0;
+    while i + needle_len <= text.len() && count < limit {
+        if &text[i..i + needle_len] == needle {
+            count += 1;
+            i += needle_len;
+        } else {
+            i += 1;
+        }
+    }
+    count
+}
+
 // Validate shell command text - syntax only; host enforces allow-list
 #[unsafe(no_mangle)]
 pub extern "C" fn prepare_shell_exec(cmd_ptr: *const u8, cmd_len: usize) -> i32 {
@@ -169,8 +285,8 @@ pub extern "C" fn prepare_shell_exec(cmd_ptr: *const u8, cmd_len: usize) -> i32
         {
             return 0;
         }
-        // Basic allowed charset check (letters, numbers, space, dot, slash, dash, underscore)
-        if !cmd_str.chars().all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, ' ' | '.' | '/' | '-' | '_' )) {
+        // Basic allowed charset check (letters, numbers, space, quotes and path punctuation)
+        if !cmd_str.chars().all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, ' ' | '.' | '/' | '-' | '_' | '\'' | '"' | '=' | ',' | ':')) {
             return 0;
         }
         1
@@ -196,3 +312,97 @@ pub extern "C" fn prepare_recommend_mcps(task_ptr: *const u8, task_len: usize) -
         0
     }
 }
+
+// Argument buffers: the host copies string inputs into memory from wasmforge_alloc
+// and hands them back through wasmforge_free once the call returns
+#[unsafe(no_mangle)]
+pub extern "C" fn wasmforge_alloc(len: usize) -> *mut u8 {
+    match core::alloc::Layout::array::<u8>(len.max(1)) {
+        // Null on failure, which the host reports instead of writing anywhere
+        Ok(layout) => unsafe { std::alloc::alloc(layout) },
+        Err(_) => core::ptr::null_mut(),
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wasmforge_free(ptr: *mut u8, len: usize) {
+    if let (false, Ok(layout)) = (ptr.is_null(), core::alloc::Layout::array::<u8>(len.max(1))) {
+        unsafe { std::alloc::dealloc(ptr, layout) }
+    }
+}
+
+// Result buffer convention: the host reads `MIME type, NUL, payload` from
+// wasmforge_result_ptr/len after a call and then invokes wasmforge_result_clear
+thread_local! {
+    static RESULT: core::cell::RefCell<Vec<u8>> = const { core::cell::RefCell::new(Vec::new()) };
+}
+
+fn set_result(mime_type: &str, data: &[u8]) {
+    RESULT.with(|r| {
+        let mut r = r.borrow_mut();
+        r.clear();
+        r.extend_from_slice(mime_type.as_bytes());
+        r.push(0);
+        r.extend_from_slice(data);
+    });
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn wasmforge_result_ptr() -> i32 {
+    RESULT.with(|r| r.borrow().as_ptr() as i32)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn wasmforge_result_len() -> i32 {
+    RESULT.with(|r| r.borrow().len() as i32)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn wasmforge_result_clear() {
+    RESULT.with(|r| r.borrow_mut().clear());
+}
+
+// JSON-in/JSON-out tool: {"text": "..."} -> {"words": n, "lines": n, "chars": n}
+// Returns 0 on success; the reply is published through the result buffer
+#[unsafe(no_mangle)]
+pub extern "C" fn word_stats(args_ptr: *const u8, args_len: usize) -> i32 {
+    if args_ptr.is_null() || args_len == 0 {
+        return 1;
+    }
+
+    let args_bytes = unsafe { core::slice::from_raw_parts(args_ptr, args_len) };
+    let text = match serde_json::from_slice::<serde_json::Value>(args_bytes) {
+        Ok(args) => match args.get("text").and_then(|t| t.as_str()) {
+            Some(text) => text.to_string(),
+            None => {
+                set_result("application/json", br#"{"error":"missing string field 'text'"}"#);
+                return 2;
+            }
+        },
+        Err(_) => return 1,
+    };
+
+    let reply = serde_json::json!({
+        "words": text.split_whitespace().count(),
+        "lines": text.lines().count(),
+        "chars": text.chars().count()
+    });
+    set_result("application/json", reply.to_string().as_bytes());
+    0
+}
+
+// 1x1 RGBA PNG used to demonstrate image results
+const SAMPLE_PNG: [u8; 70] = [
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f, 0x15, 0xc4,
+    0x89, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0xf0, 0xef, 0xba, 0xf6,
+    0x1f, 0x00, 0x05, 0x8a, 0x02, 0xaf, 0xea, 0x1b, 0xdb, 0x49, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45,
+    0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+];
+
+// Return a sample image through the result buffer; the i32 is the pixel count
+#[unsafe(no_mangle)]
+pub extern "C" fn sample_image() -> i32 {
+    set_result("image/png", &SAMPLE_PNG);
+    1
+}